@@ -1,10 +1,12 @@
 use anyhow::Context;
 use lazy_static::lazy_static;
 use std::collections::BTreeMap;
+use std::os::raw::c_void;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 use std::{env, fs, str, usize};
-use tree_sitter::{Language, Parser, Query};
+use tree_sitter::{KindSet, Language, Node, Parser, Point, Query, Tree};
 use tree_sitter_loader::Loader;
 
 include!("../src/tests/helpers/dirs.rs");
@@ -17,6 +19,8 @@ lazy_static! {
     static ref REPETITION_COUNT: usize = env::var("TREE_SITTER_BENCHMARK_REPETITION_COUNT")
         .map(|s| usize::from_str_radix(&s, 10).unwrap())
         .unwrap_or(5);
+    static ref COUNT_ALLOCATIONS: bool =
+        env::var("TREE_SITTER_BENCHMARK_COUNT_ALLOCATIONS").is_ok();
     static ref TEST_LOADER: Loader = Loader::with_parser_lib_path(SCRATCH_DIR.clone());
     static ref EXAMPLE_AND_QUERY_PATHS_BY_LANGUAGE_DIR: BTreeMap<PathBuf, (Vec<PathBuf>, Vec<PathBuf>)> = {
         fn process_dir(result: &mut BTreeMap<PathBuf, (Vec<PathBuf>, Vec<PathBuf>)>, dir: &Path) {
@@ -62,7 +66,44 @@ lazy_static! {
     };
 }
 
+static ALLOCATION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn calloc(count: usize, size: usize) -> *mut c_void;
+    fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+}
+
+// Count the library's allocations, in order to show how much memory is reallocated.
+// This is only enabled when `TREE_SITTER_BENCHMARK_COUNT_ALLOCATIONS` is set, so that
+// it doesn't slow down the other benchmarks.
+unsafe extern "C" fn counting_malloc(size: usize) -> *mut c_void {
+    ALLOCATION_COUNT.fetch_add(1, Ordering::Relaxed);
+    malloc(size)
+}
+
+unsafe extern "C" fn counting_calloc(count: usize, size: usize) -> *mut c_void {
+    ALLOCATION_COUNT.fetch_add(1, Ordering::Relaxed);
+    calloc(count, size)
+}
+
+unsafe extern "C" fn counting_realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    ALLOCATION_COUNT.fetch_add(1, Ordering::Relaxed);
+    realloc(ptr, size)
+}
+
 fn main() {
+    if *COUNT_ALLOCATIONS {
+        unsafe {
+            tree_sitter::set_allocator(
+                Some(counting_malloc),
+                Some(counting_calloc),
+                Some(counting_realloc),
+                None,
+            )
+        };
+    }
+
     let max_path_length = EXAMPLE_AND_QUERY_PATHS_BY_LANGUAGE_DIR
         .values()
         .flat_map(|(e, q)| {
@@ -122,6 +163,91 @@ fn main() {
             }));
         }
 
+        eprintln!("  Accessing Fields (by name vs by id):");
+        let field_names = (1..=language.field_count() as u16)
+            .filter_map(|id| language.field_name_for_id(id))
+            .collect::<Vec<_>>();
+        for example_path in example_paths {
+            if let Some(filter) = EXAMPLE_FILTER.as_ref() {
+                if !example_path.to_str().unwrap().contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            access_fields(
+                &mut parser,
+                language,
+                example_path,
+                max_path_length,
+                &field_names,
+            );
+        }
+
+        eprintln!("  Collecting Children (new vec vs reused buffer):");
+        for example_path in example_paths {
+            if let Some(filter) = EXAMPLE_FILTER.as_ref() {
+                if !example_path.to_str().unwrap().contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            collect_children(&mut parser, example_path, max_path_length);
+        }
+
+        eprintln!("  Filtering Node Kinds (by name vs by kind set):");
+        let kind_names = (0..language.node_kind_count() as u16)
+            .filter(|id| language.node_kind_is_named(*id))
+            .filter_map(|id| language.node_kind_for_id(id))
+            .take(8)
+            .collect::<Vec<_>>();
+        for example_path in example_paths {
+            if let Some(filter) = EXAMPLE_FILTER.as_ref() {
+                if !example_path.to_str().unwrap().contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            filter_kinds(
+                &mut parser,
+                language,
+                example_path,
+                max_path_length,
+                &kind_names,
+            );
+        }
+
+        eprintln!("  Getting Root Nodes (cached vs uncached):");
+        for example_path in example_paths {
+            if let Some(filter) = EXAMPLE_FILTER.as_ref() {
+                if !example_path.to_str().unwrap().contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            get_root_nodes(&mut parser, example_path, max_path_length);
+        }
+
+        eprintln!("  Switching Languages (new parser vs switched parser vs same language):");
+        let other_language = EXAMPLE_AND_QUERY_PATHS_BY_LANGUAGE_DIR
+            .keys()
+            .find(|path| *path != language_path)
+            .map_or(language, |path| get_language(path));
+        for example_path in example_paths {
+            if let Some(filter) = EXAMPLE_FILTER.as_ref() {
+                if !example_path.to_str().unwrap().contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            switch_languages(
+                &mut parser,
+                language,
+                other_language,
+                example_path,
+                max_path_length,
+            );
+        }
+
         eprintln!("  Parsing Invalid Code (mismatched languages):");
         let mut error_speeds = Vec::new();
         for (other_language_path, (example_paths, _)) in
@@ -205,6 +331,280 @@ fn parse(path: &Path, max_path_length: usize, mut action: impl FnMut(&[u8])) ->
     speed as usize
 }
 
+fn access_fields(
+    parser: &mut Parser,
+    language: Language,
+    path: &Path,
+    max_path_length: usize,
+    field_names: &[&str],
+) {
+    eprint!(
+        "    {:width$}\t",
+        path.file_name().unwrap().to_str().unwrap(),
+        width = max_path_length
+    );
+
+    let source_code = fs::read(path)
+        .with_context(|| format!("Failed to read {:?}", path))
+        .unwrap();
+    let tree = parser.parse(&source_code, None).expect("Failed to parse");
+    let field_ids = field_names
+        .iter()
+        .map(|name| language.field_id_for_name(name).unwrap())
+        .collect::<Vec<_>>();
+
+    let nodes = all_nodes(&tree);
+
+    let time_accesses = |action: &dyn Fn(Node) -> usize| {
+        let time = Instant::now();
+        let mut found = 0;
+        for _ in 0..*REPETITION_COUNT {
+            for node in &nodes {
+                found += action(*node);
+            }
+        }
+        (time.elapsed() / (*REPETITION_COUNT as u32), found)
+    };
+
+    let (by_name, found_by_name) = time_accesses(&|node| {
+        field_names
+            .iter()
+            .filter(|name| node.child_by_field_name(name).is_some())
+            .count()
+    });
+    let (by_id, found_by_id) = time_accesses(&|node| {
+        field_ids
+            .iter()
+            .filter(|id| node.child_by_field_id(**id).is_some())
+            .count()
+    });
+    assert_eq!(found_by_name, found_by_id);
+
+    eprintln!(
+        "nodes {}\tby name {} us\tby id {} us",
+        nodes.len(),
+        by_name.as_micros(),
+        by_id.as_micros()
+    );
+}
+
+fn collect_children(parser: &mut Parser, path: &Path, max_path_length: usize) {
+    eprint!(
+        "    {:width$}\t",
+        path.file_name().unwrap().to_str().unwrap(),
+        width = max_path_length
+    );
+
+    let source_code = fs::read(path)
+        .with_context(|| format!("Failed to read {:?}", path))
+        .unwrap();
+    let tree = parser.parse(&source_code, None).expect("Failed to parse");
+    let nodes = all_nodes(&tree);
+    let mut cursor = tree.walk();
+
+    let time = Instant::now();
+    let mut collected_into_vecs = 0;
+    for _ in 0..*REPETITION_COUNT {
+        for node in &nodes {
+            collected_into_vecs += node.children(&mut cursor).collect::<Vec<_>>().len();
+        }
+    }
+    let into_vecs = time.elapsed() / (*REPETITION_COUNT as u32);
+
+    let time = Instant::now();
+    let mut buffer = Vec::new();
+    let mut collected_into_buffer = 0;
+    for _ in 0..*REPETITION_COUNT {
+        for node in &nodes {
+            collected_into_buffer += node.collect_children(&mut buffer).len();
+        }
+    }
+    let into_buffer = time.elapsed() / (*REPETITION_COUNT as u32);
+    assert_eq!(collected_into_vecs, collected_into_buffer);
+
+    eprintln!(
+        "nodes {}\tnew vec {} us\treused buffer {} us",
+        nodes.len(),
+        into_vecs.as_micros(),
+        into_buffer.as_micros()
+    );
+}
+
+fn filter_kinds(
+    parser: &mut Parser,
+    language: Language,
+    path: &Path,
+    max_path_length: usize,
+    kind_names: &[&str],
+) {
+    eprint!(
+        "    {:width$}\t",
+        path.file_name().unwrap().to_str().unwrap(),
+        width = max_path_length
+    );
+
+    let source_code = fs::read(path)
+        .with_context(|| format!("Failed to read {:?}", path))
+        .unwrap();
+    let tree = parser.parse(&source_code, None).expect("Failed to parse");
+    let kinds = KindSet::new(language, kind_names);
+    let nodes = all_nodes(&tree);
+
+    let time = Instant::now();
+    let mut by_name_count = 0;
+    for _ in 0..*REPETITION_COUNT {
+        by_name_count += nodes
+            .iter()
+            .filter(|node| kind_names.contains(&node.kind()))
+            .count();
+    }
+    let by_name = time.elapsed() / (*REPETITION_COUNT as u32);
+
+    let time = Instant::now();
+    let mut by_kind_set_count = 0;
+    for _ in 0..*REPETITION_COUNT {
+        by_kind_set_count += nodes
+            .iter()
+            .filter(|node| kinds.contains(node.kind_id()))
+            .count();
+    }
+    let by_kind_set = time.elapsed() / (*REPETITION_COUNT as u32);
+    assert_eq!(by_name_count, by_kind_set_count);
+
+    // The root node isn't one of its own descendants.
+    let root = tree.root_node();
+    let root_count = kinds.contains(root.kind_id()) as usize;
+    assert_eq!(
+        root.descendants_matching(&kinds).count() + root_count,
+        by_kind_set_count / *REPETITION_COUNT
+    );
+
+    eprintln!(
+        "nodes {}\tby name {} us\tby kind set {} us",
+        nodes.len(),
+        by_name.as_micros(),
+        by_kind_set.as_micros()
+    );
+}
+
+fn get_root_nodes(parser: &mut Parser, path: &Path, max_path_length: usize) {
+    const CALL_COUNT: usize = 1_000_000;
+
+    eprint!(
+        "    {:width$}\t",
+        path.file_name().unwrap().to_str().unwrap(),
+        width = max_path_length
+    );
+
+    let source_code = fs::read(path)
+        .with_context(|| format!("Failed to read {:?}", path))
+        .unwrap();
+    let tree = parser.parse(&source_code, None).expect("Failed to parse");
+
+    // `root_node_with_offset` always calls into the C library, so it shows the
+    // cost of retrieving the root node without the cache.
+    let time = Instant::now();
+    let mut uncached_ids = 0usize;
+    for _ in 0..CALL_COUNT {
+        uncached_ids =
+            uncached_ids.wrapping_add(tree.root_node_with_offset(0, Point::default()).id());
+    }
+    let uncached = time.elapsed();
+
+    let time = Instant::now();
+    let mut cached_ids = 0usize;
+    for _ in 0..CALL_COUNT {
+        cached_ids = cached_ids.wrapping_add(tree.root_node().id());
+    }
+    let cached = time.elapsed();
+    assert_eq!(uncached_ids, cached_ids);
+
+    eprintln!(
+        "calls {}\tuncached {} us\tcached {} us",
+        CALL_COUNT,
+        uncached.as_micros(),
+        cached.as_micros()
+    );
+}
+
+fn switch_languages(
+    parser: &mut Parser,
+    language: Language,
+    other_language: Language,
+    path: &Path,
+    max_path_length: usize,
+) {
+    eprint!(
+        "    {:width$}\t",
+        path.file_name().unwrap().to_str().unwrap(),
+        width = max_path_length
+    );
+
+    let source_code = fs::read(path)
+        .with_context(|| format!("Failed to read {:?}", path))
+        .unwrap();
+
+    let new_parser = measure_repetitions(|| {
+        let mut new_parser = Parser::new();
+        new_parser.set_language(language).unwrap();
+        new_parser
+            .parse(&source_code, None)
+            .expect("Failed to parse");
+    });
+
+    // The reused parser is switched to another language and back before each parse.
+    let switched_parser = measure_repetitions(|| {
+        parser.set_language(other_language).unwrap();
+        parser.set_language(language).unwrap();
+        parser.parse(&source_code, None).expect("Failed to parse");
+    });
+
+    // Setting the language that the parser already has shouldn't do any work.
+    let same_language = measure_repetitions(|| {
+        parser.set_language(language).unwrap();
+        parser.parse(&source_code, None).expect("Failed to parse");
+    });
+
+    eprintln!(
+        "new parser {}\tswitched parser {}\tsame language {}",
+        new_parser, switched_parser, same_language
+    );
+}
+
+// Run the given function repeatedly, and describe the average time and number of
+// allocations that it took.
+fn measure_repetitions(mut f: impl FnMut()) -> String {
+    let time = Instant::now();
+    let allocation_count = ALLOCATION_COUNT.load(Ordering::Relaxed);
+    for _ in 0..*REPETITION_COUNT {
+        f();
+    }
+    let time = time.elapsed() / (*REPETITION_COUNT as u32);
+    if *COUNT_ALLOCATIONS {
+        let allocations =
+            (ALLOCATION_COUNT.load(Ordering::Relaxed) - allocation_count) / *REPETITION_COUNT;
+        format!("{} us, {} allocations", time.as_micros(), allocations)
+    } else {
+        format!("{} us", time.as_micros())
+    }
+}
+
+fn all_nodes(tree: &Tree) -> Vec<Node<'_>> {
+    let mut nodes = Vec::new();
+    let mut cursor = tree.walk();
+    loop {
+        nodes.push(cursor.node());
+        if cursor.goto_first_child() || cursor.goto_next_sibling() {
+            continue;
+        }
+        while cursor.goto_parent() && !cursor.goto_next_sibling() {}
+        if cursor.node() == tree.root_node() {
+            break;
+        }
+    }
+    nodes
+}
+
 fn get_language(path: &Path) -> Language {
     let src_dir = GRAMMARS_DIR.join(path).join("src");
     TEST_LOADER
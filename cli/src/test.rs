@@ -6,12 +6,12 @@ use lazy_static::lazy_static;
 use regex::bytes::{Regex as ByteRegex, RegexBuilder as ByteRegexBuilder};
 use regex::Regex;
 use std::ffi::OsStr;
-use std::fmt::Write as FmtWrite;
+use std::fmt::{self, Write as FmtWrite};
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::str;
-use tree_sitter::{Language, LogType, Parser, Query};
+use tree_sitter::{Language, LogType, Node, Parser, Query};
 use walkdir::WalkDir;
 
 lazy_static! {
@@ -114,9 +114,13 @@ pub fn run_tests_at_path(
             print_diff_key();
             for (i, (name, actual, expected)) in failures.iter().enumerate() {
                 println!("\n  {}. {}:", i + 1, name);
+                let mismatch = sexp_diff(actual, expected);
                 let actual = format_sexp_indented(&actual, 2);
                 let expected = format_sexp_indented(&expected, 2);
                 print_diff(&actual, &expected);
+                if let Some(mismatch) = mismatch {
+                    println!("  {}", mismatch.replace('\n', "\n  "));
+                }
             }
             Err(anyhow!(""))
         }
@@ -376,6 +380,175 @@ pub fn strip_sexp_fields(sexp: String) -> String {
     SEXP_FIELD_REGEX.replace_all(&sexp, " (").to_string()
 }
 
+/// Assert that a node's S-expression matches the expected one, ignoring differences
+/// in whitespace, and panic with a description of the first mismatching node if not.
+pub fn assert_sexp_eq(node: Node, expected: &str) {
+    if let Some(mismatch) = sexp_diff(&node.to_sexp(), expected) {
+        panic!("S-expressions differ\n{}", mismatch);
+    }
+}
+
+/// Compare two S-expressions structurally, and describe the first node at which they
+/// differ, along with the path of node names leading to it. Returns `None` if the
+/// S-expressions are equivalent.
+pub fn sexp_diff(actual: &str, expected: &str) -> Option<String> {
+    let (actual_node, expected_node) = match (SexpNode::parse(actual), SexpNode::parse(expected)) {
+        (Some(a), Some(e)) => (a, e),
+        _ => {
+            let actual = WHITESPACE_REGEX.replace_all(actual.trim(), " ");
+            let expected = WHITESPACE_REGEX.replace_all(expected.trim(), " ");
+            if actual == expected {
+                return None;
+            }
+            return Some(format!("expected: {}\nactual:   {}", expected, actual));
+        }
+    };
+    let mut path = Vec::new();
+    let (actual_node, expected_node) =
+        SexpNode::first_mismatch(&actual_node, &expected_node, &mut path)?;
+    let location = if path.is_empty() {
+        "at the root".to_string()
+    } else {
+        format!("under {}", path.join(" > "))
+    };
+    let describe = |node: Option<&SexpNode>| match node {
+        Some(node) => node.to_string(),
+        None => "nothing".to_string(),
+    };
+    Some(format!(
+        "first mismatch {}:\n  expected: {}\n  actual:   {}",
+        location,
+        describe(expected_node),
+        describe(actual_node)
+    ))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SexpNode {
+    field: Option<String>,
+    name: String,
+    children: Vec<SexpNode>,
+}
+
+impl SexpNode {
+    fn parse(sexp: &str) -> Option<Self> {
+        let mut tokens = Vec::new();
+        let mut token_start = None;
+        for (i, c) in sexp.char_indices() {
+            if c == '(' || c == ')' || c.is_whitespace() {
+                if let Some(start) = token_start.take() {
+                    tokens.push(&sexp[start..i]);
+                }
+                if !c.is_whitespace() {
+                    tokens.push(&sexp[i..i + 1]);
+                }
+            } else if token_start.is_none() {
+                token_start = Some(i);
+            }
+        }
+        if let Some(start) = token_start {
+            tokens.push(&sexp[start..]);
+        }
+
+        let mut tokens = tokens.into_iter().peekable();
+        let result = Self::parse_node(&mut tokens, None)?;
+        if tokens.next().is_some() {
+            return None;
+        }
+        Some(result)
+    }
+
+    fn parse_node<'a>(
+        tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+        field: Option<String>,
+    ) -> Option<Self> {
+        if tokens.next()? != "(" {
+            return None;
+        }
+
+        // Node names like `MISSING identifier` or `UNEXPECTED 'x'` span several tokens.
+        let mut name = String::new();
+        while let Some(token) = tokens.peek() {
+            if *token == "(" || *token == ")" || token.ends_with(':') {
+                break;
+            }
+            if !name.is_empty() {
+                name.push(' ');
+            }
+            name += tokens.next().unwrap();
+        }
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut children = Vec::new();
+        loop {
+            let token = *tokens.peek()?;
+            match token {
+                ")" => {
+                    tokens.next();
+                    break;
+                }
+                "(" => children.push(Self::parse_node(tokens, None)?),
+                _ => {
+                    let field = token.trim_end_matches(':').to_string();
+                    tokens.next();
+                    children.push(Self::parse_node(tokens, Some(field))?);
+                }
+            }
+        }
+        Some(SexpNode {
+            field,
+            name,
+            children,
+        })
+    }
+
+    fn first_mismatch<'a>(
+        actual: &'a SexpNode,
+        expected: &'a SexpNode,
+        path: &mut Vec<String>,
+    ) -> Option<(Option<&'a SexpNode>, Option<&'a SexpNode>)> {
+        if actual.field != expected.field || actual.name != expected.name {
+            return Some((Some(actual), Some(expected)));
+        }
+        path.push(actual.label());
+        let child_count = actual.children.len().max(expected.children.len());
+        for i in 0..child_count {
+            match (actual.children.get(i), expected.children.get(i)) {
+                (Some(a), Some(e)) => {
+                    if let Some(mismatch) = Self::first_mismatch(a, e, path) {
+                        return Some(mismatch);
+                    }
+                }
+                (a, e) => return Some((a, e)),
+            }
+        }
+        path.pop();
+        None
+    }
+
+    fn label(&self) -> String {
+        match &self.field {
+            Some(field) => format!("{}: {}", field, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl fmt::Display for SexpNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(field) = &self.field {
+            write!(f, "{}: ", field)?;
+        }
+        write!(f, "({}", self.name)?;
+        for child in &self.children {
+            write!(f, " {}", child)?;
+        }
+        write!(f, ")")
+    }
+}
+
 fn parse_test_content(name: String, content: String, file_path: Option<PathBuf>) -> TestEntry {
     let mut children = Vec::new();
     let bytes = content.as_bytes();
@@ -839,4 +1012,53 @@ code with ----
             }
         );
     }
+
+    #[test]
+    fn test_sexp_diff() {
+        assert_eq!(
+            sexp_diff(
+                "(program (call function: (identifier) arguments: (args)))",
+                "(program\n  (call\n    function: (identifier)\n    arguments: (args)))"
+            ),
+            None
+        );
+
+        assert_eq!(
+            sexp_diff(
+                "(program (call function: (member (identifier) (number)) arguments: (args)))",
+                "(program (call function: (member (identifier) (identifier)) arguments: (args)))"
+            )
+            .unwrap(),
+            [
+                "first mismatch under program > call > function: member:",
+                "  expected: (identifier)",
+                "  actual:   (number)",
+            ]
+            .join("\n")
+        );
+
+        assert_eq!(
+            sexp_diff(
+                "(program (statement) (MISSING \";\"))",
+                "(program (statement))"
+            )
+            .unwrap(),
+            [
+                "first mismatch under program:",
+                "  expected: nothing",
+                "  actual:   (MISSING \";\")",
+            ]
+            .join("\n")
+        );
+
+        assert_eq!(
+            sexp_diff("(a left: (b))", "(a right: (b))").unwrap(),
+            [
+                "first mismatch under a:",
+                "  expected: right: (b)",
+                "  actual:   left: (b)",
+            ]
+            .join("\n")
+        );
+    }
 }
@@ -10,10 +10,13 @@ use super::helpers::{
 use crate::{
     generate,
     parse::perform_edit,
-    test::{parse_tests, print_diff, print_diff_key, strip_sexp_fields, TestEntry},
+    test::{assert_sexp_eq, parse_tests, print_diff, print_diff_key, strip_sexp_fields, TestEntry},
     util,
 };
-use std::fs;
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+};
 use tree_sitter::{LogType, Node, Parser, Tree};
 
 #[test]
@@ -327,6 +330,34 @@ fn test_feature_corpus_files() {
     }
 }
 
+#[test]
+fn test_assert_sexp_eq() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("json")).unwrap();
+    let tree = parser.parse("[1, true]", None).unwrap();
+
+    // Differences in whitespace are ignored.
+    assert_sexp_eq(
+        tree.root_node(),
+        "(document\n  (array\n    (number)\n    (true)))",
+    );
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        assert_sexp_eq(tree.root_node(), "(document (array (number) (false)))")
+    }));
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert_eq!(
+        *message,
+        [
+            "S-expressions differ",
+            "first mismatch under document > array:",
+            "  expected: (false)",
+            "  actual:   (true)",
+        ]
+        .join("\n")
+    );
+}
+
 fn check_consistent_sizes(tree: &Tree, input: &Vec<u8>) {
     fn check(node: Node, line_offsets: &Vec<usize>) {
         let start_byte = node.start_byte();
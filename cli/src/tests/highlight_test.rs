@@ -4,8 +4,15 @@ use std::ffi::CString;
 use std::os::raw::c_char;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{fs, ptr, slice, str};
+use tree_sitter::{Parser, Point, Query, QueryCursor, QueryErrorKind};
 use tree_sitter_highlight::{
-    c, Error, Highlight, HighlightConfiguration, HighlightEvent, Highlighter, HtmlRenderer,
+    c,
+    indent::Indenter,
+    injection_ranges,
+    locals::LocalsResolver,
+    textmate::{scope_for_capture, TextMateTheme},
+    AnsiColor, AnsiRenderer, Error, Highlight, HighlightConfiguration, HighlightEvent, Highlighter,
+    HtmlRenderer, NamedHighlightEvent, QueryFragment,
 };
 
 lazy_static! {
@@ -480,6 +487,536 @@ fn test_highlighting_cancellation() {
     panic!("Expected an error while iterating highlighter");
 }
 
+#[test]
+fn test_highlighting_with_max_injection_depth() {
+    // A configuration that injects JavaScript into its own template strings,
+    // which would recurse indefinitely without a depth limit.
+    let language = get_language("javascript");
+    let queries_path = get_language_queries_path("javascript");
+    let highlights_query = fs::read_to_string(queries_path.join("highlights.scm")).unwrap();
+    let mut config = HighlightConfiguration::new(
+        language,
+        &highlights_query,
+        "((template_string) @injection.content (#set! injection.language \"javascript\"))",
+        "",
+    )
+    .unwrap();
+    config.configure(&HIGHLIGHT_NAMES);
+
+    let source = "const a = `b`;";
+    let count_injections = |max_injection_depth: Option<usize>| {
+        let mut highlighter = Highlighter::new();
+        if let Some(depth) = max_injection_depth {
+            highlighter.set_max_injection_depth(depth);
+        }
+        let mut injection_count = 0;
+        let events = highlighter
+            .highlight(&config, source.as_bytes(), None, |_| {
+                injection_count += 1;
+                Some(&config)
+            })
+            .unwrap();
+        for event in events {
+            event.unwrap();
+        }
+        injection_count
+    };
+
+    assert_eq!(Highlighter::new().max_injection_depth(), 10);
+    assert_eq!(count_injections(None), 10);
+    assert_eq!(count_injections(Some(3)), 3);
+    assert_eq!(count_injections(Some(0)), 0);
+}
+
+#[test]
+fn test_highlighting_with_named_events() {
+    let language = get_language("javascript");
+    let mut config = HighlightConfiguration::new(
+        language,
+        r#"
+        (call_expression function: (identifier) @function)
+        (identifier) @variable
+        "const" @keyword
+        "#,
+        "",
+        "",
+    )
+    .unwrap();
+    config.configure(&["function", "keyword", "variable"]);
+
+    let source = "const a = b();";
+    let mut highlighter = Highlighter::new();
+    let mut spans = Vec::new();
+    let mut names = Vec::new();
+    for event in highlighter
+        .highlight_named(&config, source.as_bytes(), None, |_| None)
+        .unwrap()
+    {
+        match event.unwrap() {
+            NamedHighlightEvent::HighlightStart(name) => names.push(name),
+            NamedHighlightEvent::HighlightEnd => {
+                names.pop();
+            }
+            NamedHighlightEvent::Source { start, end } => {
+                if let Some(name) = names.last() {
+                    spans.push((&source[start..end], *name));
+                }
+            }
+        }
+    }
+    assert_eq!(
+        spans,
+        &[("const", "keyword"), ("a", "variable"), ("b", "function")]
+    );
+}
+
+#[test]
+fn test_highlighting_with_hierarchical_name_fallback() {
+    let language = get_language("javascript");
+    let mut config =
+        HighlightConfiguration::new(language, "(identifier) @function.method.builtin", "", "")
+            .unwrap();
+
+    let highlights = |config: &HighlightConfiguration| {
+        Highlighter::new()
+            .highlight(config, b"a", None, |_| None)
+            .unwrap()
+            .filter_map(|event| match event.unwrap() {
+                HighlightEvent::HighlightStart(highlight) => Some(highlight),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // A theme that only defines the least specific name.
+    config.configure(&["function"]);
+    assert_eq!(highlights(&config), &[Highlight(0)]);
+
+    // The most specific recognized name is preferred.
+    config.configure(&["function", "function.method", "keyword"]);
+    assert_eq!(highlights(&config), &[Highlight(1)]);
+    config.configure(&["function.method.builtin", "function.method"]);
+    assert_eq!(highlights(&config), &[Highlight(0)]);
+
+    // Names that don't match the capture are never used.
+    config.configure(&["function.call", "keyword"]);
+    assert_eq!(highlights(&config), &[]);
+}
+
+#[test]
+fn test_highlighting_with_local_definition_roles() {
+    let language = get_language("javascript");
+    let mut config = HighlightConfiguration::new(
+        language,
+        "(identifier) @variable",
+        "",
+        r#"
+        (function_declaration) @local.scope
+        (formal_parameters (identifier) @local.definition.variable.parameter)
+        (variable_declarator name: (identifier) @local.definition)
+        (identifier) @local.reference
+        "#,
+    )
+    .unwrap();
+    config.configure(&["variable", "variable.parameter"]);
+
+    let source = "function f(a) { let b = a; return b + c; }";
+    let mut highlighter = Highlighter::new();
+    let mut spans = Vec::new();
+    let mut names = Vec::new();
+    for event in highlighter
+        .highlight_named(&config, source.as_bytes(), None, |_| None)
+        .unwrap()
+    {
+        match event.unwrap() {
+            NamedHighlightEvent::HighlightStart(name) => names.push(name),
+            NamedHighlightEvent::HighlightEnd => {
+                names.pop();
+            }
+            NamedHighlightEvent::Source { start, end } => {
+                if let Some(name) = names.last() {
+                    spans.push((&source[start..end], *name));
+                }
+            }
+        }
+    }
+
+    // The parameter and the reference to it are highlighted according to the
+    // parameter's role, while other variables keep their ordinary highlight.
+    assert_eq!(
+        spans,
+        &[
+            ("f", "variable"),
+            ("a", "variable.parameter"),
+            ("b", "variable"),
+            ("a", "variable.parameter"),
+            ("b", "variable"),
+            ("c", "variable"),
+        ]
+    );
+
+    // Like other highlight names, roles fall back to less specific recognized names.
+    config.configure(&["variable"]);
+    let highlights = highlighter
+        .highlight(&config, source.as_bytes(), None, |_| None)
+        .unwrap()
+        .filter_map(|event| match event.unwrap() {
+            HighlightEvent::HighlightStart(highlight) => Some(highlight),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(highlights, &[Highlight(0); 6]);
+}
+
+#[test]
+fn test_highlighting_pre_parsed_tree() {
+    let source = "const s = html `<div>${a < b}</div>`;\nfunction c(d) { return d + 1; }";
+
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+
+    let mut highlighter = Highlighter::new();
+    let expected = highlighter
+        .highlight(
+            &JS_HIGHLIGHT,
+            source.as_bytes(),
+            None,
+            &test_language_for_injection_string,
+        )
+        .unwrap()
+        .map(|event| format!("{:?}", event.unwrap()))
+        .collect::<Vec<_>>();
+    let actual = highlighter
+        .highlight_tree(
+            &JS_HIGHLIGHT,
+            &tree,
+            source.as_bytes(),
+            None,
+            &test_language_for_injection_string,
+        )
+        .unwrap()
+        .map(|event| format!("{:?}", event.unwrap()))
+        .collect::<Vec<_>>();
+    assert!(!expected.is_empty());
+    assert_eq!(actual, expected);
+
+    // The tree must belong to the configuration's language.
+    assert!(matches!(
+        highlighter.highlight_tree(&HTML_HIGHLIGHT, &tree, source.as_bytes(), None, |_| None),
+        Err(Error::InvalidLanguage)
+    ));
+}
+
+#[test]
+fn test_resolving_textmate_theme_colors_for_captures() {
+    assert_eq!(scope_for_capture("function"), "entity.name.function");
+    assert_eq!(
+        scope_for_capture("function.method"),
+        "entity.name.function.method"
+    );
+    assert_eq!(scope_for_capture("function.builtin"), "support.function");
+    assert_eq!(scope_for_capture("functional"), "functional");
+    assert_eq!(scope_for_capture("unknown.name"), "unknown.name");
+
+    let theme = TextMateTheme::new(vec![
+        ("comment", "gray"),
+        ("entity.name", "blue"),
+        ("entity.name.function", "yellow"),
+        ("source.rust entity.name.function", "orange"),
+        ("constant.numeric, constant.language", "purple"),
+        ("support", "green"),
+        ("support.function", "red"),
+        ("support.function", "pink"),
+    ]);
+
+    // Selectors that match more parts of the scope's name are more specific.
+    assert_eq!(
+        theme.resolve_capture("source.js", "function"),
+        Some(&"yellow")
+    );
+    assert_eq!(theme.resolve_capture("source.js", "type"), Some(&"blue"));
+    assert_eq!(
+        theme.resolve_capture("source.js", "type.builtin"),
+        Some(&"green")
+    );
+
+    // Matching the root scope as well makes a selector more specific.
+    assert_eq!(
+        theme.resolve_capture("source.rust", "function"),
+        Some(&"orange")
+    );
+    assert_eq!(
+        theme.resolve_capture("source.rust", "function.method"),
+        Some(&"orange")
+    );
+
+    // Any alternative of a selector can match, and later rules break ties.
+    assert_eq!(
+        theme.resolve_capture("source.js", "number"),
+        Some(&"purple")
+    );
+    assert_eq!(
+        theme.resolve_capture("source.js", "constant.builtin"),
+        Some(&"purple")
+    );
+    assert_eq!(
+        theme.resolve_capture("source.js", "function.builtin"),
+        Some(&"pink")
+    );
+
+    assert_eq!(theme.resolve_capture("source.js", "comment"), Some(&"gray"));
+    assert_eq!(theme.resolve_capture("source.js", "operator"), None);
+}
+
+#[test]
+fn test_highlight_configuration_from_query_fragments() {
+    let language = get_language("javascript");
+    let base_query = "(identifier) @variable\n; a trailing comment";
+    let override_query = "(property_identifier) @property";
+
+    let config = HighlightConfiguration::from_fragments(
+        language,
+        &[base_query, override_query],
+        &[],
+        &["(statement_block) @local.scope"],
+    )
+    .unwrap();
+    assert_eq!(config.names(), &["local.scope", "variable", "property"]);
+
+    // Errors are reported relative to the query string that contains them.
+    let invalid_override_query = "(identifier) @variable\n(not_a_node) @error\n";
+    let error = HighlightConfiguration::from_fragments(
+        language,
+        &[base_query, invalid_override_query],
+        &["(template_string) @injection.content"],
+        &["(statement_block) @local.scope"],
+    )
+    .err()
+    .unwrap();
+    assert_eq!(error.fragment, Some(QueryFragment::Highlights(1)));
+    assert_eq!(error.error.kind, QueryErrorKind::NodeType);
+    assert_eq!(error.error.offset, 24);
+    assert_eq!(error.error.row, 1);
+    assert_eq!(error.error.column, 1);
+
+    let error = HighlightConfiguration::from_fragments(
+        language,
+        &[base_query],
+        &[],
+        &[
+            "(statement_block) @local.scope",
+            "(identifier) @local.reference (#eq?)",
+        ],
+    )
+    .err()
+    .unwrap();
+    assert_eq!(error.fragment, Some(QueryFragment::Locals(1)));
+    assert_eq!(error.error.kind, QueryErrorKind::Predicate);
+    assert_eq!(error.error.row, 0);
+}
+
+#[test]
+fn test_resolving_local_definitions_with_shadowing() {
+    let language = get_language("javascript");
+    let resolver = LocalsResolver::new(
+        language,
+        r#"
+        (statement_block) @local.scope
+        (function_declaration) @local.scope
+        (variable_declarator name: (identifier) @local.definition)
+        (formal_parameters (identifier) @local.definition)
+        (identifier) @local.reference
+        "#,
+    )
+    .unwrap();
+
+    let source = "
+let a = 1;
+function f(a) {
+  return a;
+}
+{
+  let a = 2;
+  a;
+}
+a;
+b;
+";
+
+    let mut parser = Parser::new();
+    parser.set_language(language).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let root = tree.root_node();
+    let mut cursor = QueryCursor::new();
+
+    let mut definition_row = |row, column| {
+        let point = Point::new(row, column);
+        let reference = root.descendant_for_point_range(point, point).unwrap();
+        assert_eq!(reference.kind(), "identifier");
+        resolver
+            .find_definition(&mut cursor, root, reference, source.as_bytes())
+            .map(|definition| definition.start_position().row)
+    };
+
+    // The parameter shadows the outer variable within the function.
+    assert_eq!(definition_row(3, 9), Some(2));
+
+    // The block-scoped variable shadows the outer variable within the block.
+    assert_eq!(definition_row(7, 2), Some(6));
+
+    // Outside of those scopes, the outer variable is visible again.
+    assert_eq!(definition_row(9, 0), Some(1));
+
+    // Definitions resolve to themselves.
+    assert_eq!(definition_row(6, 6), Some(6));
+
+    // Unknown names have no definition.
+    assert_eq!(definition_row(10, 0), None);
+}
+
+#[test]
+fn test_rendering_ansi_escape_codes() {
+    let source = b"let x = f(\"s\");";
+    let events = vec![
+        HighlightEvent::HighlightStart(Highlight(0)),
+        HighlightEvent::Source { start: 0, end: 3 },
+        HighlightEvent::HighlightEnd,
+        HighlightEvent::Source { start: 3, end: 8 },
+        HighlightEvent::HighlightStart(Highlight(1)),
+        HighlightEvent::Source { start: 8, end: 10 },
+        HighlightEvent::HighlightStart(Highlight(2)),
+        HighlightEvent::Source { start: 10, end: 13 },
+        HighlightEvent::HighlightEnd,
+        HighlightEvent::Source { start: 13, end: 14 },
+        HighlightEvent::HighlightEnd,
+        HighlightEvent::HighlightStart(Highlight(3)),
+        HighlightEvent::Source { start: 14, end: 15 },
+        HighlightEvent::HighlightEnd,
+    ];
+    let palette = [
+        Some(AnsiColor::Fixed(5)),
+        Some(AnsiColor::Rgb(255, 128, 0)),
+        Some(AnsiColor::Fixed(2)),
+        None,
+    ];
+
+    let mut renderer = AnsiRenderer::new();
+    renderer
+        .render(events.into_iter().map(Ok), source, &|h| palette[h.0])
+        .unwrap();
+    assert_eq!(
+        str::from_utf8(&renderer.output).unwrap(),
+        concat!(
+            "\x1b[38;5;5mlet\x1b[0m",
+            " x = ",
+            "\x1b[38;2;255;128;0mf(",
+            "\x1b[38;5;2m\"s\"\x1b[0m\x1b[38;2;255;128;0m",
+            ")\x1b[0m",
+            ";\x1b[0m",
+        )
+    );
+}
+
+#[test]
+fn test_computing_indent_levels() {
+    let language = get_language("javascript");
+    let indenter = Indenter::new(
+        language,
+        r#"
+        [(statement_block) (object)] @indent
+        "}" @branch
+        (return_statement) @dedent
+        "#,
+    )
+    .unwrap();
+
+    let source = "
+function f(a) {
+  if (a) {
+    return {
+      b: 1
+    };
+  }
+}
+"
+    .trim();
+
+    let mut parser = Parser::new();
+    parser.set_language(language).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let root = tree.root_node();
+    let mut cursor = QueryCursor::new();
+
+    // Each line's level counts the enclosing blocks, and closing braces return to
+    // the level of the line on which their block started.
+    let levels = (0..7)
+        .map(|row| indenter.indent_level(&mut cursor, root, source.as_bytes(), row))
+        .collect::<Vec<_>>();
+    assert_eq!(levels, &[0, 1, 2, 3, 2, 1, 0]);
+
+    // A new line is indented inside of any block that continues past the previous
+    // line, and is dedented after a return statement.
+    let next_levels = (0..7)
+        .map(|row| indenter.next_line_indent_level(&mut cursor, root, source.as_bytes(), row))
+        .collect::<Vec<_>>();
+    assert_eq!(next_levels, &[1, 2, 3, 3, 1, 1, 0]);
+}
+
+#[test]
+fn test_computing_injection_ranges() {
+    let language = get_language("html");
+    let query = Query::new(
+        language,
+        r#"
+        ((script_element (raw_text) @injection.content)
+         (#set! injection.language "javascript"))
+        ((style_element (raw_text) @injection.content)
+         (#set! injection.language "css"))
+        "#,
+    )
+    .unwrap();
+
+    let source = "<div>
+<script>a();</script>
+<style>b {}</style>
+<script>c();</script>
+</div>";
+
+    let mut parser = Parser::new();
+    parser.set_language(language).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+
+    let injections = injection_ranges(&tree, source.as_bytes(), &query);
+    assert_eq!(
+        injections
+            .iter()
+            .map(|(language_name, ranges)| (
+                language_name.as_str(),
+                ranges
+                    .iter()
+                    .map(|range| &source[range.start_byte..range.end_byte])
+                    .collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        &[("javascript", vec!["a();", "c();"]), ("css", vec!["b {}"])]
+    );
+    assert_eq!(injections[0].1[1].start_point, Point::new(3, 8));
+
+    // The ranges can be used to parse the injected language.
+    parser.set_language(get_language("javascript")).unwrap();
+    parser.set_included_ranges(&injections[0].1).unwrap();
+    let js_tree = parser.parse(source, None).unwrap();
+    assert_eq!(
+        js_tree.root_node().to_sexp(),
+        concat!(
+            "(program ",
+            "(expression_statement (call_expression function: (identifier) arguments: (arguments))) ",
+            "(expression_statement (call_expression function: (identifier) arguments: (arguments))))",
+        )
+    );
+}
+
 #[test]
 fn test_highlighting_via_c_api() {
     let highlights = vec![
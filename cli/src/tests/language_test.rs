@@ -0,0 +1,173 @@
+use super::helpers::fixtures::get_language;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tree_sitter::{Language, LanguageFn, Parser, LANGUAGE_VERSION};
+
+#[test]
+fn test_language_parse_state_count() {
+    let language = get_language("json");
+    assert!(language.parse_state_count() > 0);
+    assert!(language.lookahead_iterator(0).is_some());
+    assert!(language
+        .lookahead_iterator(language.parse_state_count() as u16)
+        .is_none());
+}
+
+#[test]
+fn test_language_lookahead_iterator() {
+    let language = get_language("json");
+
+    let symbols = language.lookahead_iterator(0).unwrap().collect::<Vec<_>>();
+    assert!(!symbols.is_empty());
+
+    // Every symbol produced is a real symbol in the language, and none repeat.
+    let mut sorted_symbols = symbols.clone();
+    sorted_symbols.sort_unstable();
+    sorted_symbols.dedup();
+    assert_eq!(sorted_symbols.len(), symbols.len());
+    for symbol in &symbols {
+        assert!(language.node_kind_for_id(*symbol).is_some());
+    }
+
+    // The lookahead iterator stays exhausted once it has finished.
+    let mut iterator = language.lookahead_iterator(0).unwrap();
+    while iterator.next().is_some() {}
+    assert_eq!(iterator.next(), None);
+}
+
+#[test]
+fn test_language_next_state() {
+    let language = get_language("json");
+    let left_brace = language.id_for_node_kind("{", false);
+    let object = language.id_for_node_kind("object", true);
+
+    // State 1 is the parser's initial state, where a document can begin with an object.
+    let state = language.next_state(1, left_brace);
+    assert_ne!(state, 0);
+    assert!((state as usize) < language.parse_state_count());
+    assert_ne!(language.next_state(1, object), 0);
+
+    // A closing brace can follow the opening brace.
+    let right_brace = language.id_for_node_kind("}", false);
+    assert!(language
+        .lookahead_iterator(state)
+        .unwrap()
+        .any(|symbol| symbol == right_brace));
+    assert_ne!(language.next_state(state, right_brace), 0);
+
+    // State 0 is the error-recovery state, which never shifts tokens.
+    assert_eq!(language.next_state(0, left_brace), 0);
+
+    // States and symbols that don't belong to the language have no next state.
+    assert_eq!(language.next_state(u16::MAX, left_brace), 0);
+    assert_eq!(language.next_state(1, u16::MAX - 2), 0);
+}
+
+#[test]
+fn test_language_equality() {
+    let json = get_language("json");
+    let javascript = get_language("javascript");
+
+    // A language retrieved from a parser is the same as the one that was assigned.
+    let mut parser = Parser::new();
+    parser.set_language(json).unwrap();
+    assert_eq!(parser.language(), Some(json));
+    assert_eq!(get_language("json"), json);
+    assert_ne!(json, javascript);
+
+    let languages = [json, javascript, get_language("json")]
+        .iter()
+        .cloned()
+        .collect::<HashSet<_>>();
+    assert_eq!(languages.len(), 2);
+    assert!(languages.contains(&parser.language().unwrap()));
+}
+
+static JSON_LANGUAGE: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn tree_sitter_json() -> *const () {
+    JSON_LANGUAGE.load(Ordering::SeqCst) as *const ()
+}
+
+#[test]
+fn test_language_fn() {
+    let language = get_language("json");
+    JSON_LANGUAGE.store(language.into_raw() as usize, Ordering::SeqCst);
+
+    let language_fn = unsafe { LanguageFn::from_raw(tree_sitter_json) };
+    assert_eq!(language_fn.language(), language);
+    assert_eq!(Language::from(language_fn), language);
+    assert_eq!(unsafe { Language::from_raw(language.into_raw()) }, language);
+
+    let mut parser = Parser::new();
+    parser.set_language(language_fn.language()).unwrap();
+    let tree = parser.parse("[1, null]", None).unwrap();
+    assert_eq!(
+        tree.root_node().to_sexp(),
+        "(document (array (number) (null)))"
+    );
+}
+
+#[test]
+fn test_language_keyword_tokens() {
+    let language = get_language("javascript");
+    let keywords = language.keyword_tokens();
+    for keyword in &["function", "return", "if", "else", "const", "class", "new"] {
+        assert!(keywords.contains(keyword), "missing keyword {}", keyword);
+    }
+
+    // Punctuation, operators, and named node kinds are not keywords.
+    for kind in &["(", "+=", "=>", "identifier", "program"] {
+        assert!(!keywords.contains(kind), "unexpected keyword {}", kind);
+    }
+
+    let unique_keywords = keywords.iter().collect::<HashSet<_>>();
+    assert_eq!(unique_keywords.len(), keywords.len());
+}
+
+#[test]
+fn test_language_validate() {
+    for name in &["json", "javascript", "rust", "html"] {
+        assert_eq!(get_language(name).validate(), Ok(()));
+    }
+
+    // A mocked language, whose leading fields are the ABI version, symbol count,
+    // alias count and token count, and whose tables are all null.
+    let mut mock = [0u64; 64];
+    let set_header = |mock: &mut [u64; 64], header: [u32; 4]| unsafe {
+        let fields = mock.as_mut_ptr() as *mut u32;
+        for (i, value) in header.iter().enumerate() {
+            *fields.add(i) = *value;
+        }
+    };
+    let validate = |mock: &[u64; 64]| {
+        let language = unsafe { Language::from_raw(mock.as_ptr() as *const ()) };
+        language.validate().map_err(|error| error.to_string())
+    };
+
+    set_header(&mut mock, [LANGUAGE_VERSION as u32, 0, 0, 0]);
+    assert_eq!(
+        validate(&mock),
+        Err("Inconsistent language. The language has no symbols".to_string())
+    );
+
+    set_header(&mut mock, [LANGUAGE_VERSION as u32, 5, 0, 10]);
+    assert_eq!(
+        validate(&mock),
+        Err("Inconsistent language. The language has more tokens than symbols".to_string())
+    );
+
+    // Parsers refuse to use an inconsistent language.
+    let language = unsafe { Language::from_raw(mock.as_ptr() as *const ()) };
+    assert_eq!(
+        Parser::new()
+            .set_language(language)
+            .map_err(|error| error.to_string()),
+        validate(&mock)
+    );
+
+    set_header(&mut mock, [1, 5, 0, 3]);
+    assert!(validate(&mock)
+        .unwrap_err()
+        .starts_with("Incompatible language version 1."));
+}
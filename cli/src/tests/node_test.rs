@@ -3,8 +3,10 @@ use super::helpers::fixtures::{fixtures_dir, get_language, get_test_language};
 use super::helpers::random::Rand;
 use crate::generate::generate_parser_for_grammar;
 use crate::parse::perform_edit;
+use crate::util::{format_node_location, format_node_location_with_tab_width};
+use std::collections::HashSet;
 use std::fs;
-use tree_sitter::{Node, Parser, Point, Tree};
+use tree_sitter::{ChildError, KindSet, Node, Parser, Point, RecoveryKind, Tree};
 
 const JSON_EXAMPLE: &'static str = r#"
 
@@ -199,6 +201,122 @@ fn test_node_children() {
     );
 }
 
+#[test]
+fn test_node_collect_children() {
+    let tree = parse_json_example();
+    let mut buffer = Vec::new();
+    let array_node = tree.root_node().child(0).unwrap();
+
+    let children = array_node.collect_children(&mut buffer);
+    assert_eq!(
+        children.iter().map(|n| n.kind()).collect::<Vec<_>>(),
+        &["[", "number", ",", "false", ",", "object", "]"]
+    );
+
+    // The buffer's previous contents are replaced.
+    let object_node = array_node.named_child(2).unwrap();
+    let children = object_node.collect_children(&mut buffer);
+    assert_eq!(
+        children.iter().map(|n| n.kind()).collect::<Vec<_>>(),
+        &["{", "pair", "}"]
+    );
+
+    let number_node = array_node.named_child(0).unwrap();
+    assert!(number_node.collect_children(&mut buffer).is_empty());
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_node_text_between() {
+    let tree = parse_json_example();
+    let array_node = tree.root_node().child(0).unwrap();
+    let number_node = array_node.child(1).unwrap();
+    let comma_node = array_node.child(2).unwrap();
+    let false_node = array_node.child(3).unwrap();
+    let source = JSON_EXAMPLE.as_bytes();
+
+    assert_eq!(
+        Node::text_between(number_node, false_node, source),
+        Some(",\n  ")
+    );
+    assert_eq!(
+        Node::text_between(number_node, comma_node, source),
+        Some("")
+    );
+    assert_eq!(Node::text_between(false_node, number_node, source), None);
+
+    // The nodes may come from a tree whose source is longer than the given text.
+    assert_eq!(
+        Node::text_between(number_node, false_node, &source[..number_node.end_byte()]),
+        None
+    );
+}
+
+#[test]
+fn test_node_blank_lines_between() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let source = "a; b;\nc;\n\n\nd;\n// comment\n\ne;";
+    let tree = parser.parse(source, None).unwrap();
+    let children = (0..6)
+        .map(|i| tree.root_node().named_child(i).unwrap())
+        .collect::<Vec<_>>();
+    let (a, b, c, d, comment, e) = (
+        children[0],
+        children[1],
+        children[2],
+        children[3],
+        children[4],
+        children[5],
+    );
+    assert_eq!(comment.kind(), "comment");
+    let source = source.as_bytes();
+
+    // Nodes on the same line.
+    assert_eq!(Node::blank_lines_between(a, b, source), 0);
+
+    // Nodes on adjacent lines, and nodes separated by blank lines.
+    assert_eq!(Node::blank_lines_between(b, c, source), 1);
+    assert_eq!(Node::blank_lines_between(c, d, source), 3);
+
+    // With a comment between the nodes, only the whitespace after the comment counts.
+    assert_eq!(Node::blank_lines_between(d, e, source), 2);
+    assert_eq!(Node::blank_lines_between(d, comment, source), 1);
+
+    // Nodes that are out of order.
+    assert_eq!(Node::blank_lines_between(c, b, source), 0);
+}
+
+#[test]
+fn test_node_leading_whitespace() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let source = "  function f() {\n    return a;\n}";
+    let tree = parser.parse(source, None).unwrap();
+
+    let function_node = tree.root_node().child(0).unwrap();
+    assert_eq!(function_node.kind(), "function_declaration");
+    assert_eq!(function_node.leading_whitespace(source.as_bytes()), "  ");
+
+    let body_node = function_node.child_by_field_name("body").unwrap();
+    let return_node = body_node.named_child(0).unwrap();
+    assert_eq!(return_node.kind(), "return_statement");
+    assert_eq!(return_node.leading_whitespace(source.as_bytes()), "\n    ");
+
+    // The first token of a node has the same leading whitespace as the node.
+    let return_keyword_node = return_node.child(0).unwrap();
+    assert_eq!(
+        return_keyword_node.leading_whitespace(source.as_bytes()),
+        "\n    "
+    );
+
+    let identifier_node = return_node.named_child(0).unwrap();
+    assert_eq!(identifier_node.leading_whitespace(source.as_bytes()), " ");
+
+    let semicolon_node = return_node.child(2).unwrap();
+    assert_eq!(semicolon_node.leading_whitespace(source.as_bytes()), "");
+}
+
 #[test]
 fn test_node_children_by_field_name() {
     let mut parser = Parser::new();
@@ -248,6 +366,179 @@ fn test_node_parent_of_child_by_field_name() {
     );
 }
 
+#[test]
+fn test_node_parent_until_and_parent_while() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let source = "function outer() { function inner() { return [a.b.c.d]; } }";
+    let tree = parser.parse(source, None).unwrap();
+
+    let b_offset = source.find("b.c").unwrap();
+    let b_node = tree
+        .root_node()
+        .descendant_for_byte_range(b_offset, b_offset + 1)
+        .unwrap();
+    assert_eq!(b_node.kind(), "property_identifier");
+
+    // The nearest enclosing function is found, rather than the outer one.
+    let function_node = b_node.parent_until("function_declaration").unwrap();
+    assert_eq!(
+        function_node
+            .child_by_field_name("name")
+            .unwrap()
+            .utf8_text(source.as_bytes())
+            .unwrap(),
+        "inner"
+    );
+    assert_eq!(
+        function_node.parent_until("function_declaration").unwrap(),
+        tree.root_node().named_child(0).unwrap()
+    );
+    assert_eq!(b_node.parent_until("class_declaration"), None);
+
+    // Walking up through the chain of member expressions reaches the outermost one.
+    let member_node = b_node
+        .parent_while(|node| node.kind() == "member_expression")
+        .unwrap();
+    assert_eq!(member_node.utf8_text(source.as_bytes()).unwrap(), "a.b.c.d");
+    assert_eq!(member_node.parent().unwrap().kind(), "array");
+    assert_eq!(b_node.parent_while(|node| node.kind() == "array"), None);
+}
+
+#[test]
+fn test_node_leaves() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("json")).unwrap();
+    let source = "[1,true,{},[null]]";
+    let tree = parser.parse(source, None).unwrap();
+
+    let leaves = tree.root_node().leaves().collect::<Vec<_>>();
+    assert_eq!(
+        leaves.iter().map(|node| node.kind()).collect::<Vec<_>>(),
+        &["[", "number", ",", "true", ",", "{", "}", ",", "[", "null", "]", "]"]
+    );
+
+    // The leaves cover the whole source, without any hidden tokens.
+    let text = leaves
+        .iter()
+        .map(|node| node.utf8_text(source.as_bytes()).unwrap())
+        .collect::<String>();
+    assert_eq!(text, source);
+
+    // The leaves of a subtree stop at the end of that subtree.
+    let object_node = tree.root_node().child(0).unwrap().named_child(2).unwrap();
+    assert_eq!(object_node.kind(), "object");
+    assert_eq!(
+        object_node
+            .leaves()
+            .map(|node| node.kind())
+            .collect::<Vec<_>>(),
+        &["{", "}"]
+    );
+
+    // A leaf is its own only leaf.
+    let number_node = leaves[1];
+    assert_eq!(number_node.leaves().collect::<Vec<_>>(), &[number_node]);
+
+    // Missing nodes are leaves.
+    parser.set_language(get_language("javascript")).unwrap();
+    let tree = parser.parse("{\n  a: b,\n  c:\n}", None).unwrap();
+    let missing_leaves = tree
+        .root_node()
+        .leaves()
+        .filter(|node| node.is_missing())
+        .collect::<Vec<_>>();
+    assert_eq!(missing_leaves.len(), 1);
+    assert_eq!(missing_leaves[0].kind(), "identifier");
+}
+
+#[test]
+fn test_node_display() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let tree = parser.parse("let abc = 'x';", None).unwrap();
+
+    let declarator_node = tree
+        .root_node()
+        .named_child(0)
+        .unwrap()
+        .named_child(0)
+        .unwrap();
+    assert_eq!(declarator_node.to_string(), "variable_declarator [4..13]");
+    assert_eq!(declarator_node.child(1).unwrap().to_string(), "= [8..9]");
+    assert_eq!(format!("{}", tree.root_node()), "program [0..14]");
+}
+
+#[test]
+fn test_node_child_count_excluding_missing() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    fn find_arguments(tree: &Tree) -> Node<'_> {
+        tree.preorder()
+            .map(|(node, _)| node)
+            .find(|node| node.kind() == "arguments")
+            .unwrap()
+    }
+
+    // The closing parenthesis of the arguments is missing.
+    let tree = parser.parse("const h = `i ${j(k} l`", None).unwrap();
+    let arguments_node = find_arguments(&tree);
+    assert!(arguments_node.child(2).unwrap().is_missing());
+    assert_eq!(arguments_node.child_count(), 3);
+    assert_eq!(arguments_node.child_count_excluding_missing(), 2);
+
+    // Without any missing children, the counts are the same.
+    let tree = parser.parse("const h = `i ${j(k)} l`", None).unwrap();
+    let arguments_node = find_arguments(&tree);
+    assert_eq!(arguments_node.child_count(), 3);
+    assert_eq!(arguments_node.child_count_excluding_missing(), 3);
+}
+
+#[test]
+fn test_node_try_child() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    fn find_arguments(tree: &Tree) -> Node<'_> {
+        tree.preorder()
+            .map(|(node, _)| node)
+            .find(|node| node.kind() == "arguments")
+            .unwrap()
+    }
+
+    let tree = parser.parse("const h = `i ${j(k)} l`", None).unwrap();
+    let arguments_node = find_arguments(&tree);
+    assert_eq!(arguments_node.try_child(1).unwrap().kind(), "identifier");
+    assert_eq!(arguments_node.try_child(2).unwrap().kind(), ")");
+    assert_eq!(
+        arguments_node.try_child(3),
+        Err(ChildError::OutOfRange {
+            index: 3,
+            child_count: 3
+        })
+    );
+
+    // The closing parenthesis of the arguments is missing.
+    let tree = parser.parse("const h = `i ${j(k} l`", None).unwrap();
+    let arguments_node = find_arguments(&tree);
+    assert_eq!(arguments_node.try_child(1).unwrap().kind(), "identifier");
+    assert_eq!(
+        arguments_node.try_child(2),
+        Err(ChildError::Missing { index: 2 })
+    );
+
+    // A node with no children.
+    let identifier_node = arguments_node.child(1).unwrap();
+    assert_eq!(
+        identifier_node.try_child(0),
+        Err(ChildError::OutOfRange {
+            index: 0,
+            child_count: 0
+        })
+    );
+}
+
 #[test]
 fn test_node_field_name_for_child() {
     let mut parser = Parser::new();
@@ -364,6 +655,226 @@ fn test_node_named_child() {
     assert_eq!(tree.root_node().parent(), None);
 }
 
+#[test]
+fn test_node_first_child_for_byte() {
+    let tree = parse_json_example();
+    let array_node = tree.root_node().child(0).unwrap();
+
+    // A byte within a child returns that child.
+    let false_offset = JSON_EXAMPLE.find("false").unwrap();
+    let false_node = array_node.first_child_for_byte(false_offset + 1).unwrap();
+    assert_eq!(false_node.kind(), "false");
+    assert_eq!(false_node, array_node.named_child(1).unwrap());
+
+    // A byte between children returns the next child.
+    let comma_offset = JSON_EXAMPLE.find(",").unwrap();
+    assert_eq!(
+        array_node
+            .first_child_for_byte(comma_offset)
+            .unwrap()
+            .kind(),
+        ","
+    );
+    assert_eq!(
+        array_node
+            .first_child_for_byte(comma_offset + 1)
+            .unwrap()
+            .kind(),
+        "false"
+    );
+    assert_eq!(
+        array_node
+            .first_named_child_for_byte(comma_offset)
+            .unwrap()
+            .kind(),
+        "false"
+    );
+
+    // A byte past the end of the node returns nothing.
+    assert_eq!(array_node.first_child_for_byte(array_node.end_byte()), None);
+}
+
+#[test]
+fn test_node_utf16_range() {
+    let source = "[\"é\", \"😀\", \"a\"]";
+    let mut parser = Parser::new();
+    parser.set_language(get_language("json")).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let array_node = tree.root_node().child(0).unwrap();
+    let utf16_source = source.encode_utf16().collect::<Vec<_>>();
+
+    // A two-byte character takes one code unit.
+    let accent_node = array_node.named_child(0).unwrap();
+    assert_eq!(accent_node.byte_range(), 1..5);
+    assert_eq!(accent_node.utf16_range(source.as_bytes()), 1..4);
+
+    // A four-byte character takes a surrogate pair.
+    let emoji_node = array_node.named_child(1).unwrap();
+    assert_eq!(emoji_node.byte_range(), 7..13);
+    assert_eq!(emoji_node.utf16_range(source.as_bytes()), 6..10);
+    assert_eq!(
+        String::from_utf16(&utf16_source[emoji_node.utf16_range(source.as_bytes())]).unwrap(),
+        "\"😀\""
+    );
+
+    // Offsets after the multibyte characters are shifted accordingly.
+    let ascii_node = array_node.named_child(2).unwrap();
+    assert_eq!(ascii_node.byte_range(), 15..18);
+    assert_eq!(ascii_node.utf16_range(source.as_bytes()), 12..15);
+    assert_eq!(
+        array_node.utf16_range(source.as_bytes()),
+        0..utf16_source.len()
+    );
+}
+
+#[test]
+fn test_node_named_child_count() {
+    let tree = parse_json_example();
+    let array_node = tree.root_node().child(0).unwrap();
+    assert_eq!(array_node.child_count(), 7);
+    assert_eq!(array_node.named_child_count(), 3);
+
+    let mut cursor = tree.walk();
+    let mut nodes = vec![tree.root_node()];
+    while let Some(node) = nodes.pop() {
+        let children = node.children(&mut cursor).collect::<Vec<_>>();
+        assert!(node.named_child_count() <= node.child_count());
+        assert_eq!(
+            node.named_child_count(),
+            children.iter().filter(|child| child.is_named()).count()
+        );
+        nodes.extend(children);
+    }
+}
+
+#[test]
+fn test_node_named_descendants() {
+    fn collect_named_descendants<'tree>(node: Node<'tree>, nodes: &mut Vec<Node<'tree>>) {
+        for i in 0..node.child_count() {
+            let child = node.child(i).unwrap();
+            if child.is_named() {
+                nodes.push(child);
+            }
+            collect_named_descendants(child, nodes);
+        }
+    }
+
+    let tree = parse_json_example();
+    let root = tree.root_node();
+    let mut expected = Vec::new();
+    collect_named_descendants(root, &mut expected);
+
+    let descendants = root.named_descendants().collect::<Vec<_>>();
+    assert_eq!(descendants.len(), expected.len());
+    assert_eq!(descendants, expected);
+    assert!(descendants.iter().all(|node| node.is_named()));
+    let kinds = descendants
+        .iter()
+        .map(|node| node.kind())
+        .collect::<Vec<_>>();
+    assert_eq!(&kinds[0..4], &["array", "number", "false", "object"]);
+
+    // The descendants of a node beneath the root stay within that node.
+    let object_node = descendants[3];
+    let object_descendants = object_node.named_descendants().collect::<Vec<_>>();
+    let mut expected = Vec::new();
+    collect_named_descendants(object_node, &mut expected);
+    assert_eq!(object_descendants, expected);
+    assert_eq!(object_descendants.first().unwrap().kind(), "pair");
+    assert_eq!(object_descendants.last().unwrap().kind(), "null");
+
+    // Leaf nodes have no descendants.
+    assert_eq!(descendants[1].named_descendants().count(), 0);
+}
+
+#[test]
+fn test_node_nth_named_descendant() {
+    // Find the nth named descendant by counting named nodes in pre-order.
+    fn find_nth_named_descendant<'tree>(node: Node<'tree>, n: &mut usize) -> Option<Node<'tree>> {
+        for i in 0..node.child_count() {
+            let child = node.child(i).unwrap();
+            if child.is_named() {
+                if *n == 0 {
+                    return Some(child);
+                }
+                *n -= 1;
+            }
+            if let Some(result) = find_nth_named_descendant(child, n) {
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    let tree = parse_json_example();
+    let root = tree.root_node();
+    let count = root.named_descendants().count();
+    for n in 0..count {
+        let expected = find_nth_named_descendant(root, &mut n.clone());
+        assert_eq!(root.nth_named_descendant(n), expected);
+    }
+    assert_eq!(root.nth_named_descendant(count), None);
+
+    assert_eq!(root.nth_named_descendant(0).unwrap().kind(), "array");
+    assert_eq!(root.nth_named_descendant(3).unwrap().kind(), "object");
+    assert_eq!(root.nth_named_descendant(4).unwrap().kind(), "pair");
+
+    // The numbering starts from the node on which it was requested.
+    let object_node = root.nth_named_descendant(3).unwrap();
+    assert_eq!(object_node.nth_named_descendant(0).unwrap().kind(), "pair");
+}
+
+#[test]
+fn test_node_descendants_matching_kind_set() {
+    let tree = parse_json_example();
+    let root = tree.root_node();
+    let language = tree.language();
+
+    let kinds = KindSet::new(language, &["number", "null", ",", "not_a_kind"]);
+    assert!(kinds.contains(language.id_for_node_kind("number", true)));
+    assert!(kinds.contains(language.id_for_node_kind(",", false)));
+    assert!(!kinds.contains(language.id_for_node_kind("array", true)));
+    assert!(!kinds.contains(u16::MAX));
+
+    let descendants = root.descendants_matching(&kinds).collect::<Vec<_>>();
+    assert_eq!(
+        descendants
+            .iter()
+            .map(|node| node.kind())
+            .collect::<Vec<_>>(),
+        &["number", ",", ",", "null"]
+    );
+
+    // The result is the same as comparing every descendant's kind by name.
+    let mut cursor = root.walk();
+    let mut expected = Vec::new();
+    let mut stack = root.children(&mut cursor).collect::<Vec<_>>();
+    stack.reverse();
+    while let Some(node) = stack.pop() {
+        if ["number", "null", ","].contains(&node.kind()) {
+            expected.push(node);
+        }
+        let mut children = node.children(&mut cursor).collect::<Vec<_>>();
+        children.reverse();
+        stack.extend(children);
+    }
+    assert_eq!(descendants, expected);
+
+    let empty = KindSet::new(language, &[]);
+    assert_eq!(root.descendants_matching(&empty).count(), 0);
+
+    // The set doesn't contain nodes of another language, even those with the same kinds.
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let other_tree = parser.parse("[1, null]", None).unwrap();
+    assert_eq!(kinds.language(), language);
+    assert!(!kinds.contains_node(other_tree.root_node().nth_named_descendant(2).unwrap()));
+    assert_eq!(
+        other_tree.root_node().descendants_matching(&kinds).count(),
+        0
+    );
+}
+
 #[test]
 fn test_node_named_child_with_aliases_and_extras() {
     let (parser_name, parser_code) =
@@ -385,6 +896,45 @@ fn test_node_named_child_with_aliases_and_extras() {
     assert_eq!(root.named_child(4).unwrap().kind(), "C");
 }
 
+#[test]
+fn test_node_symbol_with_aliases() {
+    let (parser_name, parser_code) =
+        generate_parser_for_grammar(GRAMMAR_WITH_ALIASES_AND_EXTRAS).unwrap();
+    let language = get_test_language(&parser_name, &parser_code, None);
+
+    let mut parser = Parser::new();
+    parser.set_language(language).unwrap();
+
+    let tree = parser.parse("b ... b ... c", None).unwrap();
+    let root = tree.root_node();
+    let b_node = root.named_child(0).unwrap();
+    let aliased_b_node = root.named_child(2).unwrap();
+    let aliased_c_node = root.named_child(4).unwrap();
+
+    // Unaliased nodes have the same kind id and symbol.
+    assert_eq!(b_node.kind_id(), b_node.symbol());
+
+    // Aliased nodes have the kind id of the alias, but the symbol of the original rule.
+    assert_ne!(aliased_b_node.kind_id(), b_node.kind_id());
+    assert_eq!(aliased_b_node.symbol(), b_node.symbol());
+    assert_eq!(
+        language.node_kind_for_id(aliased_b_node.kind_id()),
+        Some("B")
+    );
+    assert_eq!(
+        language.node_kind_for_id(aliased_b_node.symbol()),
+        Some("b")
+    );
+
+    // A hidden rule that is aliased wherever it is used takes on the alias as its name,
+    // so its symbol is the same as its kind id.
+    assert_eq!(aliased_c_node.symbol(), aliased_c_node.kind_id());
+    assert_eq!(
+        language.node_kind_for_id(aliased_c_node.symbol()),
+        Some("C")
+    );
+}
+
 #[test]
 fn test_node_descendant_for_range() {
     let tree = parse_json_example();
@@ -497,6 +1047,35 @@ fn test_node_descendant_for_range() {
     assert_eq!(pair_node.end_position(), Point::new(6, 13));
 }
 
+#[test]
+fn test_node_path_to_descendant_for_byte_range() {
+    let tree = parse_json_example();
+    let root_node = tree.root_node();
+    let array_node = root_node.child(0).unwrap();
+
+    let colon_index = JSON_EXAMPLE.find(":").unwrap();
+    let path = root_node.path_to_descendant_for_byte_range(colon_index, colon_index + 1);
+    assert_eq!(
+        path.iter().map(|node| node.kind()).collect::<Vec<_>>(),
+        &["document", "array", "object", "pair", ":"]
+    );
+    for pair in path.windows(2) {
+        assert_eq!(pair[1].parent(), Some(pair[0]));
+    }
+
+    // The path starts at the node on which it was requested.
+    let path = array_node.path_to_descendant_for_byte_range(colon_index, colon_index + 1);
+    assert_eq!(
+        path.iter().map(|node| node.kind()).collect::<Vec<_>>(),
+        &["array", "object", "pair", ":"]
+    );
+
+    // The node itself is the smallest node that spans the range.
+    let path = array_node
+        .path_to_descendant_for_byte_range(array_node.start_byte(), array_node.end_byte());
+    assert_eq!(path, &[array_node]);
+}
+
 #[test]
 fn test_node_edit() {
     let mut code = JSON_EXAMPLE.as_bytes().to_vec();
@@ -557,6 +1136,37 @@ fn test_root_node_with_offset() {
     assert_eq!(child.end_position(), Point::new(2, 10));
 }
 
+#[test]
+fn test_node_to_sexp_with_text() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let source = "foo(bar);\n/* a\n\"b\" */";
+    let tree = parser.parse(source, None).unwrap();
+    let root_node = tree.root_node();
+
+    assert_eq!(
+        root_node.to_sexp_with_text(source.as_bytes()),
+        concat!(
+            "(program ",
+            "(expression_statement (call_expression ",
+            "function: (identifier) \"foo\" ",
+            "arguments: (arguments (identifier) \"bar\"))) ",
+            "(comment) \"/* a\\n\\\"b\\\" */\")",
+        )
+    );
+
+    // Apart from the leaf text, the result matches `to_sexp`.
+    let call_node = root_node.child(0).unwrap().child(0).unwrap();
+    assert_eq!(
+        call_node.to_sexp_with_text(source.as_bytes()),
+        "(call_expression function: (identifier) \"foo\" arguments: (arguments (identifier) \"bar\"))"
+    );
+    assert_eq!(
+        call_node.to_sexp(),
+        "(call_expression function: (identifier) arguments: (arguments (identifier)))"
+    );
+}
+
 #[test]
 fn test_node_is_extra() {
     let mut parser = Parser::new();
@@ -572,6 +1182,76 @@ fn test_node_is_extra() {
     assert!(comment_node.is_extra());
 }
 
+#[test]
+fn test_node_recovery_kind() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    // An unexpected identifier is skipped, and wrapped in an error node.
+    let source = "e f;";
+    let tree = parser.parse(source, None).unwrap();
+    assert_eq!(
+        tree.root_node().to_sexp(),
+        "(program (expression_statement (identifier) (ERROR (identifier))))"
+    );
+    let mut recovered = get_all_nodes(&tree)
+        .into_iter()
+        .filter(|node| node.recovery_kind() != RecoveryKind::None)
+        .collect::<Vec<_>>();
+    recovered.dedup();
+    assert_eq!(recovered.len(), 1);
+    assert_eq!(recovered[0].recovery_kind(), RecoveryKind::Skipped);
+    assert_eq!(recovered[0].utf8_text(source.as_bytes()).unwrap(), "f");
+    assert_eq!(recovered[0].child(0).unwrap().kind(), "identifier");
+
+    // A missing object value is inserted, and takes up no space.
+    let source = "{\n  a: b,\n  c:\n}";
+    let tree = parser.parse(source, None).unwrap();
+    let recovered = get_all_nodes(&tree)
+        .into_iter()
+        .filter(|node| node.recovery_kind() != RecoveryKind::None)
+        .collect::<Vec<_>>();
+    assert_eq!(recovered.len(), 1);
+    assert_eq!(recovered[0].recovery_kind(), RecoveryKind::Missing);
+    assert_eq!(recovered[0].kind(), "identifier");
+    assert_eq!(recovered[0].start_byte(), recovered[0].end_byte());
+    assert!(!recovered[0].is_error());
+}
+
+#[test]
+fn test_node_siblings_skipping_extras() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let source = "a;\n// one\n/* two */\nb;\n// three\n";
+    let tree = parser.parse(source, None).unwrap();
+
+    let root_node = tree.root_node();
+    let first_statement = root_node.child(0).unwrap();
+    let second_statement = root_node.child(3).unwrap();
+    let last_comment = root_node.child(4).unwrap();
+    assert_eq!(first_statement.utf8_text(source.as_bytes()).unwrap(), "a;");
+    assert_eq!(second_statement.utf8_text(source.as_bytes()).unwrap(), "b;");
+    assert!(first_statement.next_sibling().unwrap().is_extra());
+    assert!(last_comment.is_extra());
+
+    assert_eq!(
+        first_statement.next_sibling_skipping_extras(),
+        Some(second_statement)
+    );
+    assert_eq!(
+        second_statement.prev_sibling_skipping_extras(),
+        Some(first_statement)
+    );
+
+    // There are only extras after the last statement, and nothing before the first.
+    assert_eq!(second_statement.next_sibling_skipping_extras(), None);
+    assert_eq!(first_statement.prev_sibling_skipping_extras(), None);
+    assert_eq!(
+        last_comment.prev_sibling_skipping_extras(),
+        Some(second_statement)
+    );
+}
+
 #[test]
 fn test_node_sexp() {
     let mut parser = Parser::new();
@@ -589,6 +1269,94 @@ fn test_node_sexp() {
     assert_eq!(identifier_node.to_sexp(), "(identifier)");
 }
 
+#[test]
+fn test_node_equality_and_hashing() {
+    let tree = parse_json_example();
+    let root_node = tree.root_node();
+    let array_node = root_node.child(0).unwrap();
+
+    // The traversal visits parent nodes again on its way back up the tree, but each
+    // node is only stored once.
+    let all_nodes = get_all_nodes(&tree);
+    let mut nodes = all_nodes.iter().cloned().collect::<HashSet<_>>();
+    let node_count = nodes.len();
+    assert!(node_count < all_nodes.len());
+    assert_eq!(
+        node_count,
+        all_nodes
+            .iter()
+            .map(|n| n.id())
+            .collect::<HashSet<_>>()
+            .len()
+    );
+
+    // The same nodes, reached in different ways, are deduplicated.
+    let mut cursor = array_node.walk();
+    for child in array_node.children(&mut cursor) {
+        assert!(!nodes.insert(child));
+        assert_eq!(child.parent(), Some(array_node));
+    }
+    assert!(!nodes.insert(root_node));
+    assert!(nodes.contains(&array_node.named_child(0).unwrap()));
+    assert_eq!(nodes.len(), node_count);
+
+    // Nodes in a copy of the tree are not the same nodes, even though they have the same ids.
+    let tree_copy = tree.clone();
+    let root_node_copy = tree_copy.root_node();
+    assert_eq!(root_node_copy.id(), root_node.id());
+    assert_ne!(root_node_copy, root_node);
+    assert!(!nodes.contains(&root_node_copy));
+}
+
+#[test]
+fn test_node_location_formatting_with_tabs() {
+    let source = "{\n\t\"a\":\t[1, 2],\n\t\"b\": {\n\t}\n}";
+    let mut parser = Parser::new();
+    parser.set_language(get_language("json")).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    let root_node = tree.root_node();
+
+    let array_start = source.find('[').unwrap();
+    let array_node = root_node
+        .descendant_for_byte_range(array_start, array_start + 6)
+        .unwrap();
+    assert_eq!(array_node.kind(), "array");
+
+    // The carets line up with the node's text once tabs have been expanded.
+    assert_eq!(
+        format_node_location(source.as_bytes(), array_node),
+        concat!(
+            "    \"a\":    [1, 2],\n", //
+            "            ^^^^^^\n",
+        )
+    );
+    assert_eq!(
+        format_node_location_with_tab_width(source.as_bytes(), array_node, 2),
+        concat!(
+            "  \"a\":  [1, 2],\n", //
+            "        ^^^^^^\n",
+        )
+    );
+
+    // Nodes that span several lines are underlined on each line.
+    let object_start = source.rfind('{').unwrap();
+    let object_node = root_node
+        .descendant_for_byte_range(object_start, object_start + 1)
+        .unwrap()
+        .parent()
+        .unwrap();
+    assert_eq!(object_node.kind(), "object");
+    assert_eq!(
+        format_node_location(source.as_bytes(), object_node),
+        concat!(
+            "    \"b\": {\n", //
+            "         ^\n",
+            "    }\n",
+            "^^^^^\n",
+        )
+    );
+}
+
 #[test]
 fn test_node_field_names() {
     let (parser_name, parser_code) = generate_parser_for_grammar(
@@ -12,7 +12,10 @@ use std::{
     sync::atomic::{AtomicUsize, Ordering},
     thread, time,
 };
-use tree_sitter::{IncludedRangesError, InputEdit, LogType, Parser, Point, Range};
+use tree_sitter::{
+    strip_bom, Chunks, IncludedRangesError, InputEdit, InputEncoding, LogType, Parser,
+    ParserBuilder, ParserBuilderError, Point, Range,
+};
 
 #[test]
 fn test_parsing_simple_string() {
@@ -151,6 +154,108 @@ fn test_parsing_with_custom_utf8_input() {
     assert_eq!(root.child(0).unwrap().kind(), "function_item");
 }
 
+#[test]
+fn test_parsing_tokens() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    let source = "let a = [1, b]; // c";
+    let tokens = parser.tokenize(source).unwrap();
+    assert_eq!(
+        tokens
+            .iter()
+            .map(|token| (
+                token.kind,
+                &source[token.range.start_byte..token.range.end_byte]
+            ))
+            .collect::<Vec<_>>(),
+        &[
+            ("let", "let"),
+            ("identifier", "a"),
+            ("=", "="),
+            ("[", "["),
+            ("number", "1"),
+            (",", ","),
+            ("identifier", "b"),
+            ("]", "]"),
+            (";", ";"),
+            ("comment", "// c"),
+        ]
+    );
+    assert_eq!(tokens[4].range.start_point, Point::new(0, 9));
+
+    // Missing tokens are omitted.
+    let source = "const h = `i ${j(k} l`";
+    let tree = parser.parse(source, None).unwrap();
+    assert!(tree.root_node().leaves().any(|node| node.is_missing()));
+    let tokens = parser.tokenize(source).unwrap();
+    assert_eq!(tokens.len(), tree.root_node().leaves().count() - 1);
+    assert!(tokens
+        .iter()
+        .all(|token| token.range.start_byte < token.range.end_byte));
+}
+
+#[test]
+fn test_parsing_chunks() {
+    // A text that is stored in pieces, like a rope, which records the offsets that
+    // were requested.
+    struct Pieces {
+        pieces: Vec<&'static str>,
+        requested_offsets: std::cell::RefCell<Vec<usize>>,
+    }
+
+    impl Chunks for Pieces {
+        fn chunk_at(&self, byte: usize) -> &[u8] {
+            self.requested_offsets.borrow_mut().push(byte);
+            let mut piece_start = 0;
+            for piece in &self.pieces {
+                let piece_end = piece_start + piece.len();
+                if byte < piece_end {
+                    return &piece.as_bytes()[(byte - piece_start)..];
+                }
+                piece_start = piece_end;
+            }
+            &[]
+        }
+    }
+
+    let pieces = Pieces {
+        pieces: vec!["pub fn f", "oo() {\n", "  1", "\n}"],
+        requested_offsets: Default::default(),
+    };
+
+    let mut parser = Parser::new();
+    parser.set_language(get_language("rust")).unwrap();
+    let tree = parser.parse_chunks(&pieces, None).unwrap();
+    let root = tree.root_node();
+    assert_eq!(
+        root.to_sexp(),
+        concat!(
+            "(source_file ",
+            "(function_item ",
+            "(visibility_modifier) ",
+            "name: (identifier) ",
+            "parameters: (parameters) ",
+            "body: (block (integer_literal))))"
+        )
+    );
+    assert_eq!(root.end_byte(), 20);
+    assert_eq!(
+        root.child(0)
+            .unwrap()
+            .child_by_field_name("name")
+            .unwrap()
+            .byte_range(),
+        7..10
+    );
+
+    // Each piece was requested from its start, without the text being copied.
+    let requested_offsets = pieces.requested_offsets.borrow();
+    for offset in &[0, 8, 15, 18, 20] {
+        assert!(requested_offsets.contains(offset));
+    }
+}
+
 #[test]
 fn test_parsing_with_custom_utf16_input() {
     let mut parser = Parser::new();
@@ -270,6 +375,96 @@ fn test_parsing_text_with_byte_order_mark() {
     assert_eq!(tree.root_node().start_byte(), 3);
 }
 
+#[test]
+fn test_parsing_with_detected_encoding() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("rust")).unwrap();
+
+    let expected_sexp =
+        "(source_file (function_item name: (identifier) parameters: (parameters) body: (block)))";
+    let utf16_le = |text: &str| {
+        text.encode_utf16()
+            .flat_map(|unit| vec![unit as u8, (unit >> 8) as u8])
+            .collect::<Vec<_>>()
+    };
+    let utf16_be = |text: &str| {
+        text.encode_utf16()
+            .flat_map(|unit| vec![(unit >> 8) as u8, unit as u8])
+            .collect::<Vec<_>>()
+    };
+
+    // UTF8, with and without a BOM
+    for text in &["fn a() {}", "\u{FEFF}fn a() {}"] {
+        let (tree, encoding) = parser.parse_detect_encoding(text.as_bytes(), None).unwrap();
+        assert_eq!(encoding, InputEncoding::Utf8);
+        assert_eq!(tree.root_node().to_sexp(), expected_sexp);
+    }
+
+    // UTF16LE, with and without a BOM
+    for text in &["\u{FEFF}fn a() {}", "fn a() {}"] {
+        let (tree, encoding) = parser.parse_detect_encoding(&utf16_le(text), None).unwrap();
+        assert_eq!(encoding, InputEncoding::Utf16LE);
+        assert_eq!(tree.root_node().to_sexp(), expected_sexp);
+    }
+
+    // UTF16BE, with and without a BOM
+    for text in &["\u{FEFF}fn a() {}", "fn a() {}"] {
+        let (tree, encoding) = parser.parse_detect_encoding(&utf16_be(text), None).unwrap();
+        assert_eq!(encoding, InputEncoding::Utf16BE);
+        assert_eq!(tree.root_node().to_sexp(), expected_sexp);
+    }
+
+    // UTF16 without a BOM can contain characters whose low byte is zero, as long as
+    // most of the text is ASCII.
+    let text = "fn a() { \"\u{100}\u{4E00}\"; }";
+    let (tree, encoding) = parser.parse_detect_encoding(&utf16_le(text), None).unwrap();
+    assert_eq!(encoding, InputEncoding::Utf16LE);
+    assert!(!tree.root_node().has_error());
+    let (tree, encoding) = parser.parse_detect_encoding(&utf16_be(text), None).unwrap();
+    assert_eq!(encoding, InputEncoding::Utf16BE);
+    assert!(!tree.root_node().has_error());
+
+    // ASCII text with an even length is not mistaken for UTF16.
+    let (_, encoding) = parser.parse_detect_encoding(b"fn a() {}\n", None).unwrap();
+    assert_eq!(encoding, InputEncoding::Utf8);
+}
+
+#[test]
+fn test_parsing_text_with_stripped_byte_order_mark() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("rust")).unwrap();
+
+    let source = "\u{FEFF}fn a() {}\nfn b() {}";
+    let (stripped_source, bom_length) = strip_bom(source.as_bytes());
+    assert_eq!(stripped_source, b"fn a() {}\nfn b() {}");
+    assert_eq!(bom_length, 3);
+    assert_eq!(strip_bom(stripped_source), (stripped_source, 0));
+
+    // Without stripping, the BOM is skipped and positions are relative to the full text.
+    let tree = parser.parse(source, None).unwrap();
+    let function_node = tree.root_node().child(0).unwrap();
+    assert_eq!(function_node.start_byte(), 3);
+    assert_eq!(function_node.start_position(), Point::new(0, 3));
+
+    // After stripping, positions are relative to the text after the BOM, and can be
+    // converted by adding the BOM's length.
+    let stripped_tree = parser.parse(stripped_source, None).unwrap();
+    assert_eq!(
+        stripped_tree.root_node().to_sexp(),
+        tree.root_node().to_sexp()
+    );
+    let stripped_function_node = stripped_tree.root_node().child(0).unwrap();
+    assert_eq!(stripped_function_node.start_byte(), 0);
+    assert_eq!(
+        stripped_function_node.end_byte() + bom_length,
+        function_node.end_byte()
+    );
+    assert_eq!(
+        stripped_tree.root_node().child(1).unwrap().start_byte() + bom_length,
+        tree.root_node().child(1).unwrap().start_byte()
+    );
+}
+
 #[test]
 fn test_parsing_invalid_chars_at_eof() {
     let mut parser = Parser::new();
@@ -311,6 +506,88 @@ fn test_parsing_ends_when_input_callback_returns_empty() {
     assert_eq!(tree.root_node().end_byte(), 6);
 }
 
+#[test]
+fn test_parsing_memory_mapped_file() {
+    use std::io::{Seek, SeekFrom, Write};
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(b"fn main() { let x = 1; }").unwrap();
+    let mmap = unsafe { memmap2::Mmap::map(&file).unwrap() };
+
+    let mut parser = Parser::new();
+    parser.set_language(get_language("rust")).unwrap();
+    let mut tree = parser.parse_mmap(&mmap, None).unwrap();
+    assert_eq!(
+        tree.root_node().to_sexp(),
+        "(source_file (function_item name: (identifier) parameters: (parameters) body: (block (let_declaration pattern: (identifier) value: (integer_literal)))))"
+    );
+
+    // Rewrite the file and reparse the new mapping incrementally.
+    drop(mmap);
+    file.set_len(0).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(b"fn main() { let x = 12; }").unwrap();
+    let mmap = unsafe { memmap2::Mmap::map(&file).unwrap() };
+
+    tree.edit(&InputEdit {
+        start_byte: 21,
+        old_end_byte: 21,
+        new_end_byte: 22,
+        start_position: Point::new(0, 21),
+        old_end_position: Point::new(0, 21),
+        new_end_position: Point::new(0, 22),
+    });
+    let tree = parser.parse_mmap(&mmap, Some(&tree)).unwrap();
+    let value = tree.root_node().descendant_for_byte_range(20, 22).unwrap();
+    assert_eq!(value.kind(), "integer_literal");
+    assert_eq!(value.utf8_text(&mmap).unwrap(), "12");
+}
+
+#[test]
+fn test_parsing_incremental_stream() {
+    // A reader that returns one chunk of text per read, like a pipe.
+    struct ChunkReader(Vec<&'static str>);
+
+    impl std::io::Read for ChunkReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.0.remove(0).as_bytes();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    let mut parser = Parser::new();
+    parser.set_language(get_language("json")).unwrap();
+
+    let mut partial_trees = Vec::new();
+    let tree = parser
+        .parse_incremental_stream(ChunkReader(vec!["[1, ", "2,\n", "3]"]), |tree, text| {
+            partial_trees.push((
+                String::from_utf8(text.to_vec()).unwrap(),
+                tree.root_node().has_error(),
+            ));
+        })
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        partial_trees,
+        &[
+            ("[1, ".to_string(), true),
+            ("[1, 2,\n".to_string(), true),
+            ("[1, 2,\n3]".to_string(), false),
+        ]
+    );
+    assert_eq!(
+        tree.root_node().to_sexp(),
+        "(document (array (number) (number) (number)))"
+    );
+    assert_eq!(tree.root_node().end_position(), Point::new(1, 2));
+}
+
 // Incremental parsing
 
 #[test]
@@ -407,6 +684,62 @@ fn test_parsing_after_editing_end_of_code() {
     assert_eq!(recorder.strings_read(), vec![" * ", "abc.d)",]);
 }
 
+#[test]
+fn test_parsing_after_truncating_end_of_code() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    let mut code = b"a();\nb();\nc();\nd();".to_vec();
+    let mut tree = parser.parse(&code, None).unwrap();
+    assert_eq!(tree.root_node().named_child_count(), 4);
+
+    let position = "a();\nb();\n".len();
+    let deleted_length = code.len() - position;
+    perform_edit(
+        &mut tree,
+        &mut code,
+        &Edit {
+            position,
+            deleted_length,
+            inserted_text: Vec::new(),
+        },
+    );
+    assert_eq!(code, b"a();\nb();\n");
+
+    // The statements before the truncation are unchanged, and the truncated
+    // statements are now empty.
+    let root = tree.root_node();
+    assert!(root.has_changes());
+    assert_eq!(root.end_byte(), position);
+    assert!(!root.named_child(0).unwrap().has_changes());
+    for i in 2..4 {
+        let truncated_statement = root.named_child(i).unwrap();
+        assert!(truncated_statement.has_changes());
+        assert_eq!(truncated_statement.start_byte(), position);
+        assert_eq!(truncated_statement.end_byte(), position);
+    }
+
+    let mut recorder = ReadRecorder::new(&code);
+    let tree = parser
+        .parse_with(&mut |i, _| recorder.read(i), Some(&tree))
+        .unwrap();
+    assert_eq!(
+        tree.root_node().to_sexp(),
+        concat!(
+            "(program ",
+            "(expression_statement (call_expression function: (identifier) arguments: (arguments))) ",
+            "(expression_statement (call_expression function: (identifier) arguments: (arguments))))",
+        )
+    );
+    assert_eq!(tree.root_node().end_byte(), code.len());
+
+    // The first statement is reused without being read again.
+    assert!(recorder
+        .strings_read()
+        .iter()
+        .all(|string| !string.contains("a(")));
+}
+
 #[test]
 fn test_parsing_empty_file_with_reused_tree() {
     let mut parser = Parser::new();
@@ -799,6 +1132,63 @@ fn test_parsing_with_timeout_and_no_completion() {
     });
 }
 
+// Stack depth limits
+
+#[test]
+fn test_parsing_with_a_max_stack_depth() {
+    allocations::record(|| {
+        let mut parser = Parser::new();
+        parser.set_language(get_language("json")).unwrap();
+        assert_eq!(parser.max_stack_depth(), 0);
+
+        let nested_arrays = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+
+        // Input that nests more deeply than the limit is rejected.
+        parser.set_max_stack_depth(100);
+        let tree = parser.parse(nested_arrays(150), None);
+        assert!(tree.is_none());
+
+        // The failed parse is discarded, so the next parse starts from scratch.
+        let tree = parser.parse(nested_arrays(50), None).unwrap();
+        assert!(!tree.root_node().has_error());
+
+        // Removing the limit allows arbitrarily deep input.
+        parser.set_max_stack_depth(0);
+        let tree = parser.parse(nested_arrays(150), None).unwrap();
+        assert!(!tree.root_node().has_error());
+    });
+}
+
+// Parse statistics
+
+#[test]
+fn test_parsing_records_parse_stats() {
+    allocations::record(|| {
+        let mut parser = Parser::new();
+        parser.set_language(get_language("json")).unwrap();
+        assert_eq!(parser.last_parse_stats(), None);
+
+        let source = "[1, {\"a\": true}, null]";
+        parser.parse(source, None).unwrap();
+        let stats = parser.last_parse_stats().unwrap();
+        assert_eq!(stats.bytes_parsed, source.len());
+        assert!(stats.token_count >= 11);
+        assert!(stats.reduction_count > 0);
+        assert!(!stats.had_errors);
+
+        // Statistics are reset at the start of each parse.
+        parser.parse("[1, , 2]", None).unwrap();
+        let stats = parser.last_parse_stats().unwrap();
+        assert_eq!(stats.bytes_parsed, 8);
+        assert!(stats.had_errors);
+
+        // A parse that is halted does not produce any statistics.
+        parser.set_max_stack_depth(10);
+        assert!(parser.parse("[".repeat(20), None).is_none());
+        assert_eq!(parser.last_parse_stats(), None);
+    });
+}
+
 // Included Ranges
 
 #[test]
@@ -1280,6 +1670,43 @@ fn test_parsing_with_included_ranges_and_missing_tokens() {
     assert_eq!(root.child(3).unwrap().start_byte(), 4);
 }
 
+// Parser builder
+
+#[test]
+fn test_parsing_with_a_parser_builder() {
+    let source_code = "<script>a();</script> b();";
+    let script_start = source_code.find("a()").unwrap();
+    let script_end = source_code.find("</script>").unwrap();
+
+    let mut parser = ParserBuilder::new()
+        .language(get_language("javascript"))
+        .timeout(time::Duration::from_secs(1))
+        .included_ranges(&[simple_range(script_start, script_end)])
+        .build()
+        .unwrap();
+    assert_eq!(parser.language(), Some(get_language("javascript")));
+    assert_eq!(parser.timeout_micros(), 1_000_000);
+
+    let tree = parser.parse(source_code, None).unwrap();
+    assert_eq!(
+        tree.root_node().to_sexp(),
+        "(program (expression_statement (call_expression function: (identifier) arguments: (arguments))))"
+    );
+    assert_eq!(tree.root_node().start_byte(), script_start);
+
+    // Invalid configuration is reported when the parser is built.
+    let error = ParserBuilder::new()
+        .language(get_language("javascript"))
+        .included_ranges(&[simple_range(10, 20), simple_range(0, 5)])
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(
+        error,
+        ParserBuilderError::IncludedRanges(IncludedRangesError(1))
+    );
+}
+
 fn simple_range(start: usize, end: usize) -> Range {
     Range {
         start_byte: start,
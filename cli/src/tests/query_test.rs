@@ -5,10 +5,12 @@ use super::helpers::{
 };
 use lazy_static::lazy_static;
 use rand::{prelude::StdRng, SeedableRng};
-use std::{env, fmt::Write};
+use std::{env, fmt::Write, iter, vec};
 use tree_sitter::{
-    CaptureQuantifier, Language, Node, Parser, Point, Query, QueryCapture, QueryCursor, QueryError,
-    QueryErrorKind, QueryMatch, QueryPredicate, QueryPredicateArg, QueryProperty,
+    CaptureNameConflictError, CaptureQuantifier, CaptureTreeNode, Language, MatchOrder, Node,
+    Parser, Point, Query, QueryBuilder, QueryCapture, QueryCursor, QueryError, QueryErrorKind,
+    QueryMatch, QueryPredicate, QueryPredicateArg, QueryPredicateFailure, QueryProperty,
+    QueryWarning, QueryWarningKind, Range, TextProvider,
 };
 
 lazy_static! {
@@ -186,6 +188,58 @@ fn test_query_errors_on_invalid_syntax() {
     });
 }
 
+#[test]
+fn test_query_with_comments() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+
+        // Trailing comments, with and without a final newline.
+        assert!(Query::new(language, "(identifier) @id ; trailing comment").is_ok());
+        assert!(Query::new(language, "(identifier) @id\n; trailing comment\n").is_ok());
+
+        // Comments inside of a pattern.
+        let query = Query::new(
+            language,
+            "
+            (pair ; the pair
+              key: (_) @key ; its key
+              ; its value
+              value: (_) @value)
+            ",
+        )
+        .unwrap();
+        assert_eq!(query.pattern_count(), 1);
+        assert_eq!(query.capture_names(), &["key", "value"]);
+
+        // When a comment swallows the end of a pattern, the error points at the end of
+        // the pattern's last token, not at the end of the comment.
+        assert_eq!(
+            Query::new(language, "(identifier ; a comment)").unwrap_err(),
+            QueryError {
+                row: 0,
+                column: 11,
+                offset: 11,
+                kind: QueryErrorKind::Syntax,
+                message: [
+                    "(identifier ; a comment)", //
+                    "           ^",
+                ]
+                .join("\n")
+            }
+        );
+        assert_eq!(
+            Query::new(language, "(pair\n  key: (_) @key ; (_)\n  ; done)\n")
+                .unwrap_err()
+                .message,
+            [
+                "  key: (_) @key ; (_)", //
+                "               ^",
+            ]
+            .join("\n")
+        );
+    });
+}
+
 #[test]
 fn test_query_errors_on_invalid_symbols() {
     allocations::record(|| {
@@ -264,6 +318,54 @@ fn test_query_errors_on_invalid_symbols() {
     });
 }
 
+#[test]
+fn test_query_validate_all_reports_every_invalid_pattern() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let source = concat!(
+            "(identifier) @variable\n",
+            "; a comment with a \"quote\n",
+            "(clas) @class\n",
+            "\"function\" @keyword\n",
+            "(if_statement condit: (identifier))\n",
+        );
+
+        assert_eq!(
+            Query::new(language, source).unwrap_err(),
+            QueryError {
+                row: 2,
+                offset: 50,
+                column: 1,
+                kind: QueryErrorKind::NodeType,
+                message: "clas".to_string()
+            }
+        );
+        assert_eq!(
+            Query::validate_all(language, source).err().unwrap(),
+            &[
+                QueryError {
+                    row: 2,
+                    offset: 50,
+                    column: 1,
+                    kind: QueryErrorKind::NodeType,
+                    message: "clas".to_string()
+                },
+                QueryError {
+                    row: 4,
+                    offset: 97,
+                    column: 14,
+                    kind: QueryErrorKind::Field,
+                    message: "condit".to_string()
+                },
+            ]
+        );
+
+        // Valid queries are created as usual.
+        let query = Query::validate_all(language, "(identifier) @a \"function\" @b").unwrap();
+        assert_eq!(query.pattern_count(), 2);
+    });
+}
+
 #[test]
 fn test_query_errors_on_invalid_predicates() {
     allocations::record(|| {
@@ -294,6 +396,18 @@ fn test_query_errors_on_invalid_predicates() {
                     .to_string()
             }
         );
+        assert_eq!(
+            Query::new(language, "((identifier) @id (#not-match-ignore-case? @id))")
+                .unwrap_err()
+                .message,
+            "Wrong number of arguments to #not-match-ignore-case? predicate. Expected 2, got 1."
+        );
+        assert_eq!(
+            Query::new(language, r#"((identifier) @id (#eq-ignore-case? "a" @id))"#)
+                .unwrap_err()
+                .message,
+            r#"First argument to #eq-ignore-case? predicate must be a capture name. Got literal "a"."#
+        );
         assert_eq!(
             Query::new(language, "((identifier) @id (#eq? @id @ok))").unwrap_err(),
             QueryError {
@@ -727,6 +841,33 @@ fn test_query_matches_capturing_error_nodes() {
     });
 }
 
+#[test]
+fn test_query_matches_capturing_missing_nodes() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            "(augmented_assignment_expression right: (identifier) @the-missing)",
+        )
+        .unwrap();
+
+        let source = "const a = `b ${d += } c`";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+        let matches = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .map(|m| m.captures[0].node)
+            .collect::<Vec<_>>();
+
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].is_missing());
+        assert_eq!(matches[0].start_byte(), matches[0].end_byte());
+        assert!(!cursor.did_exceed_match_limit());
+    });
+}
+
 #[test]
 fn test_query_matches_with_extra_children() {
     allocations::record(|| {
@@ -849,6 +990,71 @@ fn test_query_matches_with_wildcard_at_the_root() {
     });
 }
 
+#[test]
+fn test_query_captures_with_named_and_anonymous_wildcards() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let source = "[1, a, f(b)];";
+
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+
+        let mut captured_ids = |query_source: &str| {
+            let query = Query::new(language, query_source).unwrap();
+            let mut ids = cursor
+                .matches(&query, tree.root_node(), source.as_bytes())
+                .flat_map(|m| m.captures.iter().map(|c| c.node.id()).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids
+        };
+        let mut named_ids = tree
+            .preorder()
+            .filter(|(node, _)| node.is_named())
+            .map(|(node, _)| node.id())
+            .collect::<Vec<_>>();
+        let mut all_ids = tree
+            .preorder()
+            .map(|(node, _)| node.id())
+            .collect::<Vec<_>>();
+        named_ids.sort_unstable();
+        all_ids.sort_unstable();
+
+        // At the top level of a pattern, the named wildcard captures every named node,
+        // and the anonymous wildcard captures every node.
+        assert_eq!(captured_ids("(_) @node"), named_ids);
+        assert_eq!(captured_ids("_ @node"), all_ids);
+
+        // As children, the named wildcard skips anonymous nodes like punctuation.
+        assert_query_matches(
+            language,
+            &Query::new(language, "(array (_) @named)").unwrap(),
+            source,
+            &[
+                (0, vec![("named", "1")]),
+                (0, vec![("named", "a")]),
+                (0, vec![("named", "f(b)")]),
+            ],
+        );
+        assert_query_matches(
+            language,
+            &Query::new(language, "(array _ @any)").unwrap(),
+            source,
+            &[
+                (0, vec![("any", "[")]),
+                (0, vec![("any", "1")]),
+                (0, vec![("any", ",")]),
+                (0, vec![("any", "a")]),
+                (0, vec![("any", ",")]),
+                (0, vec![("any", "f(b)")]),
+                (0, vec![("any", "]")]),
+            ],
+        );
+    });
+}
+
 #[test]
 fn test_query_matches_with_immediate_siblings() {
     allocations::record(|| {
@@ -954,6 +1160,40 @@ fn test_query_matches_with_last_named_child() {
     });
 }
 
+#[test]
+fn test_query_matches_with_negated_field_on_functions() {
+    allocations::record(|| {
+        let language = get_language("python");
+        let query = Query::new(
+            language,
+            "
+            (function_definition
+                !return_type
+                name: (identifier) @untyped)
+
+            (function_definition
+                return_type: (_)
+                name: (identifier) @typed)
+            ",
+        )
+        .unwrap();
+        assert_query_matches(
+            language,
+            &query,
+            "
+            def a(): pass
+            def b() -> int: pass
+            def c(x: int): pass
+            ",
+            &[
+                (0, vec![("untyped", "a")]),
+                (1, vec![("typed", "b")]),
+                (0, vec![("untyped", "c")]),
+            ],
+        );
+    });
+}
+
 #[test]
 fn test_query_matches_with_negated_fields() {
     allocations::record(|| {
@@ -1814,6 +2054,37 @@ fn test_query_matches_with_anonymous_tokens() {
     });
 }
 
+#[test]
+fn test_query_matches_with_keyword_literals() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (function_declaration "async" name: (identifier) @name)
+            "async" @keyword
+            "#,
+        )
+        .unwrap();
+
+        // The property name `async` is a named node, so it isn't matched.
+        assert_query_matches(
+            language,
+            &query,
+            "async function a() { b.async(); }\nfunction c() {}",
+            &[(1, vec![("keyword", "async")]), (0, vec![("name", "a")])],
+        );
+
+        // Quoted names only refer to anonymous nodes, not to the text of named nodes.
+        assert_eq!(
+            Query::new(language, r#"(identifier) "main""#)
+                .unwrap_err()
+                .kind,
+            QueryErrorKind::NodeType
+        );
+    });
+}
+
 #[test]
 fn test_query_matches_with_supertypes() {
     allocations::record(|| {
@@ -1856,6 +2127,28 @@ fn test_query_matches_with_supertypes() {
     });
 }
 
+#[test]
+fn test_query_matches_with_supertype_matching_concrete_kinds() {
+    allocations::record(|| {
+        let language = get_language("python");
+        let query = Query::new(language, "(argument_list (expression) @arg)").unwrap();
+
+        let source = "f(1, 'two', [three], four + five, six())";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+        let kinds = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .map(|m| m.captures[0].node.kind())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            &["integer", "string", "list", "binary_operator", "call"]
+        );
+    });
+}
+
 #[test]
 fn test_query_matches_within_byte_range() {
     allocations::record(|| {
@@ -1969,6 +2262,43 @@ fn test_query_matches_within_point_range() {
     });
 }
 
+#[test]
+fn test_query_matches_within_line_range() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(language, "(call_expression function: (identifier) @fn)").unwrap();
+
+        let source = "one();\ntwo();\nthree();\nfour();\n";
+
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let mut cursor = QueryCursor::new();
+
+        // Only the calls on the second and third lines overlap the range.
+        let matches = cursor
+            .set_point_range(Point::new(1, 0)..Point::new(3, 0))
+            .matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[(0, vec![("fn", "two")]), (0, vec![("fn", "three")])]
+        );
+
+        // The equivalent byte range excludes the same matches.
+        let start_byte = source.find("two").unwrap();
+        let end_byte = source.find("four").unwrap();
+        let matches = cursor
+            .set_point_range(Point::new(0, 0)..Point::new(0, 0))
+            .set_byte_range(start_byte..end_byte)
+            .matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[(0, vec![("fn", "two")]), (0, vec![("fn", "three")])]
+        );
+    });
+}
+
 #[test]
 fn test_query_captures_within_byte_range() {
     allocations::record(|| {
@@ -2325,79 +2655,1060 @@ fn test_query_matches_with_multiple_captures_on_a_node() {
 }
 
 #[test]
-fn test_query_matches_with_captured_wildcard_at_root() {
+fn test_query_matches_captures_by_index() {
     allocations::record(|| {
-        let language = get_language("python");
+        let language = get_language("javascript");
         let query = Query::new(
             language,
             "
-            ; captured wildcard at the root
-            (_ [
-                (except_clause (block) @block)
-                (finally_clause (block) @block)
-            ]) @stmt
-
-            [
-                (while_statement (block) @block)
-                (if_statement (block) @block)
-
-                ; captured wildcard at the root within an alternation
-                (_ [
-                    (else_clause (block) @block)
-                    (elif_clause (block) @block)
-                ])
-
-                (try_statement (block) @block)
-                (for_statement (block) @block)
-            ] @stmt
+            (pair key: (_) @key value: (_) @value)
+            (assignment_expression left: (identifier) @value right: (identifier) @key)
             ",
         )
         .unwrap();
 
-        let source = "
-        for i in j:
-            while True:
-                if a:
-                    print b
-                elif c:
-                    print d
-                else:
-                    try:
-                        print f
-                    except:
-                        print g
-                    finally:
-                        print h
-            else:
-                print i
-        "
-        .trim();
-
+        let source = "a = b;";
         let mut parser = Parser::new();
         let mut cursor = QueryCursor::new();
+
         parser.set_language(language).unwrap();
-        let tree = parser.parse(&source, None).unwrap();
+        let tree = parser.parse(source, None).unwrap();
 
-        let match_capture_names_and_rows = cursor
+        let matches = cursor
             .matches(&query, tree.root_node(), source.as_bytes())
-            .map(|m| {
-                m.captures
-                    .iter()
-                    .map(|c| {
-                        (
-                            query.capture_names()[c.index as usize].as_str(),
-                            c.node.kind(),
-                            c.node.start_position().row,
-                        )
-                    })
-                    .collect::<Vec<_>>()
-            })
             .collect::<Vec<_>>();
-
+        assert_eq!(matches.len(), 1);
         assert_eq!(
-            match_capture_names_and_rows,
-            &[
-                vec![("stmt", "for_statement", 0), ("block", "block", 1)],
+            format_captures(matches[0].captures.iter().cloned(), &query, source),
+            &[("value", "a"), ("key", "b")]
+        );
+        assert_eq!(
+            matches[0]
+                .captures_by_index()
+                .map(|(index, node)| (
+                    query.capture_names()[index as usize].as_str(),
+                    node.utf8_text(source.as_bytes()).unwrap()
+                ))
+                .collect::<Vec<_>>(),
+            &[("key", "b"), ("value", "a")]
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_nodes_for_quantified_capture_index() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(language, "(array (identifier)+ @element) @array").unwrap();
+
+        let source = "[a, b, c];";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let element_ix = query.capture_index_for_name("element").unwrap();
+        let array_ix = query.capture_index_for_name("array").unwrap();
+        let matches = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            matches[0]
+                .nodes_for_capture_index(element_ix)
+                .map(|node| node.utf8_text(source.as_bytes()).unwrap())
+                .collect::<Vec<_>>(),
+            &["a", "b", "c"]
+        );
+        assert_eq!(
+            matches[0]
+                .nodes_for_capture_index(array_ix)
+                .map(|node| node.utf8_text(source.as_bytes()).unwrap())
+                .collect::<Vec<_>>(),
+            &["[a, b, c]"]
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_capture_count() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(language, "(array (identifier)+ @element) @array").unwrap();
+
+        let source = "[a, b, c]; [d]; [e, f];";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let element_ix = query.capture_index_for_name("element").unwrap();
+        let array_ix = query.capture_index_for_name("array").unwrap();
+        let counts = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .map(|m| (m.capture_count(array_ix), m.capture_count(element_ix)))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, &[(1, 3), (1, 1), (1, 2)]);
+    });
+}
+
+#[test]
+fn test_query_matches_with_diagnostics() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            ((identifier) @name
+              (#not-eq? @name "a")
+              (#eq? @name "b"))
+            "#,
+        )
+        .unwrap();
+
+        let source = "a; b; c;";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let results = cursor
+            .matches_with_diagnostics(&query, tree.root_node(), source.as_bytes())
+            .map(|(m, failure)| {
+                (
+                    m.captures[0].node.utf8_text(source.as_bytes()).unwrap(),
+                    failure,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            &[
+                (
+                    "a",
+                    Some(QueryPredicateFailure {
+                        predicate_index: 0,
+                        operator: "not-eq?".to_string().into_boxed_str(),
+                        args: vec![
+                            QueryPredicateArg::Capture(0),
+                            QueryPredicateArg::String("a".to_string().into_boxed_str()),
+                        ],
+                    })
+                ),
+                ("b", None),
+                (
+                    "c",
+                    Some(QueryPredicateFailure {
+                        predicate_index: 1,
+                        operator: "eq?".to_string().into_boxed_str(),
+                        args: vec![
+                            QueryPredicateArg::Capture(0),
+                            QueryPredicateArg::String("b".to_string().into_boxed_str()),
+                        ],
+                    })
+                ),
+            ]
+        );
+
+        // Only the accepted match is returned by `matches`.
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[(0, vec![("name", "b")])]
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_with_diagnostics_report_predicates_as_written() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            ((identifier) @name
+              (#set! kind "name")
+              (#match-full? @name "[ab]")
+              (#has-parent? @name arguments expression_statement))
+            "#,
+        )
+        .unwrap();
+
+        let source = "a; foo; b.c;";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let results = cursor
+            .matches_with_diagnostics(&query, tree.root_node(), source.as_bytes())
+            .map(|(m, failure)| {
+                (
+                    m.captures[0].node.utf8_text(source.as_bytes()).unwrap(),
+                    failure,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            &[
+                ("a", None),
+                (
+                    "foo",
+                    Some(QueryPredicateFailure {
+                        predicate_index: 0,
+                        operator: "match-full?".to_string().into_boxed_str(),
+                        args: vec![
+                            QueryPredicateArg::Capture(0),
+                            QueryPredicateArg::String("[ab]".to_string().into_boxed_str()),
+                        ],
+                    })
+                ),
+                (
+                    "b",
+                    Some(QueryPredicateFailure {
+                        predicate_index: 1,
+                        operator: "has-parent?".to_string().into_boxed_str(),
+                        args: vec![
+                            QueryPredicateArg::Capture(0),
+                            QueryPredicateArg::String("arguments".to_string().into_boxed_str()),
+                            QueryPredicateArg::String(
+                                "expression_statement".to_string().into_boxed_str()
+                            ),
+                        ],
+                    })
+                ),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_with_chunked_text_provider() {
+    // A text that is stored in pieces, like a rope. The text of a node is provided as
+    // the parts of each piece that the node overlaps.
+    struct Pieces(Vec<&'static str>);
+
+    impl<'a> TextProvider<'a> for &'a Pieces {
+        type I = vec::IntoIter<&'a [u8]>;
+
+        fn text(&mut self, node: Node) -> Self::I {
+            let range = node.byte_range();
+            let mut chunks = Vec::new();
+            let mut piece_start = 0;
+            for piece in &self.0 {
+                let piece_end = piece_start + piece.len();
+                let start = range.start.max(piece_start);
+                let end = range.end.min(piece_end);
+                if start < end {
+                    chunks.push(&piece.as_bytes()[(start - piece_start)..(end - piece_start)]);
+                }
+                piece_start = piece_end;
+            }
+            chunks.into_iter()
+        }
+    }
+
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            ((identifier) @constant (#match? @constant "^[A-Z_]+$"))
+            ((assignment_expression left: (identifier) @left right: (identifier) @right)
+              (#eq? @left @right))
+            "#,
+        )
+        .unwrap();
+
+        let pieces = Pieces(vec!["ab", "c = a", "bc; D", "EF = ghi;"]);
+        let source = pieces.0.concat();
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(&source, None).unwrap();
+
+        let matches = cursor.matches(&query, tree.root_node(), &pieces);
+        assert_eq!(
+            collect_matches(matches, &query, source.as_str()),
+            &[
+                (1, vec![("left", "abc"), ("right", "abc")]),
+                (0, vec![("constant", "DEF")]),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_in_pattern_order() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (number) @num
+            (string) @str
+            (identifier) @id
+            "#,
+        )
+        .unwrap();
+
+        let source = "f(a, 1, 'x', b, 2, 'y');";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        assert_eq!(cursor.match_order(), MatchOrder::Document);
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[
+                (2, vec![("id", "f")]),
+                (2, vec![("id", "a")]),
+                (0, vec![("num", "1")]),
+                (1, vec![("str", "'x'")]),
+                (2, vec![("id", "b")]),
+                (0, vec![("num", "2")]),
+                (1, vec![("str", "'y'")]),
+            ]
+        );
+
+        cursor.set_match_order(MatchOrder::Pattern);
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[
+                (0, vec![("num", "1")]),
+                (0, vec![("num", "2")]),
+                (1, vec![("str", "'x'")]),
+                (1, vec![("str", "'y'")]),
+                (2, vec![("id", "f")]),
+                (2, vec![("id", "a")]),
+                (2, vec![("id", "b")]),
+            ]
+        );
+
+        // Finding a match always searches in document order.
+        let first_match = cursor
+            .find_match(&query, tree.root_node(), source.as_bytes(), |_| true)
+            .unwrap();
+        assert_eq!(first_match.pattern_index, 2);
+        assert_eq!(
+            first_match.captures[0]
+                .node
+                .utf8_text(source.as_bytes())
+                .unwrap(),
+            "f"
+        );
+    });
+}
+
+#[test]
+fn test_query_match_capture_tree() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (call_expression
+              function: (member_expression
+                object: (identifier) @object
+                property: (property_identifier) @property) @callee
+              arguments: (arguments (identifier) @arg)) @call
+            "#,
+        )
+        .unwrap();
+
+        let source = "a.b(c);";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        fn format_capture_tree(query: &Query, nodes: &[CaptureTreeNode], output: &mut String) {
+            for (i, node) in nodes.iter().enumerate() {
+                if i > 0 {
+                    output.push(' ');
+                }
+                output.push_str(&query.capture_names()[node.capture.index as usize]);
+                if !node.children.is_empty() {
+                    output.push('(');
+                    format_capture_tree(query, &node.children, output);
+                    output.push(')');
+                }
+            }
+        }
+
+        let matches = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .map(|m| {
+                let mut output = String::new();
+                format_capture_tree(&query, &m.capture_tree().roots, &mut output);
+                output
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(matches, &["call(callee(object property) arg)"]);
+    });
+}
+
+#[test]
+fn test_query_match_root_node() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (call_expression
+              function: (member_expression
+                object: (identifier) @object) @callee
+              arguments: (arguments) @args) @call
+            (expression_statement (call_expression (arguments (identifier) @arg)))
+            (expression_statement (parenthesized_expression (_) @inner) @outer)
+            ((identifier) @first . (identifier) @second)
+            "#,
+        )
+        .unwrap();
+
+        let source = "a.b(c); (d.e()); f(x, y);";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let mut roots = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .map(|m| {
+                let root = m.root_node();
+                (m.pattern_index, root.utf8_text(source.as_bytes()).unwrap())
+            })
+            .collect::<Vec<_>>();
+        roots.sort();
+        assert_eq!(
+            roots,
+            &[
+                (0, "a.b(c)"),
+                (0, "d.e()"),
+                (1, "a.b(c);"),
+                (1, "f(x, y);"),
+                (1, "f(x, y);"),
+                (2, "(d.e());"),
+                (3, "x")
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_query_skipping_captures_that_span_included_range_gaps() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (call_expression) @call
+            (arguments) @args
+            (number) @num
+            (identifier) @id
+            "#,
+        )
+        .unwrap();
+
+        let source = "foo(1, xxxx 2);";
+        let gap_start = source.find("xxxx").unwrap();
+        let gap_end = source.find("2").unwrap();
+        let included_ranges = [
+            Range {
+                start_byte: 0,
+                end_byte: gap_start,
+                start_point: Point::new(0, 0),
+                end_point: Point::new(0, gap_start),
+            },
+            Range {
+                start_byte: gap_end,
+                end_byte: source.len(),
+                start_point: Point::new(0, gap_end),
+                end_point: Point::new(0, source.len()),
+            },
+        ];
+
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        parser.set_included_ranges(&included_ranges).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        assert_eq!(tree.included_ranges(), &included_ranges);
+
+        // By default, nodes that span the gap are captured.
+        let mut cursor = QueryCursor::new();
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[
+                ("call", "foo(1, xxxx 2)"),
+                ("id", "foo"),
+                ("args", "(1, xxxx 2)"),
+                ("num", "1"),
+                ("num", "2"),
+            ]
+        );
+
+        cursor.set_skip_gap_captures(true);
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[("id", "foo"), ("num", "1"), ("num", "2")]
+        );
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[
+                (3, vec![("id", "foo")]),
+                (2, vec![("num", "1")]),
+                (2, vec![("num", "2")]),
+            ]
+        );
+
+        // A match is skipped as a whole if any of its captures spans the gap. When
+        // iterating over captures, the ones that were returned before the match was
+        // finished can't be taken back, but the rest of the match is skipped.
+        let call_query = Query::new(
+            language,
+            "(call_expression function: (identifier) @callee arguments: (arguments) @args)",
+        )
+        .unwrap();
+        let captures = cursor.captures(&call_query, tree.root_node(), source.as_bytes());
+        assert!(collect_captures(captures, &call_query, source)
+            .iter()
+            .all(|(name, _)| *name != "args"));
+        let matches =
+            cursor.matches_with_diagnostics(&call_query, tree.root_node(), source.as_bytes());
+        assert_eq!(matches.count(), 0);
+        let matches = cursor.matches_over(
+            &call_query,
+            iter::once((0, tree.root_node(), source.as_bytes())),
+        );
+        assert_eq!(matches.count(), 0);
+
+        cursor.set_skip_gap_captures(false);
+        let matches = cursor.matches_over(
+            &call_query,
+            iter::once((0, tree.root_node(), source.as_bytes())),
+        );
+        assert_eq!(matches.count(), 1);
+    });
+}
+
+#[test]
+fn test_query_captures_with_capture_allowlist() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (function_declaration name: (identifier) @function)
+            ((call_expression
+              function: (identifier) @callee
+              arguments: (arguments (number) @arg))
+             (#eq? @callee "b"))
+            (string) @string
+            "#,
+        )
+        .unwrap();
+
+        let source = "function a() { b(1, 'x'); c(2); }";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+
+        // The predicate on the `@callee` capture still applies when it is not allowed.
+        cursor.set_capture_allowlist(&["arg", "string", "nonexistent"]);
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[("arg", "1"), ("string", "'x'")]
+        );
+
+        // Matches are not restricted by the allowlist.
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[
+                (0, vec![("function", "a")]),
+                (1, vec![("callee", "b"), ("arg", "1")]),
+                (2, vec![("string", "'x'")]),
+            ]
+        );
+
+        cursor.clear_capture_allowlist();
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[
+                ("function", "a"),
+                ("callee", "b"),
+                ("arg", "1"),
+                ("string", "'x'"),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_query_cursor_find_match_stops_early() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(language, "(identifier) @id").unwrap();
+
+        let source = "a; b; c; d; e;";
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let mut call_count = 0;
+        let found = cursor
+            .find_match(&query, tree.root_node(), source.as_bytes(), |m| {
+                call_count += 1;
+                m.captures[0].node.utf8_text(source.as_bytes()).unwrap() == "b"
+            })
+            .unwrap();
+        assert_eq!(call_count, 2);
+        assert_eq!(found.pattern_index, 0);
+        assert_eq!(
+            format_captures(found.captures.into_iter(), &query, source),
+            &[("id", "b")]
+        );
+
+        let mut call_count = 0;
+        let found = cursor.find_match(&query, tree.root_node(), source.as_bytes(), |_| {
+            call_count += 1;
+            false
+        });
+        assert!(found.is_none());
+        assert_eq!(call_count, 5);
+
+        // The cursor stops searching once a match is found, so the later matches, which
+        // would exceed the match limit, are never computed.
+        let query = Query::new(
+            language,
+            "
+            (
+                (comment) @doc
+                ; not immediate
+                (class_declaration) @class
+            )
+
+            (call_expression function: (identifier) @function)
+            ",
+        )
+        .unwrap();
+        let source = format!("first(); {}", "/* hi */ a.b(); ".repeat(50));
+        let tree = parser.parse(&source, None).unwrap();
+        cursor.set_match_limit(32);
+
+        let found = cursor
+            .find_match(&query, tree.root_node(), source.as_bytes(), |_| true)
+            .unwrap();
+        assert_eq!(
+            format_captures(found.captures.into_iter(), &query, &source),
+            &[("function", "first")]
+        );
+        assert!(!cursor.did_exceed_match_limit());
+
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, &source),
+            &[(1, vec![("function", "first")])]
+        );
+        assert!(cursor.did_exceed_match_limit());
+    });
+}
+
+#[test]
+fn test_query_matches_over_multiple_trees() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(language, r#"((identifier) @id (#not-eq? @id "skip"))"#).unwrap();
+
+        let sources = ["a; b;", "skip; c;", "d;"];
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let trees = sources
+            .iter()
+            .map(|source| parser.parse(source, None).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut cursor = QueryCursor::new();
+        let matches = cursor
+            .matches_over(
+                &query,
+                trees
+                    .iter()
+                    .zip(sources.iter())
+                    .enumerate()
+                    .map(|(i, (tree, source))| (i, tree.root_node(), source.as_bytes())),
+            )
+            .map(|(tree_id, m)| {
+                let text = m.captures[0]
+                    .node
+                    .utf8_text(sources[tree_id].as_bytes())
+                    .unwrap();
+                (tree_id, text)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(matches, &[(0, "a"), (0, "b"), (1, "c"), (2, "d")]);
+    });
+}
+
+#[test]
+fn test_query_cursor_with_timeout() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(language, "(identifier) @id").unwrap();
+
+        let identifier_count = 50_000;
+        let source = "a;\n".repeat(identifier_count);
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(&source, None).unwrap();
+
+        let mut cursor = QueryCursor::new();
+        assert_eq!(cursor.timeout_micros(), 0);
+
+        // Execution halts early once the timeout has elapsed.
+        cursor.set_timeout_micros(1);
+        assert_eq!(cursor.timeout_micros(), 1);
+        let match_count = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert!(match_count < identifier_count);
+        assert!(cursor.timed_out());
+
+        let capture_count = cursor
+            .captures(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert!(capture_count < identifier_count);
+        assert!(cursor.timed_out());
+
+        // Without a timeout, all of the matches are found.
+        cursor.set_timeout_micros(0);
+        let match_count = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert_eq!(match_count, identifier_count);
+        assert!(!cursor.timed_out());
+    });
+}
+
+#[test]
+fn test_query_cursor_with_max_memory() {
+    allocations::record(|| {
+        let language = get_language("json");
+        let query = Query::new(language, "(array (number) @first (number) @second)").unwrap();
+
+        let source = format!(
+            "[{}]",
+            (0..300)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(&source, None).unwrap();
+
+        let mut cursor = QueryCursor::new();
+        assert_eq!(cursor.max_memory(), 0);
+        let match_count = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert_eq!(match_count, 300 * 299 / 2);
+        assert!(!cursor.did_exceed_max_memory());
+
+        // Execution halts early once the cursor's states need more memory than allowed.
+        let mut cursor = QueryCursor::new();
+        cursor.set_max_memory(4096);
+        assert_eq!(cursor.max_memory(), 4096);
+        let match_count = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert_eq!(match_count, 21);
+        assert!(cursor.did_exceed_max_memory());
+
+        // Memory that the cursor retained from an earlier execution is reused without
+        // counting towards the limit.
+        let match_count = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert_eq!(match_count, 78);
+        assert!(cursor.did_exceed_max_memory());
+
+        // Without a limit, all of the matches are found again.
+        cursor.set_max_memory(0);
+        let match_count = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert_eq!(match_count, 300 * 299 / 2);
+        assert!(!cursor.did_exceed_max_memory());
+
+        // Once the cursor has retained enough memory, the limit is no longer reached.
+        cursor.set_max_memory(4096);
+        let match_count = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .count();
+        assert_eq!(match_count, 300 * 299 / 2);
+        assert!(!cursor.did_exceed_max_memory());
+    });
+}
+
+#[test]
+fn test_query_warnings() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (identifier) @id
+            ((identifier) @keyword (#eq? @keyword "if") (#eq? @keyword "else"))
+            ((identifier) @constant (#eq? @constant "foo") (#match? @constant "^[A-Z]"))
+            ((identifier) @constant (#eq? @constant "FOO") (#match? @constant "^[A-Z]"))
+            (identifier)   @id
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.warnings(),
+            &[
+                QueryWarning {
+                    pattern_index: 1,
+                    row: 2,
+                    message: "Capture @keyword can't both equal \"if\" and satisfy #eq?"
+                        .to_string(),
+                    kind: QueryWarningKind::Unsatisfiable,
+                },
+                QueryWarning {
+                    pattern_index: 2,
+                    row: 3,
+                    message: "Capture @constant can't both equal \"foo\" and satisfy #match?"
+                        .to_string(),
+                    kind: QueryWarningKind::Unsatisfiable,
+                },
+                QueryWarning {
+                    pattern_index: 4,
+                    row: 5,
+                    message: "Pattern is identical to pattern 0".to_string(),
+                    kind: QueryWarningKind::Duplicate,
+                },
+            ]
+        );
+
+        // The query can still be used.
+        let source = "FOO; bar;";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[
+                (0, vec![("id", "FOO")]),
+                (3, vec![("constant", "FOO")]),
+                (4, vec![("id", "FOO")]),
+                (0, vec![("id", "bar")]),
+                (4, vec![("id", "bar")]),
+            ]
+        );
+
+        // Patterns whose structure is more specific than that of another pattern only
+        // match a subset of its nodes.
+        let query = Query::new(
+            language,
+            r#"
+            (call_expression function: (identifier) @function)
+            (call_expression) @call
+            ((call_expression (arguments (string) @arg)) (#eq? @arg "'a'"))
+            ((call_expression) @call (#eq? @call "b()"))
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            query.warnings(),
+            &[
+                QueryWarning {
+                    pattern_index: 0,
+                    row: 1,
+                    message: "Pattern only matches a subset of pattern 1".to_string(),
+                    kind: QueryWarningKind::Subset,
+                },
+                QueryWarning {
+                    pattern_index: 2,
+                    row: 3,
+                    message: "Pattern only matches a subset of pattern 1".to_string(),
+                    kind: QueryWarningKind::Subset,
+                },
+            ]
+        );
+
+        // Queries without any problems have no warnings.
+        let query = Query::new(language, "(identifier) @id (number) @num").unwrap();
+        assert!(query.warnings().is_empty());
+    });
+}
+
+#[test]
+fn test_query_builder() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let builder = QueryBuilder::node("pair")
+            .child(
+                QueryBuilder::node("property_identifier")
+                    .field("key")
+                    .capture("key"),
+            )
+            .child(QueryBuilder::anonymous(":"))
+            .child(
+                QueryBuilder::node("arrow_function")
+                    .field("value")
+                    .child(QueryBuilder::wildcard().field("body").capture("body")),
+            );
+        assert_eq!(
+            builder.to_source(),
+            r#"(pair key: (property_identifier) @key ":" value: (arrow_function body: (_) @body))"#
+        );
+
+        let built_query = builder.build(language).unwrap();
+        let written_query = Query::new(
+            language,
+            r#"
+            (pair
+              key: (property_identifier) @key
+              ":"
+              value: (arrow_function body: (_) @body))
+            "#,
+        )
+        .unwrap();
+        assert_eq!(built_query.capture_names(), written_query.capture_names());
+
+        let source = "a = { b: () => c, d: function() {}, e: f => g };";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor1 = QueryCursor::new();
+        let mut cursor2 = QueryCursor::new();
+
+        let built_matches = collect_matches(
+            cursor1.matches(&built_query, tree.root_node(), source.as_bytes()),
+            &built_query,
+            source,
+        );
+        let written_matches = collect_matches(
+            cursor2.matches(&written_query, tree.root_node(), source.as_bytes()),
+            &written_query,
+            source,
+        );
+        assert_eq!(
+            built_matches,
+            &[
+                (0, vec![("key", "b"), ("body", "c")]),
+                (0, vec![("key", "e"), ("body", "g")]),
+            ]
+        );
+        assert_eq!(built_matches, written_matches);
+
+        // Anonymous node text is escaped.
+        assert_eq!(
+            QueryBuilder::anonymous("\"").capture("quote").to_source(),
+            r#""\"" @quote"#
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_with_captured_wildcard_at_root() {
+    allocations::record(|| {
+        let language = get_language("python");
+        let query = Query::new(
+            language,
+            "
+            ; captured wildcard at the root
+            (_ [
+                (except_clause (block) @block)
+                (finally_clause (block) @block)
+            ]) @stmt
+
+            [
+                (while_statement (block) @block)
+                (if_statement (block) @block)
+
+                ; captured wildcard at the root within an alternation
+                (_ [
+                    (else_clause (block) @block)
+                    (elif_clause (block) @block)
+                ])
+
+                (try_statement (block) @block)
+                (for_statement (block) @block)
+            ] @stmt
+            ",
+        )
+        .unwrap();
+
+        let source = "
+        for i in j:
+            while True:
+                if a:
+                    print b
+                elif c:
+                    print d
+                else:
+                    try:
+                        print f
+                    except:
+                        print g
+                    finally:
+                        print h
+            else:
+                print i
+        "
+        .trim();
+
+        let mut parser = Parser::new();
+        let mut cursor = QueryCursor::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(&source, None).unwrap();
+
+        let match_capture_names_and_rows = cursor
+            .matches(&query, tree.root_node(), source.as_bytes())
+            .map(|m| {
+                m.captures
+                    .iter()
+                    .map(|c| {
+                        (
+                            query.capture_names()[c.index as usize].as_str(),
+                            c.node.kind(),
+                            c.node.start_position().row,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            match_capture_names_and_rows,
+            &[
+                vec![("stmt", "for_statement", 0), ("block", "block", 1)],
                 vec![("stmt", "while_statement", 1), ("block", "block", 2)],
                 vec![("stmt", "if_statement", 2), ("block", "block", 3)],
                 vec![("stmt", "if_statement", 2), ("block", "block", 5)],
@@ -2607,132 +3918,466 @@ fn test_query_matches_with_indefinite_step_containing_no_captures() {
 }
 
 #[test]
-fn test_query_captures_basic() {
+fn test_query_captures_basic() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (pair
+              key: _ @method.def
+              (function
+                name: (identifier) @method.alias))
+
+            (variable_declarator
+              name: _ @function.def
+              value: (function
+                name: (identifier) @function.alias))
+
+            ":" @delimiter
+            "=" @operator
+            "#,
+        )
+        .unwrap();
+
+        let source = "
+          a({
+            bc: function de() {
+              const fg = function hi() {}
+            },
+            jk: function lm() {
+              const no = function pq() {}
+            },
+          });
+        ";
+
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(&source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[
+                (2, vec![("delimiter", ":")]),
+                (0, vec![("method.def", "bc"), ("method.alias", "de")]),
+                (3, vec![("operator", "=")]),
+                (1, vec![("function.def", "fg"), ("function.alias", "hi")]),
+                (2, vec![("delimiter", ":")]),
+                (0, vec![("method.def", "jk"), ("method.alias", "lm")]),
+                (3, vec![("operator", "=")]),
+                (1, vec![("function.def", "no"), ("function.alias", "pq")]),
+            ],
+        );
+
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[
+                ("method.def", "bc"),
+                ("delimiter", ":"),
+                ("method.alias", "de"),
+                ("function.def", "fg"),
+                ("operator", "="),
+                ("function.alias", "hi"),
+                ("method.def", "jk"),
+                ("delimiter", ":"),
+                ("method.alias", "lm"),
+                ("function.def", "no"),
+                ("operator", "="),
+                ("function.alias", "pq"),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_query_captures_with_full_and_partial_regex_matches() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            ((identifier) @partial
+             (#match? @partial "[a-z]+"))
+
+            ((identifier) @full
+             (#match-full? @full "[a-z]+"))
+
+            ((identifier) @not-full
+             (#not-match-full? @not-full "[a-z]+"))
+            "#,
+        )
+        .unwrap();
+
+        let source = "abc; raw_id; ABC;";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[
+                ("partial", "abc"),
+                ("full", "abc"),
+                ("partial", "raw_id"),
+                ("not-full", "raw_id"),
+                ("not-full", "ABC"),
+            ],
+        );
+
+        // Alternations are anchored as a whole.
+        let query = Query::new(
+            language,
+            r#"((identifier) @id (#match-full? @id "ab|xyz"))"#,
+        )
+        .unwrap();
+        let source = "ab; abc; xyz; wxyz;";
+        let tree = parser.parse(source, None).unwrap();
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[("id", "ab"), ("id", "xyz")],
+        );
+    });
+}
+
+#[test]
+fn test_query_captures_with_text_conditions() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            ((identifier) @constant
+             (#match? @constant "^[A-Z]{2,}$"))
+
+             ((identifier) @constructor
+              (#match? @constructor "^[A-Z]"))
+
+            ((identifier) @function.builtin
+             (#eq? @function.builtin "require"))
+
+            ((identifier) @variable
+             (#not-match? @variable "^(lambda|load)$"))
+            "#,
+        )
+        .unwrap();
+
+        let source = "
+          toad
+          load
+          panda
+          lambda
+          const ab = require('./ab');
+          new Cd(EF);
+        ";
+
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(&source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_captures(captures, &query, source),
+            &[
+                ("variable", "toad"),
+                ("variable", "panda"),
+                ("variable", "ab"),
+                ("function.builtin", "require"),
+                ("variable", "require"),
+                ("constructor", "Cd"),
+                ("variable", "Cd"),
+                ("constant", "EF"),
+                ("constructor", "EF"),
+                ("variable", "EF"),
+            ],
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_with_predicates_comparing_two_captures() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            ((assignment_expression
+                left: (identifier) @left
+                right: (identifier) @right)
+             (#eq? @left @right))
+
+            ((assignment_expression
+                left: (identifier) @left
+                right: (identifier) @right)
+             (#not-eq? @left @right))
+            "#,
+        )
+        .unwrap();
+
+        let source = "x = x; x = y; yy = y; y = y;";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[
+                (0, vec![("left", "x"), ("right", "x")]),
+                (1, vec![("left", "x"), ("right", "y")]),
+                (1, vec![("left", "yy"), ("right", "y")]),
+                (0, vec![("left", "y"), ("right", "y")]),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_query_captures_with_glob_predicates() {
     allocations::record(|| {
         let language = get_language("javascript");
         let query = Query::new(
             language,
             r#"
-            (pair
-              key: _ @method.def
-              (function
-                name: (identifier) @method.alias))
+            ((identifier) @getter
+             (#glob? @getter "get*"))
 
-            (variable_declarator
-              name: _ @function.def
-              value: (function
-                name: (identifier) @function.alias))
+            ((identifier) @short
+             (#glob? @short "a?c"))
 
-            ":" @delimiter
-            "=" @operator
+            ((identifier) @other
+             (#not-glob? @other "*_*"))
+
+            ((comment) @path
+             (#glob? @path "// src/*.rs"))
             "#,
         )
         .unwrap();
 
         let source = "
-          a({
-            bc: function de() {
-              const fg = function hi() {}
-            },
-            jk: function lm() {
-              const no = function pq() {}
-            },
-          });
+          getX;
+          get;
+          forget;
+          abc;
+          abbc;
+          ac;
+          a_c;
+          // src/lib.rs
+          // src/a/b.rs
         ";
 
         let mut parser = Parser::new();
         parser.set_language(language).unwrap();
-        let tree = parser.parse(&source, None).unwrap();
+        let tree = parser.parse(source, None).unwrap();
         let mut cursor = QueryCursor::new();
-        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
-
-        assert_eq!(
-            collect_matches(matches, &query, source),
-            &[
-                (2, vec![("delimiter", ":")]),
-                (0, vec![("method.def", "bc"), ("method.alias", "de")]),
-                (3, vec![("operator", "=")]),
-                (1, vec![("function.def", "fg"), ("function.alias", "hi")]),
-                (2, vec![("delimiter", ":")]),
-                (0, vec![("method.def", "jk"), ("method.alias", "lm")]),
-                (3, vec![("operator", "=")]),
-                (1, vec![("function.def", "no"), ("function.alias", "pq")]),
-            ],
-        );
 
         let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
         assert_eq!(
             collect_captures(captures, &query, source),
             &[
-                ("method.def", "bc"),
-                ("delimiter", ":"),
-                ("method.alias", "de"),
-                ("function.def", "fg"),
-                ("operator", "="),
-                ("function.alias", "hi"),
-                ("method.def", "jk"),
-                ("delimiter", ":"),
-                ("method.alias", "lm"),
-                ("function.def", "no"),
-                ("operator", "="),
-                ("function.alias", "pq"),
+                ("getter", "getX"),
+                ("other", "getX"),
+                ("getter", "get"),
+                ("other", "get"),
+                ("other", "forget"),
+                ("short", "abc"),
+                ("other", "abc"),
+                ("other", "abbc"),
+                ("other", "ac"),
+                ("short", "a_c"),
+                ("path", "// src/lib.rs"),
             ]
         );
+
+        // Globs with many wildcards don't take exponential time to reject a match.
+        let query = Query::new(
+            language,
+            r#"((identifier) @name (#glob? @name "*a*a*a*a*a*a*a*a*a*a*a*a*b"))"#,
+        )
+        .unwrap();
+        let source = "a".repeat(64) + ";";
+        let tree = parser.parse(&source, None).unwrap();
+        let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(captures.count(), 0);
     });
 }
 
 #[test]
-fn test_query_captures_with_text_conditions() {
+fn test_query_captures_with_case_insensitive_predicates() {
     allocations::record(|| {
         let language = get_language("javascript");
         let query = Query::new(
             language,
             r#"
-            ((identifier) @constant
-             (#match? @constant "^[A-Z]{2,}$"))
+            ((identifier) @select
+             (#eq-ignore-case? @select "select"))
 
-             ((identifier) @constructor
-              (#match? @constructor "^[A-Z]"))
+            ((identifier) @from
+             (#match-ignore-case? @from "^fr[o]m$"))
 
-            ((identifier) @function.builtin
-             (#eq? @function.builtin "require"))
+            ((identifier) @other
+             (#not-eq-ignore-case? @other "SELECT")
+             (#not-match-ignore-case? @other "^FROM$"))
 
-            ((identifier) @variable
-             (#not-match? @variable "^(lambda|load)$"))
+            (assignment_expression
+              left: (identifier) @left
+              right: (identifier) @right
+              (#eq-ignore-case? @left @right))
             "#,
         )
         .unwrap();
 
         let source = "
-          toad
-          load
-          panda
-          lambda
-          const ab = require('./ab');
-          new Cd(EF);
+          SELECT;
+          select;
+          SeLeCt;
+          selection;
+          FROM;
+          from;
+          x = X;
+          y = z;
         ";
 
         let mut parser = Parser::new();
         parser.set_language(language).unwrap();
-        let tree = parser.parse(&source, None).unwrap();
+        let tree = parser.parse(source, None).unwrap();
         let mut cursor = QueryCursor::new();
 
         let captures = cursor.captures(&query, tree.root_node(), source.as_bytes());
         assert_eq!(
             collect_captures(captures, &query, source),
             &[
-                ("variable", "toad"),
-                ("variable", "panda"),
-                ("variable", "ab"),
-                ("function.builtin", "require"),
-                ("variable", "require"),
-                ("constructor", "Cd"),
-                ("variable", "Cd"),
-                ("constant", "EF"),
-                ("constructor", "EF"),
-                ("variable", "EF"),
+                ("select", "SELECT"),
+                ("select", "select"),
+                ("select", "SeLeCt"),
+                ("other", "selection"),
+                ("from", "FROM"),
+                ("from", "from"),
+                ("other", "x"),
+                ("left", "x"),
+                ("other", "X"),
+                ("right", "X"),
+                ("other", "y"),
+                ("other", "z"),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_with_is_at_line_start_predicate() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let source = "a;\n  b;\nc; d;\n  /* e */ f;";
+
+        let query = Query::new(
+            language,
+            r#"((identifier) @name (#is-at-line-start? @name))"#,
+        )
+        .unwrap();
+        assert_query_matches(
+            language,
+            &query,
+            source,
+            &[(0, vec![("name", "a")]), (0, vec![("name", "c")])],
+        );
+
+        // Indented tokens are also accepted when whitespace is ignored, but not tokens
+        // that follow other tokens on the same line, including comments.
+        let query = Query::new(
+            language,
+            r#"((identifier) @name (#is-at-line-start? @name "ignore-whitespace"))"#,
+        )
+        .unwrap();
+        assert_query_matches(
+            language,
+            &query,
+            source,
+            &[
+                (0, vec![("name", "a")]),
+                (0, vec![("name", "b")]),
+                (0, vec![("name", "c")]),
+            ],
+        );
+
+        assert_eq!(
+            Query::new(
+                language,
+                r#"((identifier) @name (#is-at-line-start? @name "whitespace"))"#,
+            )
+            .unwrap_err()
+            .kind,
+            QueryErrorKind::Predicate
+        );
+    });
+}
+
+#[test]
+fn test_query_matches_with_has_ancestor_and_has_parent_predicates() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let source = "a; b(c); d(e(f));";
+
+        let query = Query::new(
+            language,
+            r#"((identifier) @name (#has-ancestor? @name call_expression))"#,
+        )
+        .unwrap();
+        assert_query_matches(
+            language,
+            &query,
+            source,
+            &[
+                (0, vec![("name", "b")]),
+                (0, vec![("name", "c")]),
+                (0, vec![("name", "d")]),
+                (0, vec![("name", "e")]),
+                (0, vec![("name", "f")]),
             ],
         );
+
+        // Only the immediate parent is checked, against any of the given kinds.
+        let query = Query::new(
+            language,
+            r#"((identifier) @name (#has-parent? @name arguments expression_statement))"#,
+        )
+        .unwrap();
+        assert_query_matches(
+            language,
+            &query,
+            source,
+            &[
+                (0, vec![("name", "a")]),
+                (0, vec![("name", "c")]),
+                (0, vec![("name", "f")]),
+            ],
+        );
+
+        assert_eq!(
+            Query::new(language, r#"((identifier) @name (#has-ancestor? @name))"#,)
+                .unwrap_err()
+                .kind,
+            QueryErrorKind::Predicate
+        );
+
+        // The node kinds must belong to the language.
+        let error = Query::new(
+            language,
+            r#"((identifier) @name (#has-parent? @name not_a_kind))"#,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind, QueryErrorKind::Predicate);
+        assert_eq!(
+            error.message,
+            "Invalid node kind \"not_a_kind\" in #has-parent? predicate."
+        );
     });
 }
 
@@ -3174,6 +4819,63 @@ fn test_query_captures_ordered_by_both_start_and_end_positions() {
     });
 }
 
+#[test]
+fn test_query_captures_sorted_across_patterns() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let query = Query::new(
+            language,
+            r#"
+            (function_declaration
+              name: (identifier) @function
+              body: (statement_block (return_statement (_) @return)))
+            (call_expression function: (identifier) @call)
+            (member_expression) @member
+            (number) @number
+            (string) @string
+            "#,
+        )
+        .unwrap();
+
+        let source = "
+          function a() { return b.c(1, 'x'); }
+          d(2, e.f);
+        ";
+
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+
+        let captures = cursor
+            .captures_sorted(&query, tree.root_node(), source.as_bytes())
+            .collect::<Vec<_>>();
+        for pair in captures.windows(2) {
+            assert!(pair[0].1.node.start_byte() <= pair[1].1.node.start_byte());
+        }
+        assert_eq!(
+            captures
+                .iter()
+                .map(|(pattern_index, capture)| (
+                    *pattern_index,
+                    query.capture_names()[capture.index as usize].as_str(),
+                    capture.node.utf8_text(source.as_bytes()).unwrap(),
+                ))
+                .collect::<Vec<_>>(),
+            &[
+                (0, "function", "a"),
+                (0, "return", "b.c(1, 'x')"),
+                (2, "member", "b.c"),
+                (3, "number", "1"),
+                (4, "string", "'x'"),
+                (1, "call", "d"),
+                (3, "number", "2"),
+                (2, "member", "e.f"),
+            ],
+        );
+    });
+}
+
 #[test]
 fn test_query_captures_with_matches_removed() {
     allocations::record(|| {
@@ -3449,6 +5151,60 @@ fn test_query_capture_names() {
     });
 }
 
+#[test]
+fn test_query_remap_capture() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let mut query = Query::new(
+            language,
+            r#"
+            (binary_expression operator: _ @keyword.operator)
+            ((identifier) @variable (#eq? @variable "b"))
+            "#,
+        )
+        .unwrap();
+
+        query.remap_capture("keyword.operator", "operator").unwrap();
+        query.remap_capture("not-a-capture", "other").unwrap();
+        assert_eq!(
+            query.capture_names(),
+            &["operator".to_string(), "variable".to_string()]
+        );
+        assert_eq!(query.capture_index_for_name("operator"), Some(0));
+        assert_eq!(query.capture_index_for_name("keyword.operator"), None);
+
+        let source = "a + b;";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[(0, vec![("operator", "+")]), (1, vec![("variable", "b")])]
+        );
+
+        // Captures can't be given the name of another capture.
+        assert_eq!(
+            query.remap_capture("variable", "operator"),
+            Err(CaptureNameConflictError("operator".to_string()))
+        );
+        assert_eq!(
+            query.capture_names(),
+            &["operator".to_string(), "variable".to_string()]
+        );
+        query.remap_capture("variable", "variable").unwrap();
+
+        // Captures can be disabled using their new names.
+        query.disable_capture("operator");
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[(0, vec![]), (1, vec![("variable", "b")])]
+        );
+    });
+}
+
 #[test]
 fn test_query_lifetime_is_separate_from_nodes_lifetime() {
     allocations::record(|| {
@@ -3583,6 +5339,42 @@ fn test_query_disable_pattern() {
     });
 }
 
+#[test]
+fn test_query_replace_source() {
+    allocations::record(|| {
+        let language = get_language("javascript");
+        let mut query = Query::new(language, "(identifier) @name").unwrap();
+
+        let source = "a + 1;";
+        let mut parser = Parser::new();
+        parser.set_language(language).unwrap();
+        let tree = parser.parse(source, None).unwrap();
+        let mut cursor = QueryCursor::new();
+
+        // The same cursor can be used with the reloaded query, which uses the new
+        // patterns and capture names.
+        query.replace_source("(number) @number").unwrap();
+        assert_eq!(query.capture_names(), &["number"]);
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[(0, vec![("number", "1")])],
+        );
+
+        // An invalid source leaves the query unchanged.
+        assert_eq!(
+            query.replace_source("(number").unwrap_err().kind,
+            QueryErrorKind::Syntax
+        );
+        assert_eq!(query.capture_names(), &["number"]);
+        let matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+        assert_eq!(
+            collect_matches(matches, &query, source),
+            &[(0, vec![("number", "1")])],
+        );
+    });
+}
+
 #[test]
 fn test_query_alternative_predicate_prefix() {
     allocations::record(|| {
@@ -2,7 +2,10 @@ use super::helpers::edits::invert_edit;
 use super::helpers::fixtures::get_language;
 use crate::parse::{perform_edit, Edit};
 use std::str;
-use tree_sitter::{InputEdit, Parser, Point, Range, Tree};
+use tree_sitter::{
+    EditError, InputEdit, Node, Parser, Point, ProfiledCursor, Range, Tree,
+    TreeDeserializationError, TreeHistory,
+};
 
 #[test]
 fn test_tree_edit() {
@@ -232,6 +235,132 @@ fn test_tree_edit() {
     }
 }
 
+#[test]
+fn test_tree_edit_marks_changed_nodes() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let mut source_code = b"a();\n\nb();\n\nc();\n".to_vec();
+    let mut tree = parser.parse(&source_code, None).unwrap();
+
+    // Rename the function called by the second statement.
+    perform_edit(
+        &mut tree,
+        &mut source_code,
+        &Edit {
+            position: 6,
+            deleted_length: 1,
+            inserted_text: b"bb".to_vec(),
+        },
+    );
+
+    // Only the nodes along the path to the edited identifier have changes,
+    // and unchanged subtrees can be skipped entirely.
+    let mut changed_nodes = Vec::new();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.has_changes() {
+            changed_nodes.push(node.kind());
+            if cursor.goto_first_child() {
+                continue;
+            }
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break;
+            }
+        }
+        if cursor.node() == tree.root_node() {
+            break;
+        }
+    }
+    assert_eq!(
+        changed_nodes,
+        &[
+            "program",
+            "expression_statement",
+            "call_expression",
+            "identifier"
+        ]
+    );
+
+    let statements = tree.root_node().children(&mut cursor).collect::<Vec<_>>();
+    assert!(!statements[0].has_changes());
+    assert!(statements[1].has_changes());
+    assert!(!statements[2].has_changes());
+    assert_eq!(statements[1].utf8_text(&source_code).unwrap(), "bb();");
+}
+
+#[test]
+fn test_tree_try_edit() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let mut tree = parser.parse("a;\nb;", None).unwrap();
+    let original_sexp = tree.root_node().to_sexp();
+
+    // An edit that starts after it ends.
+    assert_eq!(
+        tree.try_edit(&InputEdit {
+            start_byte: 2,
+            old_end_byte: 1,
+            new_end_byte: 2,
+            start_position: Point::new(0, 2),
+            old_end_position: Point::new(0, 1),
+            new_end_position: Point::new(0, 2),
+        }),
+        Err(EditError::InvalidRange)
+    );
+
+    // An edit that extends beyond the end of the tree, in terms of bytes.
+    let out_of_bounds = Err(EditError::OutOfBounds {
+        end_byte: 5,
+        end_position: Point::new(1, 2),
+    });
+    assert_eq!(
+        tree.try_edit(&InputEdit {
+            start_byte: 4,
+            old_end_byte: 10,
+            new_end_byte: 4,
+            start_position: Point::new(1, 1),
+            old_end_position: Point::new(1, 2),
+            new_end_position: Point::new(1, 1),
+        }),
+        out_of_bounds
+    );
+
+    // An edit that extends beyond the end of the tree, in terms of rows and columns.
+    assert_eq!(
+        tree.try_edit(&InputEdit {
+            start_byte: 4,
+            old_end_byte: 5,
+            new_end_byte: 4,
+            start_position: Point::new(1, 1),
+            old_end_position: Point::new(2, 0),
+            new_end_position: Point::new(1, 1),
+        }),
+        out_of_bounds
+    );
+
+    // Invalid edits leave the tree unchanged.
+    assert!(!tree.root_node().has_changes());
+    assert_eq!(tree.root_node().to_sexp(), original_sexp);
+
+    // A valid edit that appends text at the end of the tree.
+    assert_eq!(
+        tree.try_edit(&InputEdit {
+            start_byte: 5,
+            old_end_byte: 5,
+            new_end_byte: 8,
+            start_position: Point::new(1, 2),
+            old_end_position: Point::new(1, 2),
+            new_end_position: Point::new(2, 2),
+        }),
+        Ok(())
+    );
+    assert!(tree.root_node().has_changes());
+    assert_eq!(tree.root_node().end_byte(), 8);
+}
+
 #[test]
 fn test_tree_cursor() {
     let mut parser = Parser::new();
@@ -268,6 +397,92 @@ fn test_tree_cursor() {
     assert_eq!(cursor.node().is_named(), true);
 }
 
+#[test]
+fn test_tree_cursor_clone() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("rust")).unwrap();
+    let tree = parser.parse("struct Stuff { a: A }", None).unwrap();
+
+    let mut cursor = tree.walk();
+    assert!(cursor.goto_first_child());
+    assert!(cursor.goto_first_child());
+    assert_eq!(cursor.node().kind(), "struct");
+
+    // Advancing the copy does not affect the original.
+    let mut copy = cursor.clone();
+    assert!(copy.goto_next_sibling());
+    assert!(copy.goto_next_sibling());
+    assert_eq!(copy.node().kind(), "field_declaration_list");
+    assert_eq!(cursor.node().kind(), "struct");
+
+    // The copy preserves the path back to the root.
+    assert!(copy.goto_parent());
+    assert_eq!(copy.node().kind(), "struct_item");
+    assert!(copy.goto_parent());
+    assert_eq!(copy.node().kind(), "source_file");
+    assert!(!copy.goto_parent());
+
+    // The original can still move independently, even after the copy is dropped.
+    drop(copy);
+    assert!(cursor.goto_next_sibling());
+    assert_eq!(cursor.node().kind(), "type_identifier");
+    assert!(cursor.goto_parent());
+    assert_eq!(cursor.node().kind(), "struct_item");
+}
+
+#[test]
+fn test_profiled_cursor() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("rust")).unwrap();
+    let tree = parser.parse("struct Stuff { a: A, b: B }", None).unwrap();
+
+    let mut cursor = ProfiledCursor::new(tree.root_node());
+    'walk: loop {
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+
+    let summary = cursor.summary();
+    let mut visits = summary
+        .iter()
+        .map(|(kind, stats)| (*kind, stats.visits))
+        .collect::<Vec<_>>();
+    visits.sort_unstable();
+    assert_eq!(
+        visits,
+        &[
+            (",", 1),
+            (":", 2),
+            ("field_declaration", 2),
+            ("field_declaration_list", 1),
+            ("field_identifier", 2),
+            ("source_file", 1),
+            ("struct", 1),
+            ("struct_item", 1),
+            ("type_identifier", 3),
+            ("{", 1),
+            ("}", 1),
+        ]
+    );
+
+    // The root node is on the cursor's path for the whole walk.
+    let root_duration = summary
+        .iter()
+        .find(|(kind, _)| *kind == "source_file")
+        .unwrap()
+        .1
+        .duration;
+    assert!(summary
+        .iter()
+        .all(|(_, stats)| stats.duration <= root_duration));
+}
+
 #[test]
 fn test_tree_cursor_fields() {
     let mut parser = Parser::new();
@@ -424,6 +639,29 @@ fn test_tree_node_equality() {
     assert_ne!(node1.child(0).unwrap(), node2);
 }
 
+#[test]
+fn test_tree_copy_shares_structure() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("rust")).unwrap();
+    let tree = parser.parse("struct A {}", None).unwrap();
+    let sexp = tree.root_node().to_sexp();
+
+    let tree_copy = tree.copy();
+    let tree_clone = tree.clone();
+    assert_eq!(tree_copy.root_node().id(), tree.root_node().id());
+    assert_eq!(tree_clone.root_node().id(), tree.root_node().id());
+    assert_eq!(
+        tree_copy.root_node().child(0).unwrap().id(),
+        tree.root_node().child(0).unwrap().id()
+    );
+
+    // Each copy remains usable after the others are dropped.
+    drop(tree);
+    assert_eq!(tree_copy.root_node().to_sexp(), sexp);
+    drop(tree_copy);
+    assert_eq!(tree_clone.root_node().to_sexp(), sexp);
+}
+
 #[test]
 fn test_get_changed_ranges() {
     let source_code = b"{a: null};\n".to_vec();
@@ -531,6 +769,98 @@ fn test_get_changed_ranges() {
     }
 }
 
+#[test]
+fn test_tree_history_undo_and_redo() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    let mut source = b"a;".to_vec();
+    let tree = parser.parse(&source, None).unwrap();
+    let mut history = TreeHistory::new(tree, 3);
+    let mut sexps = vec![history.current().root_node().to_sexp()];
+    assert!(history.undo().is_none());
+
+    // Record a snapshot after each edit.
+    for (position, text) in [(2, " b;"), (5, " c();"), (10, " d;")].iter() {
+        let mut tree = history.current().clone();
+        let edit = perform_edit(
+            &mut tree,
+            &mut source,
+            &Edit {
+                position: *position,
+                deleted_length: 0,
+                inserted_text: text.as_bytes().to_vec(),
+            },
+        );
+        let tree = parser.parse(&source, Some(&tree)).unwrap();
+        sexps.push(tree.root_node().to_sexp());
+        history.push(edit, tree);
+        assert_eq!(history.current_edit(), Some(&edit));
+    }
+    assert!(history.redo().is_none());
+
+    // Undoing restores the previous trees, but the oldest one has been discarded.
+    assert_eq!(history.undo().unwrap().root_node().to_sexp(), sexps[2]);
+    assert_eq!(history.undo().unwrap().root_node().to_sexp(), sexps[1]);
+    assert!(history.undo().is_none());
+    assert_eq!(history.current().root_node().end_byte(), 5);
+    assert_eq!(history.current_edit(), None);
+
+    // Redoing moves forward again.
+    assert_eq!(history.redo().unwrap().root_node().to_sexp(), sexps[2]);
+
+    // Recording a new snapshot discards the snapshots that could have been redone.
+    let mut tree = history.current().clone();
+    let edit = InputEdit {
+        start_byte: 0,
+        old_end_byte: 0,
+        new_end_byte: 0,
+        start_position: Point::new(0, 0),
+        old_end_position: Point::new(0, 0),
+        new_end_position: Point::new(0, 0),
+    };
+    tree.edit(&edit);
+    history.push(edit, tree.clone());
+    assert!(history.redo().is_none());
+    assert_eq!(history.undo().unwrap().root_node().to_sexp(), sexps[2]);
+
+    // With a capacity of one, only the current snapshot is kept, with no edit to undo.
+    let mut history = TreeHistory::new(tree.clone(), 1);
+    history.push(edit, tree);
+    assert_eq!(history.current_edit(), None);
+    assert!(history.undo().is_none());
+}
+
+#[test]
+fn test_tree_print_changes() {
+    let mut source_code = b"{a: null};\n".to_vec();
+
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let mut tree = parser.parse(&source_code, None).unwrap();
+
+    // Replace `null` with `nothing`
+    let edit = Edit {
+        position: index_of(&source_code, "ull"),
+        deleted_length: 3,
+        inserted_text: b"othing".to_vec(),
+    };
+    perform_edit(&mut tree, &mut source_code, &edit);
+    let new_tree = parser.parse(&source_code, Some(&tree)).unwrap();
+
+    let mut output = Vec::new();
+    tree.print_changes(&new_tree, &mut output).unwrap();
+    assert_eq!(
+        str::from_utf8(&output).unwrap(),
+        "(0, 4) - (0, 11): null -> identifier\n"
+    );
+
+    // Comparing a tree to itself produces no output.
+    let mut output = Vec::new();
+    new_tree.print_changes(&new_tree, &mut output).unwrap();
+    assert!(output.is_empty());
+}
+
 fn index_of(text: &Vec<u8>, substring: &str) -> usize {
     str::from_utf8(text.as_slice())
         .unwrap()
@@ -561,3 +891,315 @@ fn get_changed_ranges(
     *tree = new_tree;
     result
 }
+
+#[test]
+fn test_tree_reuse_stats() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    let mut source = (0..50)
+        .map(|i| format!("a{}();\n", i))
+        .collect::<String>()
+        .into_bytes();
+    let mut tree = parser.parse(&source, None).unwrap();
+
+    // Reparsing without any edits reuses everything.
+    let same_tree = parser.parse(&source, Some(&tree)).unwrap();
+    let stats = same_tree.reuse_stats(&tree);
+    assert_eq!(stats.reparsed_bytes, 0);
+    assert_eq!(stats.reused_bytes, same_tree.root_node().end_byte());
+    assert_eq!(stats.reuse_ratio(), 1.0);
+
+    // A one-character edit only changes the statement that contains it.
+    let position = source.len() / 2;
+    let position = position + source[position..].iter().position(|b| *b == b'a').unwrap();
+    perform_edit(
+        &mut tree,
+        &mut source,
+        &Edit {
+            position,
+            deleted_length: 0,
+            inserted_text: b"!".to_vec(),
+        },
+    );
+    let new_tree = parser.parse(&source, Some(&tree)).unwrap();
+    let stats = new_tree.reuse_stats(&tree);
+    assert!(stats.reparsed_bytes > 0);
+    assert_eq!(
+        stats.reused_bytes + stats.reparsed_bytes,
+        new_tree.root_node().end_byte()
+    );
+    assert!(stats.reuse_ratio() > 0.9);
+}
+
+#[test]
+fn test_tree_preorder() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let tree = parser
+        .parse("function a(b) { return b + 1; }\nc(d);", None)
+        .unwrap();
+
+    fn collect_nodes<'a>(node: Node<'a>, depth: u32, result: &mut Vec<(Node<'a>, u32)>) {
+        result.push((node, depth));
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            collect_nodes(child, depth + 1, result);
+        }
+    }
+
+    let mut expected = Vec::new();
+    collect_nodes(tree.root_node(), 0, &mut expected);
+    let actual = tree.preorder().collect::<Vec<_>>();
+    assert_eq!(actual, expected);
+
+    assert_eq!(actual[0], (tree.root_node(), 0));
+    assert_eq!(
+        actual
+            .iter()
+            .map(|(node, depth)| format!("{}{}", "  ".repeat(*depth as usize), node.kind()))
+            .take(5)
+            .collect::<Vec<_>>(),
+        &[
+            "program",
+            "  function_declaration",
+            "    function",
+            "    identifier",
+            "    formal_parameters",
+        ]
+    );
+}
+
+#[test]
+fn test_tree_extras() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let source = "
+// The first comment
+function a(/* param */ b) {
+  return b; // trailing
+}
+/** Documentation */
+c();
+";
+    let tree = parser.parse(source, None).unwrap();
+
+    let extras = tree
+        .extras()
+        .map(|node| (node.kind(), node.utf8_text(source.as_bytes()).unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        extras,
+        &[
+            ("comment", "// The first comment"),
+            ("comment", "/* param */"),
+            ("comment", "// trailing"),
+            ("comment", "/** Documentation */"),
+        ]
+    );
+
+    let tree = parser.parse("a(b);", None).unwrap();
+    assert_eq!(tree.extras().count(), 0);
+}
+
+#[test]
+fn test_tree_nodes_on_line() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let source = "function a() {\n  b(c);\n}\nd;\n";
+    let tree = parser.parse(source, None).unwrap();
+
+    let kinds_on_line = |row| {
+        tree.nodes_on_line(row)
+            .iter()
+            .map(|node| node.kind())
+            .collect::<Vec<_>>()
+    };
+
+    // Nodes that span several lines are included on the line where they start.
+    assert_eq!(
+        kinds_on_line(0),
+        &[
+            "program",
+            "function_declaration",
+            "identifier",
+            "formal_parameters",
+            "statement_block",
+        ]
+    );
+    assert_eq!(
+        kinds_on_line(1),
+        &[
+            "expression_statement",
+            "call_expression",
+            "identifier",
+            "arguments",
+            "identifier",
+        ]
+    );
+
+    // The closing brace is anonymous.
+    assert_eq!(kinds_on_line(2), Vec::<&str>::new());
+    assert_eq!(kinds_on_line(3), &["expression_statement", "identifier"]);
+    assert_eq!(kinds_on_line(4), Vec::<&str>::new());
+}
+
+#[test]
+fn test_tree_edit_inverse() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+
+    let mut source = b"a(b);\nc(d);\n".to_vec();
+    let original_source = source.clone();
+    let original_tree = parser.parse(&source, None).unwrap();
+    let mut tree = original_tree.clone();
+
+    // Replace `b` with a multi-line argument list.
+    let edit = perform_edit(
+        &mut tree,
+        &mut source,
+        &Edit {
+            position: 2,
+            deleted_length: 1,
+            inserted_text: b"x,\ny".to_vec(),
+        },
+    );
+    let inverse = edit.inverse();
+    assert_eq!(inverse.start_byte, 2);
+    assert_eq!(inverse.old_end_byte, 6);
+    assert_eq!(inverse.new_end_byte, 3);
+    assert_eq!(inverse.old_end_position, Point::new(1, 1));
+    assert_eq!(inverse.new_end_position, Point::new(0, 3));
+    assert_eq!(inverse.inverse(), edit);
+
+    // Applying the inverse restores the original coordinates.
+    let mut tree = parser.parse(&source, Some(&tree)).unwrap();
+    assert_eq!(
+        tree.root_node().child(1).unwrap().start_position(),
+        Point::new(2, 0)
+    );
+    tree.edit(&inverse);
+    assert_eq!(
+        tree.root_node().child(1).unwrap().range(),
+        original_tree.root_node().child(1).unwrap().range()
+    );
+    let tree = parser.parse(&original_source, Some(&tree)).unwrap();
+    assert_eq!(
+        tree.root_node().to_sexp(),
+        original_tree.root_node().to_sexp()
+    );
+    assert_eq!(tree.root_node().range(), original_tree.root_node().range());
+}
+
+#[test]
+fn test_range_intersect_and_union() {
+    // Ranges within the text "one\ntwo\nthree", identified by their start and end bytes.
+    fn range(start_byte: usize, end_byte: usize) -> Range {
+        let point = |byte: usize| match byte {
+            0..=3 => Point::new(0, byte),
+            4..=7 => Point::new(1, byte - 4),
+            _ => Point::new(2, byte - 8),
+        };
+        Range {
+            start_byte,
+            end_byte,
+            start_point: point(start_byte),
+            end_point: point(end_byte),
+        }
+    }
+
+    // Overlapping
+    assert_eq!(range(1, 6).intersect(&range(4, 10)), Some(range(4, 6)));
+    assert_eq!(range(4, 10).intersect(&range(1, 6)), Some(range(4, 6)));
+    assert_eq!(range(1, 6).union(&range(4, 10)), range(1, 10));
+    assert_eq!(range(4, 10).union(&range(1, 6)), range(1, 10));
+
+    // Nested
+    assert_eq!(range(0, 13).intersect(&range(5, 7)), Some(range(5, 7)));
+    assert_eq!(range(0, 13).union(&range(5, 7)), range(0, 13));
+
+    // Adjacent
+    assert_eq!(range(0, 4).intersect(&range(4, 8)), None);
+    assert_eq!(range(0, 4).union(&range(4, 8)), range(0, 8));
+
+    // Disjoint
+    assert_eq!(range(0, 3).intersect(&range(8, 13)), None);
+    assert_eq!(range(8, 13).union(&range(0, 3)), range(0, 13));
+}
+
+#[test]
+fn test_tree_serialize_and_deserialize() {
+    let source = "const a = `b${c(d}`;\nif (e) { f(); }\n";
+
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let tree = parser.parse(source, None).unwrap();
+    assert!(tree.root_node().has_error());
+
+    let bytes = tree.serialize();
+    let restored = Tree::deserialize(&bytes, get_language("javascript")).unwrap();
+    assert_eq!(restored.root_node().to_sexp(), tree.root_node().to_sexp());
+    assert_eq!(restored.included_ranges(), tree.included_ranges());
+    assert_eq!(
+        restored
+            .preorder()
+            .map(|(node, depth)| (node.kind(), node.range(), node.is_missing(), depth))
+            .collect::<Vec<_>>(),
+        tree.preorder()
+            .map(|(node, depth)| (node.kind(), node.range(), node.is_missing(), depth))
+            .collect::<Vec<_>>(),
+    );
+
+    // The restored tree can be used for incremental parsing.
+    let mut restored = restored;
+    let mut source = source.as_bytes().to_vec();
+    let edit = Edit {
+        position: source.len() - 2,
+        deleted_length: 0,
+        inserted_text: b"g(); ".to_vec(),
+    };
+    perform_edit(&mut restored, &mut source, &edit);
+    let new_tree = parser.parse(&source, Some(&restored)).unwrap();
+    assert_eq!(
+        new_tree.root_node().to_sexp(),
+        parser.parse(&source, None).unwrap().root_node().to_sexp()
+    );
+
+    // Data that is truncated, or that belongs to a different language, is rejected.
+    assert_eq!(
+        Tree::deserialize(&bytes[0..bytes.len() - 1], get_language("javascript")).err(),
+        Some(TreeDeserializationError)
+    );
+    assert_eq!(
+        Tree::deserialize(&bytes, get_language("json")).err(),
+        Some(TreeDeserializationError)
+    );
+}
+
+#[test]
+fn test_tree_serialize_and_deserialize_empty_document() {
+    let mut parser = Parser::new();
+    parser.set_language(get_language("javascript")).unwrap();
+    let tree = parser.parse("", None).unwrap();
+    assert_eq!(tree.root_node().child_count(), 0);
+
+    // The root node has no children, but it is still restored as a node, not a token.
+    let bytes = tree.serialize();
+    let restored = Tree::deserialize(&bytes, get_language("javascript")).unwrap();
+    assert_eq!(restored.root_node().to_sexp(), "(program)");
+    assert_eq!(restored.root_node().child_count(), 0);
+
+    let mut restored = restored;
+    let mut source = Vec::new();
+    let edit = Edit {
+        position: 0,
+        deleted_length: 0,
+        inserted_text: b"a;".to_vec(),
+    };
+    perform_edit(&mut restored, &mut source, &edit);
+    let new_tree = parser.parse(&source, Some(&restored)).unwrap();
+    assert_eq!(
+        new_tree.root_node().to_sexp(),
+        "(program (expression_statement (identifier)))"
+    );
+}
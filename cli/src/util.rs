@@ -3,7 +3,7 @@ use std::io;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
-use tree_sitter::Parser;
+use tree_sitter::{Node, Parser};
 
 #[cfg(unix)]
 use anyhow::{anyhow, Context};
@@ -15,6 +15,8 @@ use std::process::{Child, ChildStdin, Command, Stdio};
 #[cfg(unix)]
 const HTML_HEADER: &[u8] = b"<!DOCTYPE html>\n<style>svg { width: 100%; }</style>\n\n";
 
+const DEFAULT_TAB_WIDTH: usize = 4;
+
 pub fn cancel_on_stdin() -> Arc<AtomicUsize> {
     let result = Arc::new(AtomicUsize::new(0));
     if atty::is(atty::Stream::Stdin) {
@@ -29,6 +31,78 @@ pub fn cancel_on_stdin() -> Arc<AtomicUsize> {
     }
     result
 }
+
+/// Format the source lines containing the given node, with the node's span underlined by
+/// carets. Tabs are expanded to tab stops every four columns.
+pub fn format_node_location(source: &[u8], node: Node) -> String {
+    format_node_location_with_tab_width(source, node, DEFAULT_TAB_WIDTH)
+}
+
+/// Format the source lines containing the given node, with the node's span underlined by
+/// carets. Tabs are expanded to tab stops every `tab_width` columns.
+pub fn format_node_location_with_tab_width(source: &[u8], node: Node, tab_width: usize) -> String {
+    let start = node.start_position();
+    let end = node.end_position();
+
+    // A node that ends at the start of a line doesn't include any of that line.
+    let last_row = if end.row > start.row && end.column == 0 {
+        end.row - 1
+    } else {
+        end.row
+    };
+
+    let mut result = String::new();
+    for (row, line) in source.split(|b| *b == b'\n').enumerate() {
+        if row < start.row {
+            continue;
+        }
+        if row > last_row {
+            break;
+        }
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let start_column = if row == start.row { start.column } else { 0 };
+        let end_column = if row == end.row {
+            end.column
+        } else {
+            line.len()
+        };
+        let start_column = start_column.min(line.len());
+        let end_column = end_column.clamp(start_column, line.len());
+
+        let (text, start_width) = expand_tabs(&line[..start_column], 0, tab_width);
+        let (span_text, span_width) =
+            expand_tabs(&line[start_column..end_column], start_width, tab_width);
+        let (rest_text, _) = expand_tabs(&line[end_column..], start_width + span_width, tab_width);
+
+        result.push_str(&text);
+        result.push_str(&span_text);
+        result.push_str(&rest_text);
+        result.push('\n');
+        result.push_str(&" ".repeat(start_width));
+        result.push_str(&"^".repeat(span_width.max(1)));
+        result.push('\n');
+    }
+    result
+}
+
+// Expand the tabs in the given text, which begins at the given display column. Returns the
+// expanded text and its width in columns.
+fn expand_tabs(text: &[u8], start_column: usize, tab_width: usize) -> (String, usize) {
+    let mut result = String::new();
+    let mut column = start_column;
+    for c in String::from_utf8_lossy(text).chars() {
+        if c == '\t' && tab_width > 0 {
+            let width = tab_width - column % tab_width;
+            result.push_str(&" ".repeat(width));
+            column += width;
+        } else {
+            result.push(c);
+            column += 1;
+        }
+    }
+    (result, column - start_column)
+}
+
 #[cfg(windows)]
 pub struct LogSession();
 
@@ -0,0 +1,153 @@
+use tree_sitter::{Language, Node, Point, Query, QueryCursor, QueryError};
+
+/// Computes indentation levels for lines of a document, using an indents query.
+///
+/// The query uses three captures:
+/// * `@indent` marks nodes whose contents are indented by one level relative to the
+///   line on which the node starts, such as blocks or object literals.
+/// * `@branch` marks nodes that return to the indentation of their enclosing node when
+///   they begin a line, such as closing braces or `else` keywords.
+/// * `@dedent` marks nodes after which the next line is indented by one less level,
+///   such as `return` statements.
+///
+/// Several `@indent` nodes that start on the same line only add one level between
+/// them, so that a line like `foo({` indents its contents by a single level.
+pub struct Indenter {
+    query: Query,
+    indent_capture_index: Option<u32>,
+    branch_capture_index: Option<u32>,
+    dedent_capture_index: Option<u32>,
+}
+
+impl Indenter {
+    /// Creates an `Indenter` for a given `Language` and indents query.
+    pub fn new(language: Language, indents_query: &str) -> Result<Self, QueryError> {
+        let query = Query::new(language, indents_query)?;
+
+        let mut indent_capture_index = None;
+        let mut branch_capture_index = None;
+        let mut dedent_capture_index = None;
+        for (i, name) in query.capture_names().iter().enumerate() {
+            let i = Some(i as u32);
+            match name.as_str() {
+                "indent" => indent_capture_index = i,
+                "branch" => branch_capture_index = i,
+                "dedent" => dedent_capture_index = i,
+                _ => {}
+            }
+        }
+
+        Ok(Indenter {
+            query,
+            indent_capture_index,
+            branch_capture_index,
+            dedent_capture_index,
+        })
+    }
+
+    /// Get the indents query used by this indenter.
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Compute the indentation level of an existing line of the document, given the
+    /// root node of its syntax tree.
+    ///
+    /// The level counts the lines on which the `@indent` nodes that contain the line
+    /// start, and is reduced by one if the line begins with a `@branch` node.
+    ///
+    /// This overwrites the point range of the given `QueryCursor`.
+    pub fn indent_level(
+        &self,
+        cursor: &mut QueryCursor,
+        root: Node,
+        source: &[u8],
+        row: usize,
+    ) -> usize {
+        let captures = self.captures_before_row(cursor, root, source, row + 1);
+        let level = indent_level_for_row(&captures, self.indent_capture_index, row);
+        let starts_with_branch = captures.iter().any(|(index, node)| {
+            Some(*index) == self.branch_capture_index && starts_line(*node, source, row)
+        });
+        if starts_with_branch {
+            level.saturating_sub(1)
+        } else {
+            level
+        }
+    }
+
+    /// Compute the indentation level for a new line inserted after the given line of
+    /// the document, given the root node of its syntax tree.
+    ///
+    /// The level counts the lines on which the `@indent` nodes that continue past the
+    /// given line start, and is reduced by one if a `@dedent` node ends on the given
+    /// line.
+    ///
+    /// This overwrites the point range of the given `QueryCursor`.
+    pub fn next_line_indent_level(
+        &self,
+        cursor: &mut QueryCursor,
+        root: Node,
+        source: &[u8],
+        row: usize,
+    ) -> usize {
+        let captures = self.captures_before_row(cursor, root, source, row + 1);
+        let level = indent_level_for_row(&captures, self.indent_capture_index, row + 1);
+        let ends_with_dedent = captures.iter().any(|(index, node)| {
+            Some(*index) == self.dedent_capture_index && node.end_position().row == row
+        });
+        if ends_with_dedent {
+            level.saturating_sub(1)
+        } else {
+            level
+        }
+    }
+
+    // Find all of the captured nodes that start before the given row.
+    fn captures_before_row<'tree>(
+        &self,
+        cursor: &mut QueryCursor,
+        root: Node<'tree>,
+        source: &[u8],
+        row: usize,
+    ) -> Vec<(u32, Node<'tree>)> {
+        cursor.set_point_range(Point::new(0, 0)..Point::new(row, 0));
+        cursor
+            .captures(&self.query, root, source)
+            .map(|(match_, capture_index)| {
+                let capture = match_.captures[capture_index];
+                (capture.index, capture.node)
+            })
+            .filter(|(_, node)| node.start_position().row < row)
+            .collect()
+    }
+}
+
+// Count the distinct rows on which the `@indent` nodes that contain the given row
+// start, ignoring any that start on the row itself.
+fn indent_level_for_row(captures: &[(u32, Node)], indent_index: Option<u32>, row: usize) -> usize {
+    let mut start_rows = captures
+        .iter()
+        .filter(|(index, node)| {
+            Some(*index) == indent_index
+                && node.start_position().row < row
+                && node.end_position().row >= row
+        })
+        .map(|(_, node)| node.start_position().row)
+        .collect::<Vec<_>>();
+    start_rows.sort_unstable();
+    start_rows.dedup();
+    start_rows.len()
+}
+
+// Check if the given node is the first non-whitespace text on the given row.
+fn starts_line(node: Node, source: &[u8], row: usize) -> bool {
+    let start = node.start_position();
+    if start.row != row {
+        return false;
+    }
+    let line_start = node.start_byte() - start.column;
+    source[line_start..node.start_byte()]
+        .iter()
+        .all(|c| c.is_ascii_whitespace())
+}
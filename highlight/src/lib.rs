@@ -1,18 +1,24 @@
 pub mod c_lib;
+pub mod indent;
+pub mod locals;
+pub mod textmate;
 pub mod util;
 pub use c_lib as c;
 
+use std::io::Write;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{iter, mem, ops, str, usize};
 use thiserror::Error;
 use tree_sitter::{
     Language, LossyUtf8, Node, Parser, Point, Query, QueryCaptures, QueryCursor, QueryError,
-    QueryMatch, Range, Tree,
+    QueryErrorKind, QueryMatch, Range, Tree,
 };
 
 const CANCELLATION_CHECK_INTERVAL: usize = 100;
+const DEFAULT_MAX_INJECTION_DEPTH: usize = 10;
 const BUFFER_HTML_RESERVE_CAPACITY: usize = 10 * 1024;
 const BUFFER_LINES_RESERVE_CAPACITY: usize = 1000;
+const LOCAL_DEF_ROLE_PREFIX: &str = "local.definition.";
 
 /// Indicates which highlight should be applied to a region of source code.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -29,6 +35,25 @@ pub enum Error {
     Unknown,
 }
 
+/// Identifies one of the query strings passed to [HighlightConfiguration::from_fragments],
+/// by its kind and its index within the list of queries of that kind.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QueryFragment {
+    Highlights(usize),
+    Injections(usize),
+    Locals(usize),
+}
+
+/// Represents an error in the queries passed to [HighlightConfiguration::from_fragments].
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueryFragmentError {
+    /// The query string that contains the error, or `None` if the error isn't associated
+    /// with any particular query string.
+    pub fragment: Option<QueryFragment>,
+    /// The error, with its position given relative to the start of its query string.
+    pub error: QueryError,
+}
+
 /// Represents a single step in rendering a syntax-highlighted document.
 #[derive(Copy, Clone, Debug)]
 pub enum HighlightEvent {
@@ -37,6 +62,15 @@ pub enum HighlightEvent {
     HighlightEnd,
 }
 
+/// Represents a single step in rendering a syntax-highlighted document, with each highlight
+/// identified by its name rather than by its index.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NamedHighlightEvent<'a> {
+    Source { start: usize, end: usize },
+    HighlightStart(&'a str),
+    HighlightEnd,
+}
+
 /// Contains the data needed to highlight code written in a particular language.
 ///
 /// This struct is immutable and can be shared between threads.
@@ -47,6 +81,7 @@ pub struct HighlightConfiguration {
     locals_pattern_index: usize,
     highlights_pattern_index: usize,
     highlight_indices: Vec<Option<Highlight>>,
+    highlight_names: Vec<String>,
     non_local_variable_patterns: Vec<bool>,
     injection_content_capture_index: Option<u32>,
     injection_language_capture_index: Option<u32>,
@@ -54,6 +89,8 @@ pub struct HighlightConfiguration {
     local_def_capture_index: Option<u32>,
     local_def_value_capture_index: Option<u32>,
     local_ref_capture_index: Option<u32>,
+    local_def_role_capture_indices: Vec<u32>,
+    local_def_role_highlights: Vec<Option<Highlight>>,
 }
 
 /// Performs syntax highlighting, recognizing a given list of highlight names.
@@ -64,6 +101,7 @@ pub struct HighlightConfiguration {
 pub struct Highlighter {
     parser: Parser,
     cursors: Vec<QueryCursor>,
+    max_injection_depth: usize,
 }
 
 /// Converts a general-purpose syntax highlighting iterator into a sequence of lines of HTML.
@@ -73,6 +111,20 @@ pub struct HtmlRenderer {
     carriage_return_highlight: Option<Highlight>,
 }
 
+/// Converts a general-purpose syntax highlighting iterator into text that is colored
+/// with ANSI escape codes, for display in a terminal.
+pub struct AnsiRenderer {
+    pub output: Vec<u8>,
+}
+
+/// A foreground color that can be used in a terminal, either from the 256-color palette
+/// or as a 24-bit RGB color.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AnsiColor {
+    Fixed(u8),
+    Rgb(u8, u8, u8),
+}
+
 #[derive(Debug)]
 struct LocalDef<'a> {
     name: &'a str,
@@ -118,6 +170,7 @@ impl Highlighter {
         Highlighter {
             parser: Parser::new(),
             cursors: Vec::new(),
+            max_injection_depth: DEFAULT_MAX_INJECTION_DEPTH,
         }
     }
 
@@ -125,20 +178,73 @@ impl Highlighter {
         &mut self.parser
     }
 
+    /// Get the maximum depth to which language injections will be nested.
+    pub fn max_injection_depth(&self) -> usize {
+        self.max_injection_depth
+    }
+
+    /// Set the maximum depth to which language injections will be nested.
+    ///
+    /// Injections that would be nested more deeply than this are not processed,
+    /// so their content is highlighted only by the enclosing layer. The default
+    /// depth is 10.
+    pub fn set_max_injection_depth(&mut self, max_injection_depth: usize) {
+        self.max_injection_depth = max_injection_depth;
+    }
+
     /// Iterate over the highlighted regions for a given slice of source code.
     pub fn highlight<'a>(
         &'a mut self,
         config: &'a HighlightConfiguration,
         source: &'a [u8],
         cancellation_flag: Option<&'a AtomicUsize>,
-        mut injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+        injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
     ) -> Result<impl Iterator<Item = Result<HighlightEvent, Error>> + 'a, Error> {
+        self.highlight_with_tree(config, None, source, cancellation_flag, injection_callback)
+    }
+
+    /// Iterate over the highlighted regions for a given slice of source code, using a
+    /// syntax tree that has already been parsed from it, like [Highlighter::highlight].
+    ///
+    /// This avoids parsing the source again when the caller keeps the tree up to date
+    /// itself, for example with incremental parsing. Language injections are still
+    /// parsed as they are found. The tree must have been produced by the language of
+    /// the given `config`, or else [Error::InvalidLanguage] is returned.
+    pub fn highlight_tree<'a>(
+        &'a mut self,
+        config: &'a HighlightConfiguration,
+        tree: &Tree,
+        source: &'a [u8],
+        cancellation_flag: Option<&'a AtomicUsize>,
+        injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+    ) -> Result<impl Iterator<Item = Result<HighlightEvent, Error>> + 'a, Error> {
+        if tree.language() != config.language {
+            return Err(Error::InvalidLanguage);
+        }
+        self.highlight_with_tree(
+            config,
+            Some(tree.clone()),
+            source,
+            cancellation_flag,
+            injection_callback,
+        )
+    }
+
+    fn highlight_with_tree<'a, F: FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a>(
+        &'a mut self,
+        config: &'a HighlightConfiguration,
+        tree: Option<Tree>,
+        source: &'a [u8],
+        cancellation_flag: Option<&'a AtomicUsize>,
+        mut injection_callback: F,
+    ) -> Result<HighlightIter<'a, F>, Error> {
         let layers = HighlightIterLayer::new(
             source,
             self,
             cancellation_flag,
             &mut injection_callback,
             config,
+            tree,
             0,
             vec![Range {
                 start_byte: 0,
@@ -162,6 +268,33 @@ impl Highlighter {
         result.sort_layers();
         Ok(result)
     }
+
+    /// Iterate over the highlighted regions for a given slice of source code, like
+    /// [Highlighter::highlight], but with each highlight identified by the name it was given
+    /// in the call to [HighlightConfiguration::configure].
+    ///
+    /// Names are resolved against `config`, so any configurations returned by the
+    /// `injection_callback` should be configured with the same list of names.
+    pub fn highlight_named<'a>(
+        &'a mut self,
+        config: &'a HighlightConfiguration,
+        source: &'a [u8],
+        cancellation_flag: Option<&'a AtomicUsize>,
+        injection_callback: impl FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a,
+    ) -> Result<impl Iterator<Item = Result<NamedHighlightEvent<'a>, Error>> + 'a, Error> {
+        let events = self.highlight(config, source, cancellation_flag, injection_callback)?;
+        Ok(events.map(move |event| {
+            Ok(match event? {
+                HighlightEvent::Source { start, end } => NamedHighlightEvent::Source { start, end },
+                HighlightEvent::HighlightStart(Highlight(i)) => {
+                    NamedHighlightEvent::HighlightStart(
+                        config.highlight_names.get(i).ok_or(Error::Unknown)?,
+                    )
+                }
+                HighlightEvent::HighlightEnd => NamedHighlightEvent::HighlightEnd,
+            })
+        }))
+    }
 }
 
 impl HighlightConfiguration {
@@ -247,6 +380,7 @@ impl HighlightConfiguration {
         let mut local_def_value_capture_index = None;
         let mut local_ref_capture_index = None;
         let mut local_scope_capture_index = None;
+        let mut local_def_role_capture_indices = Vec::new();
         for (i, name) in query.capture_names().iter().enumerate() {
             let i = Some(i as u32);
             match name.as_str() {
@@ -256,11 +390,15 @@ impl HighlightConfiguration {
                 "local.definition-value" => local_def_value_capture_index = i,
                 "local.reference" => local_ref_capture_index = i,
                 "local.scope" => local_scope_capture_index = i,
+                _ if name.starts_with(LOCAL_DEF_ROLE_PREFIX) => {
+                    local_def_role_capture_indices.extend(i)
+                }
                 _ => {}
             }
         }
 
         let highlight_indices = vec![None; query.capture_names().len()];
+        let local_def_role_highlights = vec![None; query.capture_names().len()];
         Ok(HighlightConfiguration {
             language,
             query,
@@ -268,6 +406,7 @@ impl HighlightConfiguration {
             locals_pattern_index,
             highlights_pattern_index,
             highlight_indices,
+            highlight_names: Vec::new(),
             non_local_variable_patterns,
             injection_content_capture_index,
             injection_language_capture_index,
@@ -275,9 +414,85 @@ impl HighlightConfiguration {
             local_def_value_capture_index,
             local_ref_capture_index,
             local_scope_capture_index,
+            local_def_role_capture_indices,
+            local_def_role_highlights,
         })
     }
 
+    /// Creates a `HighlightConfiguration` from queries that are split across several strings,
+    /// such as a base query file and a file of local overrides.
+    ///
+    /// The strings of each kind are concatenated in order, and then used as in
+    /// [HighlightConfiguration::new]. If any of the queries is invalid, the returned error
+    /// identifies the string that contains the error, and gives the error's position within
+    /// that string.
+    pub fn from_fragments(
+        language: Language,
+        highlights_queries: &[&str],
+        injection_queries: &[&str],
+        locals_queries: &[&str],
+    ) -> Result<Self, QueryFragmentError> {
+        let (highlights_query, highlights_ranges) =
+            concat_fragments(highlights_queries, QueryFragment::Highlights);
+        let (injection_query, injection_ranges) =
+            concat_fragments(injection_queries, QueryFragment::Injections);
+        let (locals_query, locals_ranges) = concat_fragments(locals_queries, QueryFragment::Locals);
+
+        Self::new(language, &highlights_query, &injection_query, &locals_query).map_err(
+            |mut error| {
+                if error.kind == QueryErrorKind::Language {
+                    return QueryFragmentError {
+                        fragment: None,
+                        error,
+                    };
+                }
+
+                // Predicate errors only record the row where their pattern starts, so find
+                // that row's offset in the combined query.
+                if error.kind == QueryErrorKind::Predicate {
+                    let combined_query = [&injection_query, &locals_query, &highlights_query];
+                    error.offset = combined_query
+                        .iter()
+                        .flat_map(|query| query.split_inclusive('\n'))
+                        .take(error.row)
+                        .map(str::len)
+                        .sum();
+                }
+
+                // `new` places the injections query first, followed by the locals query and
+                // then the highlights query.
+                let (query, ranges, offset) = if error.offset < injection_query.len() {
+                    (&injection_query, &injection_ranges, error.offset)
+                } else if error.offset < injection_query.len() + locals_query.len() {
+                    let offset = error.offset - injection_query.len();
+                    (&locals_query, &locals_ranges, offset)
+                } else {
+                    let offset = error.offset - injection_query.len() - locals_query.len();
+                    (&highlights_query, &highlights_ranges, offset)
+                };
+
+                let fragment = ranges
+                    .iter()
+                    .find(|(_, range)| offset <= range.end)
+                    .or(ranges.last());
+                if let Some((fragment, range)) = fragment {
+                    let offset = offset.min(range.end);
+                    error.offset = offset - range.start;
+                    error.row = query[range.start..offset].matches('\n').count();
+                    QueryFragmentError {
+                        fragment: Some(*fragment),
+                        error,
+                    }
+                } else {
+                    QueryFragmentError {
+                        fragment: None,
+                        error,
+                    }
+                }
+            },
+        )
+    }
+
     /// Get a slice containing all of the highlight names used in the configuration.
     pub fn names(&self) -> &[String] {
         self.query.capture_names()
@@ -291,34 +506,36 @@ impl HighlightConfiguration {
     /// For example, the string `function.builtin` will match against `function.method.builtin`
     /// and `function.builtin.constructor`, but will not match `function.method`.
     ///
+    /// Each capture is assigned the most specific recognized name that matches it, so captures
+    /// fall back to less specific names when a theme doesn't define their full name: a
+    /// `function.method.builtin` capture will use `function.method` if it is recognized, and
+    /// otherwise `function`.
+    ///
     /// When highlighting, results are returned as `Highlight` values, which contain the index
     /// of the matched highlight this list of highlight names.
     pub fn configure(&mut self, recognized_names: &[impl AsRef<str>]) {
-        let mut capture_parts = Vec::new();
+        self.highlight_names.clear();
+        self.highlight_names.extend(
+            recognized_names
+                .iter()
+                .map(|name| name.as_ref().to_string()),
+        );
+
         self.highlight_indices.clear();
-        self.highlight_indices
-            .extend(self.query.capture_names().iter().map(move |capture_name| {
-                capture_parts.clear();
-                capture_parts.extend(capture_name.split('.'));
-
-                let mut best_index = None;
-                let mut best_match_len = 0;
-                for (i, recognized_name) in recognized_names.into_iter().enumerate() {
-                    let mut len = 0;
-                    let mut matches = true;
-                    for part in recognized_name.as_ref().split('.') {
-                        len += 1;
-                        if !capture_parts.contains(&part) {
-                            matches = false;
-                            break;
-                        }
-                    }
-                    if matches && len > best_match_len {
-                        best_index = Some(i);
-                        best_match_len = len;
-                    }
-                }
-                best_index.map(Highlight)
+        self.highlight_indices.extend(
+            self.query
+                .capture_names()
+                .iter()
+                .map(|capture_name| best_highlight_match(capture_name, recognized_names)),
+        );
+
+        // Definitions captured as `local.definition.<role>` use the highlight for their role.
+        self.local_def_role_highlights.clear();
+        self.local_def_role_highlights
+            .extend(self.query.capture_names().iter().map(|capture_name| {
+                capture_name
+                    .strip_prefix(LOCAL_DEF_ROLE_PREFIX)
+                    .and_then(|role| best_highlight_match(role, recognized_names))
             }));
     }
 }
@@ -329,12 +546,16 @@ impl<'a> HighlightIterLayer<'a> {
     /// In the even that the new layer contains "combined injections" (injections where multiple
     /// disjoint ranges are parsed as one syntax tree), these will be eagerly processed and
     /// added to the returned vector.
+    ///
+    /// If a `tree` is given, it is used for this layer instead of parsing the source.
+    #[allow(clippy::too_many_arguments)]
     fn new<F: FnMut(&str) -> Option<&'a HighlightConfiguration> + 'a>(
         source: &'a [u8],
         highlighter: &mut Highlighter,
         cancellation_flag: Option<&'a AtomicUsize>,
         injection_callback: &mut F,
         mut config: &'a HighlightConfiguration,
+        mut tree: Option<Tree>,
         mut depth: usize,
         mut ranges: Vec<Range>,
     ) -> Result<Vec<Self>, Error> {
@@ -342,29 +563,43 @@ impl<'a> HighlightIterLayer<'a> {
         let mut queue = Vec::new();
         loop {
             if highlighter.parser.set_included_ranges(&ranges).is_ok() {
-                highlighter
-                    .parser
-                    .set_language(config.language)
-                    .map_err(|_| Error::InvalidLanguage)?;
-
-                unsafe { highlighter.parser.set_cancellation_flag(cancellation_flag) };
-                let tree = highlighter
-                    .parser
-                    .parse(source, None)
-                    .ok_or(Error::Cancelled)?;
-                unsafe { highlighter.parser.set_cancellation_flag(None) };
+                let tree = match tree.take() {
+                    Some(tree) => tree,
+                    None => {
+                        highlighter
+                            .parser
+                            .set_language(config.language)
+                            .map_err(|_| Error::InvalidLanguage)?;
+
+                        unsafe { highlighter.parser.set_cancellation_flag(cancellation_flag) };
+                        let tree = highlighter
+                            .parser
+                            .parse(source, None)
+                            .ok_or(Error::Cancelled)?;
+                        unsafe { highlighter.parser.set_cancellation_flag(None) };
+                        tree
+                    }
+                };
                 let mut cursor = highlighter.cursors.pop().unwrap_or(QueryCursor::new());
 
                 // Process combined injections.
-                if let Some(combined_injections_query) = &config.combined_injections_query {
+                if let (Some(combined_injections_query), true) = (
+                    &config.combined_injections_query,
+                    depth < highlighter.max_injection_depth,
+                ) {
                     let mut injections_by_pattern_index =
                         vec![(None, Vec::new(), false); combined_injections_query.pattern_count()];
                     let matches =
                         cursor.matches(combined_injections_query, tree.root_node(), source);
                     for mat in matches {
                         let entry = &mut injections_by_pattern_index[mat.pattern_index];
-                        let (language_name, content_node, include_children) =
-                            injection_for_match(config, combined_injections_query, &mat, source);
+                        let (language_name, content_node, include_children) = injection_for_match(
+                            config.injection_content_capture_index,
+                            config.injection_language_capture_index,
+                            combined_injections_query,
+                            &mat,
+                            source,
+                        );
                         if language_name.is_some() {
                             entry.0 = language_name;
                         }
@@ -685,16 +920,24 @@ where
 
             // If this capture represents an injection, then process the injection.
             if match_.pattern_index < layer.config.locals_pattern_index {
-                let (language_name, content_node, include_children) =
-                    injection_for_match(&layer.config, &layer.config.query, &match_, &self.source);
+                let (language_name, content_node, include_children) = injection_for_match(
+                    layer.config.injection_content_capture_index,
+                    layer.config.injection_language_capture_index,
+                    &layer.config.query,
+                    &match_,
+                    &self.source,
+                );
 
                 // Explicitly remove this match so that none of its other captures will remain
                 // in the stream of captures.
                 match_.remove();
 
                 // If a language is found with the given name, then add a new language layer
-                // to the highlighted document.
-                if let (Some(language_name), Some(content_node)) = (language_name, content_node) {
+                // to the highlighted document, unless the injection is nested too deeply.
+                let within_max_depth = self.layers[0].depth < self.highlighter.max_injection_depth;
+                if let (Some(language_name), Some(content_node), true) =
+                    (language_name, content_node, within_max_depth)
+                {
                     if let Some(config) = (self.injection_callback)(language_name) {
                         let ranges = HighlightIterLayer::intersect_ranges(
                             &self.layers[0].ranges,
@@ -708,6 +951,7 @@ where
                                 self.cancellation_flag,
                                 &mut self.injection_callback,
                                 config,
+                                None,
                                 self.layers[0].depth + 1,
                                 ranges,
                             ) {
@@ -735,11 +979,13 @@ where
             // local variable info.
             let mut reference_highlight = None;
             let mut definition_highlight = None;
+            let mut definition_role_highlight = None;
             while match_.pattern_index < layer.config.highlights_pattern_index {
                 // If the node represents a local scope, push a new local scope onto
                 // the scope stack.
                 if Some(capture.index) == layer.config.local_scope_capture_index {
                     definition_highlight = None;
+                    definition_role_highlight = None;
                     let mut scope = LocalScope {
                         inherits: true,
                         range: range.clone(),
@@ -758,9 +1004,16 @@ where
                 }
                 // If the node represents a definition, add a new definition to the
                 // local scope at the top of the scope stack.
-                else if Some(capture.index) == layer.config.local_def_capture_index {
+                else if Some(capture.index) == layer.config.local_def_capture_index
+                    || layer
+                        .config
+                        .local_def_role_capture_indices
+                        .contains(&capture.index)
+                {
                     reference_highlight = None;
                     definition_highlight = None;
+                    definition_role_highlight =
+                        layer.config.local_def_role_highlights[capture.index as usize];
                     let scope = layer.scope_stack.last_mut().unwrap();
 
                     let mut value_range = 0..0;
@@ -774,7 +1027,7 @@ where
                         scope.local_defs.push(LocalDef {
                             name,
                             value_range,
-                            highlight: None,
+                            highlight: definition_role_highlight,
                         });
                         definition_highlight =
                             scope.local_defs.last_mut().map(|s| &mut s.highlight);
@@ -867,13 +1120,19 @@ where
             let current_highlight = layer.config.highlight_indices[capture.index as usize];
 
             // If this node represents a local definition, then store the current
-            // highlight value on the local scope entry representing this node.
+            // highlight value on the local scope entry representing this node, unless
+            // the definition's role already determines its highlight.
             if let Some(definition_highlight) = definition_highlight {
-                *definition_highlight = current_highlight;
+                if definition_role_highlight.is_none() {
+                    *definition_highlight = current_highlight;
+                }
             }
 
             // Emit a scope start event and push the node's end position to the stack.
-            if let Some(highlight) = reference_highlight.or(current_highlight) {
+            if let Some(highlight) = reference_highlight
+                .or(definition_role_highlight)
+                .or(current_highlight)
+            {
                 self.last_highlight_range = Some((range.start, range.end, layer.depth));
                 layer.highlight_end_stack.push(range.end);
                 return self
@@ -1023,15 +1282,165 @@ impl HtmlRenderer {
     }
 }
 
+impl Default for AnsiRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnsiRenderer {
+    pub fn new() -> Self {
+        AnsiRenderer { output: Vec::new() }
+    }
+
+    pub fn reset(&mut self) {
+        self.output.clear();
+    }
+
+    /// Render the highlighted source, coloring each highlight with the color returned by
+    /// `color_callback`. Highlights for which the callback returns `None` keep the color
+    /// of the highlight that encloses them.
+    ///
+    /// Every highlight that ends is followed by a reset code, after which the color of
+    /// the enclosing highlight, if any, is restored.
+    pub fn render<F>(
+        &mut self,
+        highlighter: impl Iterator<Item = Result<HighlightEvent, Error>>,
+        source: &[u8],
+        color_callback: &F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(Highlight) -> Option<AnsiColor>,
+    {
+        let mut colors = Vec::new();
+        for event in highlighter {
+            match event {
+                Ok(HighlightEvent::HighlightStart(s)) => {
+                    let color = color_callback(s);
+                    if let Some(color) = color {
+                        self.start_color(color);
+                    }
+                    colors.push(color);
+                }
+                Ok(HighlightEvent::HighlightEnd) => {
+                    colors.pop();
+                    self.output.extend(b"\x1b[0m");
+                    if let Some(color) = colors.iter().rev().find_map(|color| *color) {
+                        self.start_color(color);
+                    }
+                }
+                Ok(HighlightEvent::Source { start, end }) => {
+                    self.output.extend_from_slice(&source[start..end]);
+                }
+                Err(a) => return Err(a),
+            }
+        }
+        Ok(())
+    }
+
+    fn start_color(&mut self, color: AnsiColor) {
+        match color {
+            AnsiColor::Fixed(n) => write!(&mut self.output, "\x1b[38;5;{}m", n),
+            AnsiColor::Rgb(r, g, b) => write!(&mut self.output, "\x1b[38;2;{};{};{}m", r, g, b),
+        }
+        .unwrap();
+    }
+}
+
+// Find the most specific recognized highlight name whose parts all appear in the given
+// dot-separated name.
+fn best_highlight_match(name: &str, recognized_names: &[impl AsRef<str>]) -> Option<Highlight> {
+    let name_parts = name.split('.').collect::<Vec<_>>();
+    let mut best_index = None;
+    let mut best_match_len = 0;
+    for (i, recognized_name) in recognized_names.iter().enumerate() {
+        let mut len = 0;
+        let mut matches = true;
+        for part in recognized_name.as_ref().split('.') {
+            len += 1;
+            if !name_parts.contains(&part) {
+                matches = false;
+                break;
+            }
+        }
+        if matches && len > best_match_len {
+            best_index = Some(i);
+            best_match_len = len;
+        }
+    }
+    best_index.map(Highlight)
+}
+
+/// Run an injection query on a syntax tree, and compute the ranges of the document that
+/// belong to each injected language, so that they can be passed to
+/// [Parser::set_included_ranges](tree_sitter::Parser::set_included_ranges).
+///
+/// The query uses the same captures and properties as the injections query of a
+/// [HighlightConfiguration]: `@injection.content` for the injected nodes, and either
+/// `@injection.language` or `(#set! injection.language ...)` for the language name.
+/// Languages are listed in the order in which they first appear in the document, and
+/// their ranges are ordered by position. Nested injections are not computed.
+pub fn injection_ranges(
+    tree: &Tree,
+    source: &[u8],
+    injection_query: &Query,
+) -> Vec<(String, Vec<Range>)> {
+    let mut content_capture_index = None;
+    let mut language_capture_index = None;
+    for (i, name) in injection_query.capture_names().iter().enumerate() {
+        let i = Some(i as u32);
+        match name.as_str() {
+            "injection.content" => content_capture_index = i,
+            "injection.language" => language_capture_index = i,
+            _ => {}
+        }
+    }
+
+    let document_range = [Range {
+        start_byte: 0,
+        end_byte: usize::MAX,
+        start_point: Point::new(0, 0),
+        end_point: Point::new(usize::MAX, usize::MAX),
+    }];
+    let mut result: Vec<(String, Vec<Range>)> = Vec::new();
+    let mut cursor = QueryCursor::new();
+    for mat in cursor.matches(injection_query, tree.root_node(), source) {
+        let (language_name, content_node, include_children) = injection_for_match(
+            content_capture_index,
+            language_capture_index,
+            injection_query,
+            &mat,
+            source,
+        );
+        if let (Some(language_name), Some(content_node)) = (language_name, content_node) {
+            let ranges = HighlightIterLayer::intersect_ranges(
+                &document_range,
+                &[content_node],
+                include_children,
+            );
+            if ranges.is_empty() {
+                continue;
+            }
+            match result.iter_mut().find(|(name, _)| name == language_name) {
+                Some((_, language_ranges)) => language_ranges.extend(ranges),
+                None => result.push((language_name.to_string(), ranges)),
+            }
+        }
+    }
+
+    for (_, ranges) in result.iter_mut() {
+        ranges.sort_unstable_by_key(|range| range.start_byte);
+    }
+    result
+}
+
 fn injection_for_match<'a>(
-    config: &HighlightConfiguration,
+    content_capture_index: Option<u32>,
+    language_capture_index: Option<u32>,
     query: &'a Query,
     query_match: &QueryMatch<'a, 'a>,
     source: &'a [u8],
 ) -> (Option<&'a str>, Option<Node<'a>>, bool) {
-    let content_capture_index = config.injection_content_capture_index;
-    let language_capture_index = config.injection_language_capture_index;
-
     let mut language_name = None;
     let mut content_node = None;
     for capture in query_match.captures {
@@ -1067,6 +1476,23 @@ fn injection_for_match<'a>(
     (language_name, content_node, include_children)
 }
 
+// Concatenate the given query strings, separating them with newlines so that a comment at
+// the end of one string can't affect the next, and record the range of each string.
+fn concat_fragments(
+    queries: &[&str],
+    fragment: impl Fn(usize) -> QueryFragment,
+) -> (String, Vec<(QueryFragment, ops::Range<usize>)>) {
+    let mut result = String::new();
+    let mut ranges = Vec::with_capacity(queries.len());
+    for (i, query) in queries.iter().enumerate() {
+        let start = result.len();
+        result.push_str(query);
+        ranges.push((fragment(i), start..result.len()));
+        result.push('\n');
+    }
+    (result, ranges)
+}
+
 fn shrink_and_clear<T>(vec: &mut Vec<T>, capacity: usize) {
     if vec.len() > capacity {
         vec.truncate(capacity);
@@ -0,0 +1,160 @@
+use std::ops;
+use tree_sitter::{Language, Node, Query, QueryCursor, QueryError};
+
+/// Resolves references to local variables to their definitions, using a locals query.
+///
+/// The query uses the same captures as the locals query of a [HighlightConfiguration]:
+/// `@local.scope`, `@local.definition`, `@local.definition-value` and `@local.reference`.
+/// Scopes can be prevented from seeing definitions in their enclosing scopes with the
+/// `local.scope-inherits` property.
+///
+/// [HighlightConfiguration]: crate::HighlightConfiguration
+pub struct LocalsResolver {
+    query: Query,
+    scope_capture_index: Option<u32>,
+    definition_capture_index: Option<u32>,
+    definition_value_capture_index: Option<u32>,
+    reference_capture_index: Option<u32>,
+}
+
+struct LocalDef<'a, 'tree> {
+    name: &'a str,
+    value_range: ops::Range<usize>,
+    node: Node<'tree>,
+}
+
+struct LocalScope<'a, 'tree> {
+    inherits: bool,
+    range: ops::Range<usize>,
+    local_defs: Vec<LocalDef<'a, 'tree>>,
+}
+
+impl LocalsResolver {
+    /// Creates a `LocalsResolver` for a given `Language` and locals query.
+    pub fn new(language: Language, locals_query: &str) -> Result<Self, QueryError> {
+        let query = Query::new(language, locals_query)?;
+
+        let mut scope_capture_index = None;
+        let mut definition_capture_index = None;
+        let mut definition_value_capture_index = None;
+        let mut reference_capture_index = None;
+        for (i, name) in query.capture_names().iter().enumerate() {
+            let i = Some(i as u32);
+            match name.as_str() {
+                "local.scope" => scope_capture_index = i,
+                "local.definition" => definition_capture_index = i,
+                "local.definition-value" => definition_value_capture_index = i,
+                "local.reference" => reference_capture_index = i,
+                _ => {}
+            }
+        }
+
+        Ok(LocalsResolver {
+            query,
+            scope_capture_index,
+            definition_capture_index,
+            definition_value_capture_index,
+            reference_capture_index,
+        })
+    }
+
+    /// Get the locals query used by this resolver.
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Find the definition that the given reference node refers to, searching the
+    /// tree beneath `root`.
+    ///
+    /// The innermost enclosing definition with the same name is returned, ignoring
+    /// definitions that appear after the reference. If the node is itself captured
+    /// as a definition, the node is returned. Returns `None` if the node is not a
+    /// reference to any local definition.
+    ///
+    /// This overwrites the byte range of the given `QueryCursor`.
+    pub fn find_definition<'tree>(
+        &self,
+        cursor: &mut QueryCursor,
+        root: Node<'tree>,
+        reference: Node<'tree>,
+        source: &[u8],
+    ) -> Option<Node<'tree>> {
+        let mut scope_stack = vec![LocalScope {
+            inherits: false,
+            range: 0..usize::MAX,
+            local_defs: Vec::new(),
+        }];
+
+        cursor.set_byte_range(root.start_byte()..reference.end_byte());
+        let captures = cursor.captures(&self.query, root, source);
+        for (match_, capture_index) in captures {
+            let capture = match_.captures[capture_index];
+            let range = capture.node.byte_range();
+            if range.start > reference.start_byte() {
+                break;
+            }
+
+            // Remove from the scope stack any scopes that have already ended.
+            while range.start > scope_stack.last().unwrap().range.end {
+                scope_stack.pop();
+            }
+
+            // Push a new scope onto the scope stack.
+            if Some(capture.index) == self.scope_capture_index {
+                let mut scope = LocalScope {
+                    inherits: true,
+                    range,
+                    local_defs: Vec::new(),
+                };
+                for prop in self.query.property_settings(match_.pattern_index) {
+                    if prop.key.as_ref() == "local.scope-inherits" {
+                        scope.inherits = prop.value.as_ref().is_none_or(|r| r.as_ref() == "true");
+                    }
+                }
+                scope_stack.push(scope);
+            }
+            // Add a new definition to the scope at the top of the scope stack.
+            else if Some(capture.index) == self.definition_capture_index {
+                if capture.node == reference {
+                    return Some(reference);
+                }
+
+                let mut value_range = 0..0;
+                for capture in match_.captures {
+                    if Some(capture.index) == self.definition_value_capture_index {
+                        value_range = capture.node.byte_range();
+                    }
+                }
+
+                if let Ok(name) = capture.node.utf8_text(source) {
+                    scope_stack.last_mut().unwrap().local_defs.push(LocalDef {
+                        name,
+                        value_range,
+                        node: capture.node,
+                    });
+                }
+            }
+            // Look up the reference in the scope stack, from the innermost scope outward.
+            else if Some(capture.index) == self.reference_capture_index
+                && capture.node == reference
+            {
+                let name = reference.utf8_text(source).ok()?;
+                for scope in scope_stack.iter().rev() {
+                    if let Some(def) = scope
+                        .local_defs
+                        .iter()
+                        .rev()
+                        .find(|def| def.name == name && range.start >= def.value_range.end)
+                    {
+                        return Some(def.node);
+                    }
+                    if !scope.inherits {
+                        break;
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,134 @@
+/// Tree-sitter capture names, and the TextMate scopes that they correspond to.
+///
+/// A capture name is mapped using its longest dotted prefix that appears in this table,
+/// so `function.method` maps to `entity.name.function.method`.
+const CAPTURE_SCOPES: &[(&str, &str)] = &[
+    ("attribute", "entity.other.attribute-name"),
+    ("comment", "comment"),
+    ("constant", "constant"),
+    ("constant.builtin", "constant.language"),
+    ("constructor", "entity.name.function.constructor"),
+    ("embedded", "meta.embedded"),
+    ("escape", "constant.character.escape"),
+    ("function", "entity.name.function"),
+    ("function.builtin", "support.function"),
+    ("keyword", "keyword"),
+    ("label", "entity.name.label"),
+    ("module", "entity.name.namespace"),
+    ("number", "constant.numeric"),
+    ("operator", "keyword.operator"),
+    ("property", "variable.other.property"),
+    ("punctuation", "punctuation"),
+    ("punctuation.bracket", "punctuation.section"),
+    ("punctuation.delimiter", "punctuation.separator"),
+    ("string", "string.quoted"),
+    ("string.special", "string.other"),
+    ("tag", "entity.name.tag"),
+    ("type", "entity.name.type"),
+    ("type.builtin", "support.type"),
+    ("variable", "variable"),
+    ("variable.builtin", "variable.language"),
+    ("variable.parameter", "variable.parameter"),
+];
+
+/// Get the TextMate scope that corresponds to a Tree-sitter capture name.
+///
+/// Any parts of the capture name beyond the prefix that was found in the mapping are
+/// appended to the scope, and capture names with no known prefix are used as-is.
+pub fn scope_for_capture(capture_name: &str) -> String {
+    let mut best_match: Option<(&str, &str)> = None;
+    for &(prefix, scope) in CAPTURE_SCOPES {
+        if scope_matches(capture_name, prefix)
+            && best_match.is_none_or(|(best_prefix, _)| prefix.len() > best_prefix.len())
+        {
+            best_match = Some((prefix, scope));
+        }
+    }
+    match best_match {
+        Some((prefix, scope)) => scope.to_string() + &capture_name[prefix.len()..],
+        None => capture_name.to_string(),
+    }
+}
+
+/// A list of theme rules whose selectors are TextMate scope selectors, such as
+/// `string`, `source.js comment` or `constant.numeric, constant.language`.
+///
+/// Selectors are made of alternatives separated by commas, each of which is a sequence
+/// of scope prefixes separated by spaces. Exclusions (`-`) are not supported.
+pub struct TextMateTheme<T> {
+    rules: Vec<(Vec<Vec<String>>, T)>,
+}
+
+impl<T> TextMateTheme<T> {
+    /// Create a theme from its rules, each of which pairs a selector with a value such
+    /// as a color.
+    pub fn new<S: AsRef<str>>(rules: impl IntoIterator<Item = (S, T)>) -> Self {
+        TextMateTheme {
+            rules: rules
+                .into_iter()
+                .map(|(selector, value)| {
+                    let alternatives = selector
+                        .as_ref()
+                        .split(',')
+                        .map(|path| path.split_whitespace().map(String::from).collect())
+                        .filter(|path: &Vec<String>| !path.is_empty())
+                        .collect();
+                    (alternatives, value)
+                })
+                .collect(),
+        }
+    }
+
+    /// Find the value of the rule whose selector best matches the given scopes, which
+    /// are ordered from outermost to innermost.
+    ///
+    /// Selectors are ranked by TextMate's specificity rules: a selector that matches a
+    /// more deeply nested scope wins, then one that matches more parts of that scope's
+    /// name, and then the comparison continues with the selectors' earlier elements.
+    /// When two selectors are equally specific, the later rule wins.
+    pub fn resolve(&self, scopes: &[&str]) -> Option<&T> {
+        let mut best: Option<(Vec<(usize, usize)>, &T)> = None;
+        for (alternatives, value) in &self.rules {
+            for path in alternatives {
+                if let Some(score) = selector_score(path, scopes) {
+                    if best
+                        .as_ref()
+                        .is_none_or(|(best_score, _)| score >= *best_score)
+                    {
+                        best = Some((score, value));
+                    }
+                }
+            }
+        }
+        best.map(|(_, value)| value)
+    }
+
+    /// Find the value for a Tree-sitter capture name, within the given root scope for
+    /// the language, such as `source.js`.
+    pub fn resolve_capture(&self, root_scope: &str, capture_name: &str) -> Option<&T> {
+        self.resolve(&[root_scope, &scope_for_capture(capture_name)])
+    }
+}
+
+// Score a selector path against a list of scopes, or return `None` if it doesn't
+// match. The score lists the depth and the number of name parts of the scope that
+// each selector element matched, starting with the last element.
+fn selector_score(path: &[String], scopes: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let mut score = Vec::with_capacity(path.len());
+    let mut remaining = scopes.len();
+    for element in path.iter().rev() {
+        let depth = (0..remaining)
+            .rev()
+            .find(|i| scope_matches(scopes[*i], element))?;
+        score.push((depth + 1, element.split('.').count()));
+        remaining = depth;
+    }
+    Some(score)
+}
+
+// Check if a scope such as `string.quoted.double` starts with the given dotted
+// prefix, such as `string` or `string.quoted`.
+fn scope_matches(scope: &str, prefix: &str) -> bool {
+    scope == prefix
+        || (scope.starts_with(prefix) && scope.as_bytes().get(prefix.len()) == Some(&b'.'))
+}
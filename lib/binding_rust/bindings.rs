@@ -1,5 +1,6 @@
 /* automatically generated by rust-bindgen 0.59.2 */
 
+pub type TSStateId = u16;
 pub type TSSymbol = u16;
 pub type TSFieldId = u16;
 #[repr(C)]
@@ -27,6 +28,11 @@ pub struct TSQuery {
 pub struct TSQueryCursor {
     _unused: [u8; 0],
 }
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct TSLookaheadIterator {
+    _unused: [u8; 0],
+}
 pub const TSInputEncoding_TSInputEncodingUTF8: TSInputEncoding = 0;
 pub const TSInputEncoding_TSInputEncodingUTF16: TSInputEncoding = 1;
 pub type TSInputEncoding = ::std::os::raw::c_uint;
@@ -50,6 +56,14 @@ pub struct TSRange {
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
+pub struct TSParseStats {
+    pub bytes_parsed: u32,
+    pub token_count: u32,
+    pub reduction_count: u32,
+    pub had_errors: bool,
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
 pub struct TSInput {
     pub payload: *mut ::std::os::raw::c_void,
     pub read: ::std::option::Option<
@@ -156,6 +170,11 @@ extern "C" {
     #[doc = " Tree-sitter CLI. Check the language's version using `ts_language_version`"]
     #[doc = " and compare it to this library's `TREE_SITTER_LANGUAGE_VERSION` and"]
     #[doc = " `TREE_SITTER_MIN_COMPATIBLE_LANGUAGE_VERSION` constants."]
+    #[doc = ""]
+    #[doc = " Switching languages resets the parser, but keeps the memory that it has"]
+    #[doc = " allocated for parsing, such as its parse stack and its pool of syntax tree"]
+    #[doc = " nodes, so that it can be reused for the new language. Only the external"]
+    #[doc = " scanner is recreated, and that is skipped if the language is unchanged."]
     pub fn ts_parser_set_language(self_: *mut TSParser, language: *const TSLanguage) -> bool;
 }
 extern "C" {
@@ -221,7 +240,7 @@ extern "C" {
     #[doc = "    `TSInputEncodingUTF8` or `TSInputEncodingUTF16`."]
     #[doc = ""]
     #[doc = " This function returns a syntax tree on success, and `NULL` on failure. There"]
-    #[doc = " are three possible reasons for failure:"]
+    #[doc = " are four possible reasons for failure:"]
     #[doc = " 1. The parser does not have a language assigned. Check for this using the"]
     #[doc = "`ts_parser_language` function."]
     #[doc = " 2. Parsing was cancelled due to a timeout that was set by an earlier call to"]
@@ -233,6 +252,9 @@ extern "C" {
     #[doc = "    earlier call to `ts_parser_set_cancellation_flag`. You can resume parsing"]
     #[doc = "    from where the parser left out by calling `ts_parser_parse` again with"]
     #[doc = "    the same arguments."]
+    #[doc = " 4. The parse stack grew deeper than the limit that was set by an earlier"]
+    #[doc = "    call to `ts_parser_set_max_stack_depth`. In this case, the parser is"]
+    #[doc = "    reset, so the next call to `ts_parser_parse` will start from scratch."]
     pub fn ts_parser_parse(
         self_: *mut TSParser,
         old_tree: *const TSTree,
@@ -286,6 +308,30 @@ extern "C" {
     #[doc = " Get the duration in microseconds that parsing is allowed to take."]
     pub fn ts_parser_timeout_micros(self_: *const TSParser) -> u64;
 }
+extern "C" {
+    #[doc = " Set the maximum number of entries that the parse stack is allowed to"]
+    #[doc = " contain before halting."]
+    #[doc = ""]
+    #[doc = " Deeply nested input requires a correspondingly deep parse stack. If the"]
+    #[doc = " stack grows deeper than this, parsing will halt early, returning NULL."]
+    #[doc = " A value of zero, which is the default, means that there is no limit."]
+    #[doc = " See `ts_parser_parse` for more information."]
+    pub fn ts_parser_set_max_stack_depth(self_: *mut TSParser, max_stack_depth: u32);
+}
+extern "C" {
+    #[doc = " Get the maximum number of entries that the parse stack is allowed to contain."]
+    pub fn ts_parser_max_stack_depth(self_: *const TSParser) -> u32;
+}
+extern "C" {
+    #[doc = " Get statistics about the work that was done by the most recent call to"]
+    #[doc = " `ts_parser_parse`: the number of bytes in the resulting tree, the number of"]
+    #[doc = " tokens that were lexed, the number of reductions that were performed, and"]
+    #[doc = " whether error recovery occurred."]
+    #[doc = ""]
+    #[doc = " Returns `false`, leaving `stats` untouched, if the most recent call to"]
+    #[doc = " `ts_parser_parse` did not complete."]
+    pub fn ts_parser_last_parse_stats(self_: *const TSParser, stats: *mut TSParseStats) -> bool;
+}
 extern "C" {
     #[doc = " Set the parser's current cancellation flag pointer."]
     #[doc = ""]
@@ -345,6 +391,12 @@ extern "C" {
     #[doc = " Get the language that was used to parse the syntax tree."]
     pub fn ts_tree_language(arg1: *const TSTree) -> *const TSLanguage;
 }
+extern "C" {
+    #[doc = " Get the array of included ranges that was used to parse the syntax tree."]
+    #[doc = ""]
+    #[doc = " The returned pointer must be freed by the caller."]
+    pub fn ts_tree_included_ranges(arg1: *const TSTree, length: *mut u32) -> *mut TSRange;
+}
 extern "C" {
     #[doc = " Edit the syntax tree to keep it in sync with source code that has been"]
     #[doc = " edited."]
@@ -372,6 +424,28 @@ extern "C" {
         length: *mut u32,
     ) -> *mut TSRange;
 }
+extern "C" {
+    #[doc = " Serialize the syntax tree to a compact binary format, so that it can be"]
+    #[doc = " stored and later restored using `ts_tree_deserialize` without reparsing."]
+    #[doc = ""]
+    #[doc = " The returned buffer is allocated using the library's allocator, which can be"]
+    #[doc = " changed with `ts_set_allocator`, and the caller is responsible for freeing it"]
+    #[doc = " using the matching `free` function. The length of the buffer will be written"]
+    #[doc = " to the given `length` pointer."]
+    pub fn ts_tree_serialize(self_: *const TSTree, length: *mut u32)
+        -> *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    #[doc = " Restore a syntax tree that was serialized using `ts_tree_serialize`."]
+    #[doc = ""]
+    #[doc = " The language must be the one that was used to parse the original tree."]
+    #[doc = " Returns `NULL` if the data is not a valid serialized tree for that language."]
+    pub fn ts_tree_deserialize(
+        data: *const ::std::os::raw::c_char,
+        length: u32,
+        language: *const TSLanguage,
+    ) -> *mut TSTree;
+}
 extern "C" {
     #[doc = " Get the node's type as a null-terminated string."]
     pub fn ts_node_type(arg1: TSNode) -> *const ::std::os::raw::c_char;
@@ -380,6 +454,11 @@ extern "C" {
     #[doc = " Get the node's type as a numerical id."]
     pub fn ts_node_symbol(arg1: TSNode) -> TSSymbol;
 }
+extern "C" {
+    #[doc = " Get the node's type as a numerical id as it appears in the grammar,"]
+    #[doc = " ignoring aliases. For aliased nodes, this differs from `ts_node_symbol`."]
+    pub fn ts_node_grammar_symbol(arg1: TSNode) -> TSSymbol;
+}
 extern "C" {
     #[doc = " Get the node's start byte."]
     pub fn ts_node_start_byte(arg1: TSNode) -> u32;
@@ -629,6 +708,10 @@ extern "C" {
     #[doc = " Delete a query, freeing all of the memory that it used."]
     pub fn ts_query_delete(arg1: *mut TSQuery);
 }
+extern "C" {
+    #[doc = " Get the language that the query was created for."]
+    pub fn ts_query_language(arg1: *const TSQuery) -> *const TSLanguage;
+}
 extern "C" {
     #[doc = " Get the number of patterns, captures, or string literals in the query."]
     pub fn ts_query_pattern_count(arg1: *const TSQuery) -> u32;
@@ -692,6 +775,16 @@ extern "C" {
         capture_id: u32,
     ) -> TSQuantifier;
 }
+extern "C" {
+    #[doc = " Get the depth, within the given pattern, of the node that is captured with"]
+    #[doc = " the given id. The pattern's outermost nodes have a depth of zero. If the"]
+    #[doc = " pattern does not contain the capture, `UINT32_MAX` is returned."]
+    pub fn ts_query_capture_depth_for_id(
+        arg1: *const TSQuery,
+        pattern_id: u32,
+        capture_id: u32,
+    ) -> u32;
+}
 extern "C" {
     pub fn ts_query_string_value_for_id(
         arg1: *const TSQuery,
@@ -767,6 +860,51 @@ extern "C" {
 extern "C" {
     pub fn ts_query_cursor_set_match_limit(arg1: *mut TSQueryCursor, arg2: u32);
 }
+extern "C" {
+    #[doc = " Set the maximum duration in microseconds that query execution should be"]
+    #[doc = " allowed to take before halting."]
+    #[doc = ""]
+    #[doc = " If query execution takes longer than this, it will halt early, and"]
+    #[doc = " `ts_query_cursor_next_match` and `ts_query_cursor_next_capture` will return"]
+    #[doc = " `false`. Use `ts_query_cursor_did_exceed_timeout` to distinguish this from"]
+    #[doc = " the query having found all of its matches. The timer starts when"]
+    #[doc = " `ts_query_cursor_exec` is called. A timeout of zero, which is the default,"]
+    #[doc = " means that execution is never halted."]
+    pub fn ts_query_cursor_set_timeout_micros(self_: *mut TSQueryCursor, timeout_micros: u64);
+}
+extern "C" {
+    #[doc = " Get the duration in microseconds that query execution is allowed to take."]
+    pub fn ts_query_cursor_timeout_micros(self_: *const TSQueryCursor) -> u64;
+}
+extern "C" {
+    #[doc = " Check if, on its last execution, this cursor was halted because it exceeded"]
+    #[doc = " its timeout."]
+    pub fn ts_query_cursor_did_exceed_timeout(self_: *const TSQueryCursor) -> bool;
+}
+extern "C" {
+    #[doc = " Set the maximum number of bytes that the query cursor is allowed to allocate"]
+    #[doc = " for its in-progress matches."]
+    #[doc = ""]
+    #[doc = " If query execution needs more memory than this, it will halt early, and"]
+    #[doc = " `ts_query_cursor_next_match` and `ts_query_cursor_next_capture` will return"]
+    #[doc = " `false`. Use `ts_query_cursor_did_exceed_max_memory` to distinguish this"]
+    #[doc = " from the query having found all of its matches. A limit of zero, which is"]
+    #[doc = " the default, means that the memory usage is not limited."]
+    #[doc = ""]
+    #[doc = " Only the memory allocated during each execution is counted. Memory that the"]
+    #[doc = " cursor retained from earlier executions is reused without counting towards"]
+    #[doc = " the limit."]
+    pub fn ts_query_cursor_set_max_memory(self_: *mut TSQueryCursor, max_memory: u64);
+}
+extern "C" {
+    #[doc = " Get the maximum number of bytes that the query cursor is allowed to allocate."]
+    pub fn ts_query_cursor_max_memory(self_: *const TSQueryCursor) -> u64;
+}
+extern "C" {
+    #[doc = " Check if, on its last execution, this cursor was halted because it exceeded"]
+    #[doc = " its memory limit."]
+    pub fn ts_query_cursor_did_exceed_max_memory(self_: *const TSQueryCursor) -> bool;
+}
 extern "C" {
     #[doc = " Set the range of bytes or (row, column) positions in which the query"]
     #[doc = " will be executed."]
@@ -850,6 +988,59 @@ extern "C" {
     #[doc = " See also `ts_parser_set_language`."]
     pub fn ts_language_version(arg1: *const TSLanguage) -> u32;
 }
+extern "C" {
+    #[doc = " Get the number of valid parse states in the language."]
+    pub fn ts_language_state_count(arg1: *const TSLanguage) -> u32;
+}
+extern "C" {
+    #[doc = " Check that the language's tables are internally consistent, so that it can be"]
+    #[doc = " used safely. Languages that were loaded from untrusted or possibly corrupt"]
+    #[doc = " sources can be checked before they are assigned to a parser."]
+    #[doc = ""]
+    #[doc = " This returns `NULL` if the language is consistent. Otherwise, it returns a"]
+    #[doc = " static string describing the first inconsistency that was found. The ABI"]
+    #[doc = " version is not checked."]
+    pub fn ts_language_inconsistency(arg1: *const TSLanguage) -> *const ::std::os::raw::c_char;
+}
+extern "C" {
+    #[doc = " Get the next parse state, given a current parse state and a symbol."]
+    #[doc = ""]
+    #[doc = " Combine this with lookahead iterators to generate completion suggestions or"]
+    #[doc = " valid symbols in error nodes. This returns `0` if the symbol cannot follow"]
+    #[doc = " the given state."]
+    pub fn ts_language_next_state(
+        arg1: *const TSLanguage,
+        arg2: TSStateId,
+        arg3: TSSymbol,
+    ) -> TSStateId;
+}
+extern "C" {
+    #[doc = " Create a new lookahead iterator for the given language and parse state."]
+    #[doc = ""]
+    #[doc = " This returns `NULL` if the state is invalid for the language."]
+    #[doc = ""]
+    #[doc = " Repeatedly using `ts_lookahead_iterator_next` and"]
+    #[doc = " `ts_lookahead_iterator_current_symbol` will generate valid symbols in the"]
+    #[doc = " given parse state. Both terminal and non-terminal symbols are included."]
+    pub fn ts_lookahead_iterator_new(
+        self_: *const TSLanguage,
+        state: TSStateId,
+    ) -> *mut TSLookaheadIterator;
+}
+extern "C" {
+    #[doc = " Delete a lookahead iterator, freeing all the memory used."]
+    pub fn ts_lookahead_iterator_delete(self_: *mut TSLookaheadIterator);
+}
+extern "C" {
+    #[doc = " Advance the lookahead iterator to the next symbol."]
+    #[doc = ""]
+    #[doc = " This returns `true` if there is a new symbol and `false` otherwise."]
+    pub fn ts_lookahead_iterator_next(self_: *mut TSLookaheadIterator) -> bool;
+}
+extern "C" {
+    #[doc = " Get the current symbol of the lookahead iterator."]
+    pub fn ts_lookahead_iterator_current_symbol(self_: *const TSLookaheadIterator) -> TSSymbol;
+}
 extern "C" {
     #[doc = " Set the allocation functions used by the library."]
     #[doc = ""]
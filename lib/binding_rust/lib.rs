@@ -5,9 +5,13 @@ mod util;
 use std::os::unix::io::AsRawFd;
 
 use std::{
-    char, error,
+    cell::RefCell,
+    char, cmp,
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    error,
     ffi::CStr,
-    fmt, hash, iter,
+    fmt, hash, io, iter,
     marker::PhantomData,
     mem::MaybeUninit,
     ops,
@@ -15,6 +19,7 @@ use std::{
     ptr::{self, NonNull},
     slice, str,
     sync::atomic::AtomicUsize,
+    time::{Duration, Instant},
     u16,
 };
 
@@ -37,14 +42,35 @@ pub const PARSER_HEADER: &'static str = include_str!("../include/tree_sitter/par
 
 /// An opaque object that defines how to parse a particular language. The code for each
 /// `Language` is generated by the Tree-sitter CLI.
+///
+/// Languages are compared and hashed by identity, so two `Language` values are equal
+/// if and only if they refer to the same underlying language.
 #[doc(alias = "TSLanguage")]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct Language(*const ffi::TSLanguage);
 
+/// A function that returns a `Language`, such as the `tree_sitter_<name>` function that
+/// is exported by every generated parser.
+///
+/// The function isn't called until the language is requested, which makes this a
+/// convenient thing to store for each grammar in a plugin registry.
+#[derive(Clone, Copy, Debug)]
+pub struct LanguageFn(unsafe extern "C" fn() -> *const ());
+
 /// A tree that represents the syntactic structure of a source code file.
 #[doc(alias = "TSTree")]
-pub struct Tree(NonNull<ffi::TSTree>);
+pub struct Tree(NonNull<ffi::TSTree>, ffi::TSNode);
+
+/// A bounded history of syntax tree snapshots, along with the edits between them,
+/// for implementing undo and redo.
+///
+/// Snapshots are shallow copies of trees, so they are cheap to keep.
+pub struct TreeHistory {
+    snapshots: VecDeque<(Tree, Option<InputEdit>)>,
+    current: usize,
+    capacity: usize,
+}
 
 /// A position in a multi-line text document, in terms of rows and columns.
 ///
@@ -65,6 +91,30 @@ pub struct Range {
     pub end_point: Point,
 }
 
+/// Statistics about the work done by a parser during its most recent parse.
+#[doc(alias = "TSParseStats")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseStats {
+    /// The number of bytes in the resulting syntax tree.
+    pub bytes_parsed: usize,
+    /// The number of tokens that were lexed.
+    pub token_count: usize,
+    /// The number of reductions that were performed.
+    pub reduction_count: usize,
+    /// Whether the parser had to perform error recovery.
+    pub had_errors: bool,
+}
+
+/// An estimate of how much of a syntax tree was reused from an old tree during an
+/// incremental parse, as computed by [Tree::reuse_stats].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReuseStats {
+    /// The number of bytes outside of the tree's changed ranges.
+    pub reused_bytes: usize,
+    /// The number of bytes within the tree's changed ranges.
+    pub reparsed_bytes: usize,
+}
+
 /// A summary of a change to a text document.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct InputEdit {
@@ -76,6 +126,36 @@ pub struct InputEdit {
     pub new_end_position: Point,
 }
 
+/// A token in a flat stream of tokens, as produced by [Parser::tokenize].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token {
+    /// The kind of the token's node, as returned by [Node::kind].
+    pub kind: &'static str,
+    pub range: Range,
+}
+
+/// The encoding of a text document, as detected by [Parser::parse_detect_encoding].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputEncoding {
+    Utf8,
+    /// UTF16, with the low byte of each code unit first.
+    Utf16LE,
+    /// UTF16, with the high byte of each code unit first.
+    Utf16BE,
+}
+
+/// The way in which the parser produced a node while recovering from a syntax error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RecoveryKind {
+    /// The node was not produced by error recovery.
+    None,
+    /// The node is an `ERROR` node, whose children are input that the parser skipped.
+    Skipped,
+    /// The node is a *missing* node, which the parser inserted even though it does not
+    /// appear in the input.
+    Missing,
+}
+
 /// A single node within a syntax `Tree`.
 #[doc(alias = "TSNode")]
 #[derive(Clone, Copy)]
@@ -86,6 +166,15 @@ pub struct Node<'a>(ffi::TSNode, PhantomData<&'a ()>);
 #[doc(alias = "TSParser")]
 pub struct Parser(NonNull<ffi::TSParser>);
 
+/// A builder for a `Parser`, which applies and validates the parser's configuration
+/// all at once in [ParserBuilder::build].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParserBuilder {
+    language: Option<Language>,
+    timeout_micros: u64,
+    included_ranges: Vec<Range>,
+}
+
 /// A type of log message.
 #[derive(Debug, PartialEq, Eq)]
 pub enum LogType {
@@ -100,6 +189,24 @@ type Logger<'a> = Box<dyn FnMut(LogType, &str) + 'a>;
 #[doc(alias = "TSTreeCursor")]
 pub struct TreeCursor<'a>(ffi::TSTreeCursor, PhantomData<&'a ()>);
 
+/// A `TreeCursor` that records how many nodes of each kind it visits, and how much
+/// time passes while they are on its path, for profiling code that walks a tree.
+pub struct ProfiledCursor<'a> {
+    cursor: TreeCursor<'a>,
+    path: Vec<(&'static str, Instant)>,
+    stats: HashMap<&'static str, NodeKindStats>,
+}
+
+/// The visits to nodes of one kind, as recorded by a [ProfiledCursor].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeKindStats {
+    /// The number of times that the cursor moved onto a node of this kind.
+    pub visits: usize,
+    /// The total time between the cursor moving onto these nodes and leaving them,
+    /// including the time spent within their descendants.
+    pub duration: Duration,
+}
+
 /// A set of patterns that match nodes in a syntax tree.
 #[doc(alias = "TSQuery")]
 #[derive(Debug)]
@@ -108,9 +215,25 @@ pub struct Query {
     capture_names: Vec<String>,
     capture_quantifiers: Vec<Vec<CaptureQuantifier>>,
     text_predicates: Vec<Box<[TextPredicate]>>,
+    text_predicate_sources: Vec<Box<[QueryPredicate]>>,
     property_settings: Vec<Box<[QueryProperty]>>,
     property_predicates: Vec<Box<[(QueryProperty, bool)]>>,
     general_predicates: Vec<Box<[QueryPredicate]>>,
+    warnings: Vec<QueryWarning>,
+}
+
+/// A builder for the source of a single `Query` pattern.
+///
+/// This can be used instead of formatting S-expression strings by hand. The
+/// builder produces query source with [QueryBuilder::to_source], or compiles it
+/// directly with [QueryBuilder::build].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryBuilder {
+    kind: Option<String>,
+    named: bool,
+    field: Option<String>,
+    capture: Option<String>,
+    children: Vec<QueryBuilder>,
 }
 
 /// A quantifier for captures
@@ -136,10 +259,42 @@ impl From<ffi::TSQuantifier> for CaptureQuantifier {
     }
 }
 
+/// The order in which a `QueryCursor` returns the matches of a `Query`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchOrder {
+    /// Matches are returned in the order that they are found while walking the tree.
+    Document,
+    /// Matches are grouped by the index of the pattern that produced them.
+    Pattern,
+}
+
 /// A stateful object for executing a `Query` on a syntax `Tree`.
 #[doc(alias = "TSQueryCursor")]
 pub struct QueryCursor {
     ptr: NonNull<ffi::TSQueryCursor>,
+    skip_gap_captures: bool,
+    capture_allowlist: Option<Vec<String>>,
+    match_order: MatchOrder,
+    // The matches that are buffered for `MatchOrder::Pattern`, with the range of each
+    // one's captures in `sorted_captures`. The buffer is reused across trees, so its
+    // nodes are stored with a `'static` lifetime, which is narrowed to the lifetime of
+    // the tree again when they are returned.
+    sorted_matches: Vec<(usize, u32, ops::Range<usize>)>,
+    sorted_captures: Vec<QueryCapture<'static>>,
+}
+
+/// An iterator over the symbols that are valid in a given parse state of a `Language`.
+#[doc(alias = "TSLookaheadIterator")]
+pub struct LookaheadIterator {
+    ptr: NonNull<ffi::TSLookaheadIterator>,
+}
+
+/// A set of node kinds of a particular `Language`, stored as a bitset over their numerical
+/// ids so that checking whether a node belongs to the set doesn't require comparing strings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KindSet {
+    language: Language,
+    bits: Vec<u64>,
 }
 
 /// A key-value pair associated with a particular pattern in a `Query`.
@@ -150,7 +305,7 @@ pub struct QueryProperty {
     pub capture_id: Option<usize>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum QueryPredicateArg {
     Capture(u32),
     String(Box<str>),
@@ -163,12 +318,50 @@ pub struct QueryPredicate {
     pub args: Vec<QueryPredicateArg>,
 }
 
+/// A text predicate that rejected a match, as reported by
+/// `QueryCursor::matches_with_diagnostics`.
+///
+/// The operator and arguments are those of the predicate as it is written in the query.
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueryPredicateFailure {
+    /// The index of the predicate among the pattern's text predicates, in the order in
+    /// which they appear. These are the predicates that the crate checks itself, such
+    /// as `#eq?`, `#match?`, `#glob?`, `#is-at-line-start?` and `#has-ancestor?`, along
+    /// with their variants and negations.
+    pub predicate_index: usize,
+    pub operator: Box<str>,
+    pub args: Vec<QueryPredicateArg>,
+}
+
 /// A match of a `Query` to a particular set of `Node`s.
 pub struct QueryMatch<'cursor, 'tree> {
     pub pattern_index: usize,
     pub captures: &'cursor [QueryCapture<'tree>],
     id: u32,
     cursor: *mut ffi::TSQueryCursor,
+    query: *const ffi::TSQuery,
+}
+
+/// A match of a `Query` whose captures are owned, rather than borrowed from the
+/// `QueryCursor` that produced it.
+#[derive(Clone, Debug)]
+pub struct OwnedMatch<'tree> {
+    pub pattern_index: usize,
+    pub captures: Vec<QueryCapture<'tree>>,
+}
+
+/// The captures of a `QueryMatch`, arranged into a hierarchy according to which
+/// captured nodes contain which others.
+#[derive(Clone, Debug)]
+pub struct CaptureTree<'tree> {
+    pub roots: Vec<CaptureTreeNode<'tree>>,
+}
+
+/// A capture within a `CaptureTree`, along with the captures nested inside of it.
+#[derive(Clone, Debug)]
+pub struct CaptureTreeNode<'tree> {
+    pub capture: QueryCapture<'tree>,
+    pub children: Vec<CaptureTreeNode<'tree>>,
 }
 
 /// A sequence of `QueryMatch`es associated with a given `QueryCursor`.
@@ -178,6 +371,35 @@ pub struct QueryMatches<'a, 'tree: 'a, T: TextProvider<'a>> {
     text_provider: T,
     buffer1: Vec<u8>,
     buffer2: Vec<u8>,
+    included_ranges: Vec<Range>,
+    sorted_matches: Option<slice::Iter<'a, (usize, u32, ops::Range<usize>)>>,
+    sorted_captures: &'a [QueryCapture<'static>],
+    _tree: PhantomData<&'tree ()>,
+}
+
+/// A sequence of `QueryMatch`es found in several syntax trees by a given `QueryCursor`,
+/// each paired with the identifier of the tree it was found in.
+pub struct QueryMatchesOver<'a, 'tree: 'a, T: TextProvider<'a>, I> {
+    ptr: *mut ffi::TSQueryCursor,
+    query: &'a Query,
+    trees: I,
+    current: Option<(usize, T)>,
+    buffer1: Vec<u8>,
+    buffer2: Vec<u8>,
+    skip_gap_captures: bool,
+    included_ranges: Vec<Range>,
+    _tree: PhantomData<&'tree ()>,
+}
+
+/// A sequence of `QueryMatch`es associated with a given `QueryCursor`, including the
+/// matches that were rejected by text predicates.
+pub struct QueryMatchesWithDiagnostics<'a, 'tree: 'a, T: TextProvider<'a>> {
+    ptr: *mut ffi::TSQueryCursor,
+    query: &'a Query,
+    text_provider: T,
+    buffer1: Vec<u8>,
+    buffer2: Vec<u8>,
+    included_ranges: Vec<Range>,
     _tree: PhantomData<&'tree ()>,
 }
 
@@ -188,14 +410,30 @@ pub struct QueryCaptures<'a, 'tree: 'a, T: TextProvider<'a>> {
     text_provider: T,
     buffer1: Vec<u8>,
     buffer2: Vec<u8>,
+    included_ranges: Vec<Range>,
+    allowed_captures: Option<Vec<bool>>,
     _tree: PhantomData<&'tree ()>,
 }
 
+/// A source of the text of syntax nodes, for evaluating the text predicates of a `Query`.
+///
+/// The text of a node can be returned in several chunks, such as the pieces of a rope
+/// that the node overlaps, so the source doesn't need to be stored in a contiguous
+/// buffer. The chunks are only joined when a predicate needs the node's text.
 pub trait TextProvider<'a> {
     type I: Iterator<Item = &'a [u8]> + 'a;
     fn text(&mut self, node: Node) -> Self::I;
 }
 
+/// A source of UTF8 text that is stored in pieces, such as a rope, which can be parsed
+/// with [Parser::parse_chunks] without first being copied into a contiguous buffer.
+pub trait Chunks {
+    /// Get a slice of the text that starts at the given byte offset. The slice can be
+    /// of any length, such as the remainder of the piece containing that offset. If the
+    /// offset is at or beyond the end of the text, an empty slice should be returned.
+    fn chunk_at(&self, byte: usize) -> &[u8];
+}
+
 /// A particular `Node` that has been captured with a particular name within a `Query`.
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -204,16 +442,57 @@ pub struct QueryCapture<'a> {
     pub index: u32,
 }
 
-/// An error that occurred when trying to assign an incompatible `Language` to a `Parser`.
+/// An error that occurred when trying to assign an incompatible `Language` to a `Parser`,
+/// or when a `Language` failed [validation](Language::validate).
 #[derive(Debug, PartialEq, Eq)]
 pub struct LanguageError {
     version: usize,
+    inconsistency: Option<&'static str>,
 }
 
 /// An error that occurred in `Parser::set_included_ranges`.
 #[derive(Debug, PartialEq, Eq)]
 pub struct IncludedRangesError(pub usize);
 
+/// An error that occurred in `ParserBuilder::build`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParserBuilderError {
+    Language(LanguageError),
+    IncludedRanges(IncludedRangesError),
+}
+
+/// An error that occurred in `Tree::deserialize`, because the data was not a valid
+/// serialized tree for the given language.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TreeDeserializationError;
+
+/// An error that occurred in `Query::remap_capture`, because the query already has
+/// another capture with the requested name.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CaptureNameConflictError(pub String);
+
+/// An error that occurred in `Node::try_child`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChildError {
+    /// The index is not less than the node's number of children.
+    OutOfRange { index: usize, child_count: usize },
+    /// The child at the index is a *missing* node, which the parser inserted to recover
+    /// from an error.
+    Missing { index: usize },
+}
+
+/// An error that occurred in `Tree::try_edit`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The edit's start position is after its old or new end position.
+    InvalidRange,
+    /// The edit's old end position is beyond the end of the tree.
+    OutOfBounds {
+        end_byte: usize,
+        end_position: Point,
+    },
+}
+
 /// An error that occurred when trying to create a `Query`.
 #[derive(Debug, PartialEq, Eq)]
 pub struct QueryError {
@@ -224,6 +503,26 @@ pub struct QueryError {
     pub kind: QueryErrorKind,
 }
 
+/// A problem with a `Query` that does not prevent it from being created, but that
+/// likely indicates a mistake.
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueryWarning {
+    pub pattern_index: usize,
+    pub row: usize,
+    pub message: String,
+    pub kind: QueryWarningKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum QueryWarningKind {
+    /// The pattern's predicates contradict each other, so it can never match.
+    Unsatisfiable,
+    /// The pattern is identical to an earlier pattern.
+    Duplicate,
+    /// The pattern only matches a subset of the nodes that another pattern matches.
+    Subset,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum QueryErrorKind {
     Syntax,
@@ -239,7 +538,15 @@ pub enum QueryErrorKind {
 enum TextPredicate {
     CaptureEqString(u32, String, bool),
     CaptureEqCapture(u32, u32, bool),
-    CaptureMatchString(u32, regex::bytes::Regex, bool),
+    CaptureEqStringIgnoreCase(u32, String, bool),
+    CaptureEqCaptureIgnoreCase(u32, u32, bool),
+    CaptureMatchString(u32, Box<regex::bytes::Regex>, bool),
+    CaptureGlobString(u32, Vec<char>, bool),
+    // Whether a capture starts at the beginning of a line, optionally after whitespace.
+    CaptureAtLineStart(u32, bool),
+    // Whether a capture has an ancestor, or a parent, of one of the given kinds.
+    CaptureHasAncestor(u32, Vec<u16>),
+    CaptureHasParent(u32, Vec<u16>),
 }
 
 // TODO: Remove this struct at at some point. If `core::str::lossy::Utf8Lossy`
@@ -250,6 +557,21 @@ pub struct LossyUtf8<'a> {
 }
 
 impl Language {
+    /// Create a `Language` from a raw pointer to a `TSLanguage`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must have been returned by a generated parser's `tree_sitter_<name>`
+    /// function, or by [Language::into_raw].
+    pub unsafe fn from_raw(ptr: *const ()) -> Language {
+        Language(ptr as *const ffi::TSLanguage)
+    }
+
+    /// Get the raw pointer to the `TSLanguage` underlying this `Language`.
+    pub fn into_raw(self) -> *const () {
+        self.0 as *const ()
+    }
+
     /// Get the ABI version number that indicates which version of the Tree-sitter CLI
     /// that was used to generate this `Language`.
     #[doc(alias = "ts_language_version")]
@@ -257,6 +579,32 @@ impl Language {
         unsafe { ffi::ts_language_version(self.0) as usize }
     }
 
+    /// Check that this language is compatible with this library, and that its tables
+    /// are internally consistent.
+    ///
+    /// Parsers that are generated by the Tree-sitter CLI always pass this check. It
+    /// allows a host that loads grammars from untrusted or possibly corrupt sources
+    /// to reject a bad grammar before using it, instead of misbehaving during parsing.
+    #[doc(alias = "ts_language_inconsistency")]
+    pub fn validate(&self) -> Result<(), LanguageError> {
+        let version = self.version();
+        if version < MIN_COMPATIBLE_LANGUAGE_VERSION || version > LANGUAGE_VERSION {
+            return Err(LanguageError {
+                version,
+                inconsistency: None,
+            });
+        }
+        let inconsistency = unsafe { ffi::ts_language_inconsistency(self.0) };
+        if inconsistency.is_null() {
+            Ok(())
+        } else {
+            Err(LanguageError {
+                version,
+                inconsistency: Some(unsafe { CStr::from_ptr(inconsistency) }.to_str().unwrap()),
+            })
+        }
+    }
+
     /// Get the number of distinct node types in this language.
     #[doc(alias = "ts_language_symbol_count")]
     pub fn node_kind_count(&self) -> usize {
@@ -300,6 +648,30 @@ impl Language {
         }
     }
 
+    /// Get the keywords of this language, such as `if` or `return`.
+    ///
+    /// Languages don't record which of their tokens are keywords, so these are derived
+    /// from the language's anonymous node kinds: every anonymous kind whose name looks
+    /// like an identifier is considered a keyword. Each keyword appears once, in order
+    /// of its numerical id.
+    pub fn keyword_tokens(&self) -> Vec<&'static str> {
+        let mut result = Vec::new();
+        for id in 0..self.node_kind_count() as u16 {
+            if !self.node_kind_is_visible(id) || self.node_kind_is_named(id) {
+                continue;
+            }
+            if let Some(name) = self.node_kind_for_id(id) {
+                let mut chars = name.chars();
+                let is_word = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+                    && chars.all(|c| c.is_alphanumeric() || c == '_');
+                if is_word && !result.contains(&name) {
+                    result.push(name);
+                }
+            }
+        }
+        result
+    }
+
     /// Get the number of distinct field names in this language.
     #[doc(alias = "ts_language_field_count")]
     pub fn field_count(&self) -> usize {
@@ -334,6 +706,113 @@ impl Language {
             Some(id)
         }
     }
+
+    /// Get the number of valid parse states in this language.
+    #[doc(alias = "ts_language_state_count")]
+    pub fn parse_state_count(&self) -> usize {
+        unsafe { ffi::ts_language_state_count(self.0) as usize }
+    }
+
+    /// Get the parse state that the parser moves to after consuming the given
+    /// symbol in the given parse state.
+    ///
+    /// Returns `0` if the symbol is not valid in that state, or if either of them
+    /// doesn't belong to this language.
+    #[doc(alias = "ts_language_next_state")]
+    pub fn next_state(&self, state: u16, symbol: u16) -> u16 {
+        if state as usize >= self.parse_state_count() || symbol as usize >= self.node_kind_count() {
+            return 0;
+        }
+        unsafe { ffi::ts_language_next_state(self.0, state, symbol) }
+    }
+
+    /// Create a new [LookaheadIterator] over the symbols that are valid in the
+    /// given parse state.
+    ///
+    /// Returns `None` if the state is not valid for this language.
+    #[doc(alias = "ts_lookahead_iterator_new")]
+    pub fn lookahead_iterator(&self, state: u16) -> Option<LookaheadIterator> {
+        let ptr = unsafe { ffi::ts_lookahead_iterator_new(self.0, state) };
+        NonNull::new(ptr).map(|ptr| LookaheadIterator { ptr })
+    }
+}
+
+impl LanguageFn {
+    /// Wrap a function that returns a raw pointer to a `TSLanguage`.
+    ///
+    /// This is typically a `tree_sitter_<name>` symbol that has been looked up in a
+    /// dynamically loaded library. With the `libloading` crate, for example:
+    ///
+    /// ```ignore
+    /// let library = unsafe { libloading::Library::new("libtree-sitter-json.so")? };
+    /// let language_fn = unsafe {
+    ///     let symbol: libloading::Symbol<unsafe extern "C" fn() -> *const ()> =
+    ///         library.get(b"tree_sitter_json")?;
+    ///     LanguageFn::from_raw(*symbol)
+    /// };
+    /// parser.set_language(language_fn.language())?;
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The function must be safe to call at any time, and must return a pointer that
+    /// is valid to pass to [Language::from_raw]. If the function was loaded from a
+    /// library, the library must outlive this value and every `Language` obtained from it.
+    pub unsafe fn from_raw(f: unsafe extern "C" fn() -> *const ()) -> LanguageFn {
+        LanguageFn(f)
+    }
+
+    /// Call the underlying function to get the `Language`.
+    pub fn language(&self) -> Language {
+        unsafe { Language::from_raw((self.0)()) }
+    }
+}
+
+impl From<LanguageFn> for Language {
+    fn from(language_fn: LanguageFn) -> Self {
+        language_fn.language()
+    }
+}
+
+impl KindSet {
+    /// Create a set containing the node kinds of the given language with the given names.
+    ///
+    /// Both named and anonymous node kinds with a given name are included, so a node is in
+    /// the set exactly when its [kind](Node::kind) is one of the given names. Names that
+    /// don't belong to the language are ignored.
+    pub fn new(language: Language, kinds: &[&str]) -> Self {
+        let count = language.node_kind_count();
+        let mut bits = vec![0; count.div_ceil(64)];
+        for id in 0..count as u16 {
+            if let Some(name) = language.node_kind_for_id(id) {
+                if kinds.contains(&name) {
+                    bits[id as usize / 64] |= 1 << (id % 64);
+                }
+            }
+        }
+        KindSet { language, bits }
+    }
+
+    /// Get the language whose node kinds this set contains.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// Check if the node kind with the given numerical id is in this set.
+    ///
+    /// The id must belong to the set's [language](KindSet::language). To check a node
+    /// that may come from a tree of another language, use [KindSet::contains_node].
+    pub fn contains(&self, kind_id: u16) -> bool {
+        self.bits
+            .get(kind_id as usize / 64)
+            .is_some_and(|word| word & (1 << (kind_id % 64)) != 0)
+    }
+
+    /// Check if the given node's kind is in this set. Nodes of other languages are never
+    /// in the set.
+    pub fn contains_node(&self, node: Node) -> bool {
+        node.language() == self.language && self.contains(node.kind_id())
+    }
 }
 
 impl Parser {
@@ -353,17 +832,20 @@ impl Parser {
     /// Tree-sitter CLI. Check the language's version using [Language::version]
     /// and compare it to this library's [LANGUAGE_VERSION](LANGUAGE_VERSION) and
     /// [MIN_COMPATIBLE_LANGUAGE_VERSION](MIN_COMPATIBLE_LANGUAGE_VERSION) constants.
+    /// Languages whose tables are inconsistent are also rejected, as described in
+    /// [Language::validate].
+    ///
+    /// Switching languages resets the parser, but keeps the memory that it has allocated
+    /// for parsing, so a single parser can be reused for documents in several languages
+    /// without reallocating its buffers. Only the language's external scanner is
+    /// recreated, and that is skipped if the language is unchanged.
     #[doc(alias = "ts_parser_set_language")]
     pub fn set_language(&mut self, language: Language) -> Result<(), LanguageError> {
-        let version = language.version();
-        if version < MIN_COMPATIBLE_LANGUAGE_VERSION || version > LANGUAGE_VERSION {
-            Err(LanguageError { version })
-        } else {
-            unsafe {
-                ffi::ts_parser_set_language(self.0.as_ptr(), language.0);
-            }
-            Ok(())
+        language.validate()?;
+        unsafe {
+            ffi::ts_parser_set_language(self.0.as_ptr(), language.0);
         }
+        Ok(())
     }
 
     /// Get the parser's current language.
@@ -454,10 +936,14 @@ impl Parser {
     ///   created, then you must edit `old_tree` to match the new text using
     ///   [Tree::edit].
     ///
+    /// A byte order mark at the start of the text is skipped, and isn't included in any
+    /// node. See [strip_bom] for removing it before parsing instead.
+    ///
     /// Returns a [Tree] if parsing succeeded, or `None` if:
     ///  * The parser has not yet had a language assigned with [Parser::set_language]
     ///  * The timeout set with [Parser::set_timeout_micros] expired
     ///  * The cancellation flag set with [Parser::set_cancellation_flag] was flipped
+    ///  * The parse stack grew deeper than the limit set with [Parser::set_max_stack_depth]
     #[doc(alias = "ts_parser_parse")]
     pub fn parse(&mut self, text: impl AsRef<[u8]>, old_tree: Option<&Tree>) -> Option<Tree> {
         let bytes = text.as_ref();
@@ -489,6 +975,164 @@ impl Parser {
         )
     }
 
+    /// Parse text whose encoding is not known, detecting whether it is UTF8 or UTF16
+    /// and calling [Parser::parse] or [Parser::parse_utf16] accordingly.
+    ///
+    /// The encoding is determined by the text's byte order mark, if it has one.
+    /// Otherwise, the text is treated as UTF16 if its length is even, more than half of
+    /// its code units have a zero high byte, and fewer of them have a zero low byte, as
+    /// is the case for mostly-ASCII UTF16 text. It is treated as UTF8 in all other cases. When UTF16 text has an odd length, its last
+    /// byte is ignored.
+    ///
+    /// The byte order mark is kept in the parsed text, so the positions in the
+    /// resulting tree are byte offsets into the given bytes in either encoding.
+    ///
+    /// Returns the tree along with the detected encoding, or `None` in the same cases
+    /// as [Parser::parse].
+    pub fn parse_detect_encoding(
+        &mut self,
+        bytes: &[u8],
+        old_tree: Option<&Tree>,
+    ) -> Option<(Tree, InputEncoding)> {
+        let encoding = detect_encoding(bytes);
+        let tree = match encoding {
+            InputEncoding::Utf8 => self.parse(bytes, old_tree)?,
+            InputEncoding::Utf16LE | InputEncoding::Utf16BE => {
+                let code_units = bytes
+                    .chunks_exact(2)
+                    .map(|pair| {
+                        let (low, high) = if encoding == InputEncoding::Utf16LE {
+                            (pair[0], pair[1])
+                        } else {
+                            (pair[1], pair[0])
+                        };
+                        (high as u16) << 8 | low as u16
+                    })
+                    .collect::<Vec<_>>();
+                self.parse_utf16(&code_units, old_tree)?
+            }
+        };
+        Some((tree, encoding))
+    }
+
+    /// Parse the contents of a memory-mapped file.
+    ///
+    /// This is equivalent to calling [Parser::parse] with the mapped bytes, and
+    /// avoids reading large files into memory up front. The mapping must not be
+    /// modified while the parse is running. To reparse incrementally after the
+    /// file has changed, edit `old_tree` with [Tree::edit] and pass a new mapping
+    /// of the updated file.
+    ///
+    /// This method is only available when the `memmap2` feature is enabled.
+    #[cfg(feature = "memmap2")]
+    pub fn parse_mmap(&mut self, mmap: &memmap2::Mmap, old_tree: Option<&Tree>) -> Option<Tree> {
+        self.parse(&mmap[..], old_tree)
+    }
+
+    /// Parse UTF8 text that arrives progressively from a reader, such as a pipe.
+    ///
+    /// Whenever a read returns more text, the text received so far is parsed again,
+    /// reusing the tree from the previous read, and `on_partial_tree` is called with
+    /// the new tree and the text that it was parsed from. Reads may block until more
+    /// text is available.
+    ///
+    /// Returns the tree for the complete text once the reader reaches the end of its
+    /// input, or `None` if one of the parses was halted, for the same reasons as
+    /// [Parser::parse].
+    pub fn parse_incremental_stream(
+        &mut self,
+        mut reader: impl io::Read,
+        mut on_partial_tree: impl FnMut(&Tree, &[u8]),
+    ) -> io::Result<Option<Tree>> {
+        let mut text = Vec::new();
+        let mut end_position = Point::new(0, 0);
+        let mut tree: Option<Tree> = None;
+        let mut chunk = [0; 4096];
+        loop {
+            let count = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(count) => count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+
+            // Treat the new text as an insertion at the end of the document.
+            let start_byte = text.len();
+            let start_position = end_position;
+            text.extend_from_slice(&chunk[..count]);
+            for byte in &chunk[..count] {
+                if *byte == b'\n' {
+                    end_position.row += 1;
+                    end_position.column = 0;
+                } else {
+                    end_position.column += 1;
+                }
+            }
+            if let Some(tree) = tree.as_mut() {
+                tree.edit(&InputEdit {
+                    start_byte,
+                    old_end_byte: start_byte,
+                    new_end_byte: text.len(),
+                    start_position,
+                    old_end_position: start_position,
+                    new_end_position: end_position,
+                });
+            }
+
+            match self.parse(&text, tree.as_ref()) {
+                Some(new_tree) => {
+                    on_partial_tree(&new_tree, &text);
+                    tree = Some(new_tree);
+                }
+                None => return Ok(None),
+            }
+        }
+
+        // If the reader produced no text at all, parse the empty document.
+        if tree.is_none() {
+            tree = self.parse(&text, None);
+        }
+        Ok(tree)
+    }
+
+    /// Parse UTF8 text that is stored in pieces, such as in a rope.
+    ///
+    /// The parser requests text from `chunks` as it needs it, so the pieces are never
+    /// copied into a single buffer. This is equivalent to calling [Parser::parse_with]
+    /// with a callback that calls [Chunks::chunk_at].
+    ///
+    /// # Arguments:
+    /// * `chunks` The text to parse.
+    /// * `old_tree` A previous syntax tree parsed from the same document.
+    ///   If the text of the document has changed since `old_tree` was
+    ///   created, then you must edit `old_tree` to match the new text using
+    ///   [Tree::edit].
+    pub fn parse_chunks(&mut self, chunks: &impl Chunks, old_tree: Option<&Tree>) -> Option<Tree> {
+        self.parse_with(&mut |byte, _| chunks.chunk_at(byte), old_tree)
+    }
+
+    /// Parse a slice of UTF8 text, and return the tokens of the resulting syntax tree
+    /// as a flat stream, in document order.
+    ///
+    /// The tokens are the [leaves](Node::leaves) of the tree, so they include extra
+    /// tokens such as comments, and the tokens that were skipped during error recovery.
+    /// *Missing* tokens, which don't appear in the text, are omitted.
+    ///
+    /// Returns `None` in the same cases as [Parser::parse].
+    pub fn tokenize(&mut self, text: impl AsRef<[u8]>) -> Option<Vec<Token>> {
+        let tree = self.parse(text, None)?;
+        let root = tree.root_node();
+        let tokens = root
+            .leaves()
+            .filter(|node| !node.is_missing())
+            .map(|node| Token {
+                kind: node.kind(),
+                range: node.range(),
+            })
+            .collect();
+        Some(tokens)
+    }
+
     /// Parse UTF8 text provided in chunks by a callback.
     ///
     /// # Arguments:
@@ -535,7 +1179,7 @@ impl Parser {
         let c_old_tree = old_tree.map_or(ptr::null_mut(), |t| t.0.as_ptr());
         unsafe {
             let c_new_tree = ffi::ts_parser_parse(self.0.as_ptr(), c_old_tree, c_input);
-            NonNull::new(c_new_tree).map(Tree)
+            NonNull::new(c_new_tree).map(Tree::new)
         }
     }
 
@@ -591,7 +1235,7 @@ impl Parser {
         let c_old_tree = old_tree.map_or(ptr::null_mut(), |t| t.0.as_ptr());
         unsafe {
             let c_new_tree = ffi::ts_parser_parse(self.0.as_ptr(), c_old_tree, c_input);
-            NonNull::new(c_new_tree).map(Tree)
+            NonNull::new(c_new_tree).map(Tree::new)
         }
     }
 
@@ -624,6 +1268,46 @@ impl Parser {
         unsafe { ffi::ts_parser_set_timeout_micros(self.0.as_ptr(), timeout_micros) }
     }
 
+    /// Get the maximum depth that the parse stack is allowed to reach.
+    ///
+    /// This is set via [set_max_stack_depth](Parser::set_max_stack_depth).
+    #[doc(alias = "ts_parser_max_stack_depth")]
+    pub fn max_stack_depth(&self) -> usize {
+        unsafe { ffi::ts_parser_max_stack_depth(self.0.as_ptr()) as usize }
+    }
+
+    /// Set the maximum depth that the parse stack is allowed to reach before
+    /// halting.
+    ///
+    /// Deeply nested input requires a correspondingly deep parse stack. If the
+    /// stack grows deeper than this, parsing will halt early, returning `None`,
+    /// and the parser is reset so that the next parse starts from scratch.
+    /// A limit of zero, which is the default, means that the depth is unbounded.
+    #[doc(alias = "ts_parser_set_max_stack_depth")]
+    pub fn set_max_stack_depth(&mut self, max_stack_depth: usize) {
+        unsafe { ffi::ts_parser_set_max_stack_depth(self.0.as_ptr(), max_stack_depth as u32) }
+    }
+
+    /// Get statistics about the most recent parse.
+    ///
+    /// Returns `None` if this parser has not yet completed a parse, or if the
+    /// most recent parse was halted before it finished.
+    #[doc(alias = "ts_parser_last_parse_stats")]
+    pub fn last_parse_stats(&self) -> Option<ParseStats> {
+        let mut stats = MaybeUninit::<ffi::TSParseStats>::uninit();
+        if unsafe { ffi::ts_parser_last_parse_stats(self.0.as_ptr(), stats.as_mut_ptr()) } {
+            let stats = unsafe { stats.assume_init() };
+            Some(ParseStats {
+                bytes_parsed: stats.bytes_parsed as usize,
+                token_count: stats.token_count as usize,
+                reduction_count: stats.reduction_count as usize,
+                had_errors: stats.had_errors,
+            })
+        } else {
+            None
+        }
+    }
+
     /// Set the ranges of text that the parser should include when parsing.
     ///
     /// By default, the parser will always include entire documents. This function
@@ -693,6 +1377,56 @@ impl Parser {
     }
 }
 
+impl ParserBuilder {
+    /// Create a builder for a parser with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the language that the parser should use for parsing.
+    ///
+    /// See [Parser::set_language].
+    pub fn language(mut self, language: Language) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    /// Set the maximum duration that parsing should be allowed to take before
+    /// halting.
+    ///
+    /// See [Parser::set_timeout_micros].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_micros = timeout.as_micros() as u64;
+        self
+    }
+
+    /// Set the ranges of text that the parser should include when parsing.
+    ///
+    /// See [Parser::set_included_ranges].
+    pub fn included_ranges(mut self, ranges: &[Range]) -> Self {
+        self.included_ranges = ranges.to_vec();
+        self
+    }
+
+    /// Create a `Parser` with this configuration.
+    ///
+    /// Returns an error if the language is incompatible with this version of
+    /// Tree-sitter, or if the included ranges are not ordered and disjoint.
+    pub fn build(&self) -> Result<Parser, ParserBuilderError> {
+        let mut parser = Parser::new();
+        if let Some(language) = self.language {
+            parser
+                .set_language(language)
+                .map_err(ParserBuilderError::Language)?;
+        }
+        parser.set_timeout_micros(self.timeout_micros);
+        parser
+            .set_included_ranges(&self.included_ranges)
+            .map_err(ParserBuilderError::IncludedRanges)?;
+        Ok(parser)
+    }
+}
+
 impl Drop for Parser {
     fn drop(&mut self) {
         self.stop_printing_dot_graphs();
@@ -702,10 +1436,17 @@ impl Drop for Parser {
 }
 
 impl Tree {
+    // The root node is cached so that retrieving it doesn't require a call into the
+    // C library. It must be refreshed whenever the tree is edited.
+    fn new(ptr: NonNull<ffi::TSTree>) -> Self {
+        let root = unsafe { ffi::ts_tree_root_node(ptr.as_ptr()) };
+        Tree(ptr, root)
+    }
+
     /// Get the root node of the syntax tree.
     #[doc(alias = "ts_tree_root_node")]
     pub fn root_node(&self) -> Node {
-        Node::new(unsafe { ffi::ts_tree_root_node(self.0.as_ptr()) }).unwrap()
+        Node(self.1, PhantomData)
     }
 
     /// Get the root node of the syntax tree, but with its position shifted
@@ -728,20 +1469,173 @@ impl Tree {
         Language(unsafe { ffi::ts_tree_language(self.0.as_ptr()) })
     }
 
-    /// Edit the syntax tree to keep it in sync with source code that has been
-    /// edited.
+    /// Get the included ranges that were used to parse the syntax tree.
     ///
-    /// You must describe the edit both in terms of byte offsets and in terms of
-    /// row/column coordinates.
-    #[doc(alias = "ts_tree_edit")]
-    pub fn edit(&mut self, edit: &InputEdit) {
-        let edit = edit.into();
-        unsafe { ffi::ts_tree_edit(self.0.as_ptr(), &edit) };
-    }
-
-    /// Create a new [TreeCursor] starting from the root of the tree.
-    pub fn walk(&self) -> TreeCursor {
-        self.root_node().walk()
+    /// See [Parser::set_included_ranges].
+    #[doc(alias = "ts_tree_included_ranges")]
+    pub fn included_ranges(&self) -> Vec<Range> {
+        tree_included_ranges(self.0.as_ptr())
+    }
+
+    /// Serialize the syntax tree to a compact binary format, so that it can be cached
+    /// and later restored using [Tree::deserialize] without reparsing.
+    ///
+    /// The data contains the tree's nodes, their positions and its included ranges,
+    /// as well as the state of the language's external scanner, so the restored tree
+    /// can be used for incremental parsing. It does not contain the source code.
+    #[doc(alias = "ts_tree_serialize")]
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut length = 0u32;
+        unsafe {
+            let ptr = ffi::ts_tree_serialize(self.0.as_ptr(), &mut length as *mut u32);
+            util::CBufferIter::new(ptr as *mut u8, length as usize).collect()
+        }
+    }
+
+    /// Restore a syntax tree from data that was produced by [Tree::serialize].
+    ///
+    /// The language must be the one that was used to parse the original tree. Returns
+    /// an error if the data is not a valid serialized tree for that language.
+    #[doc(alias = "ts_tree_deserialize")]
+    pub fn deserialize(bytes: &[u8], language: Language) -> Result<Tree, TreeDeserializationError> {
+        let length = u32::try_from(bytes.len()).map_err(|_| TreeDeserializationError)?;
+        let ptr = unsafe {
+            ffi::ts_tree_deserialize(bytes.as_ptr() as *const c_char, length, language.0)
+        };
+        NonNull::new(ptr)
+            .map(Tree::new)
+            .ok_or(TreeDeserializationError)
+    }
+
+    /// Edit the syntax tree to keep it in sync with source code that has been
+    /// edited.
+    ///
+    /// You must describe the edit both in terms of byte offsets and in terms of
+    /// row/column coordinates.
+    #[doc(alias = "ts_tree_edit")]
+    pub fn edit(&mut self, edit: &InputEdit) {
+        let edit = edit.into();
+        unsafe {
+            ffi::ts_tree_edit(self.0.as_ptr(), &edit);
+            self.1 = ffi::ts_tree_root_node(self.0.as_ptr());
+        }
+    }
+
+    /// Edit the syntax tree like [Tree::edit], but first check that the edit is
+    /// consistent with the tree.
+    ///
+    /// Returns an error, leaving the tree unchanged, if the edit's start position
+    /// is after its old or new end position, or if its old end position is beyond
+    /// the end of the tree, either in terms of bytes or of rows and columns.
+    pub fn try_edit(&mut self, edit: &InputEdit) -> Result<(), EditError> {
+        if edit.start_byte > edit.old_end_byte
+            || edit.start_byte > edit.new_end_byte
+            || edit.start_position > edit.old_end_position
+            || edit.start_position > edit.new_end_position
+        {
+            return Err(EditError::InvalidRange);
+        }
+
+        let root = self.root_node();
+        let end_byte = root.end_byte();
+        let end_position = root.end_position();
+        if edit.old_end_byte > end_byte || edit.old_end_position > end_position {
+            return Err(EditError::OutOfBounds {
+                end_byte,
+                end_position,
+            });
+        }
+
+        self.edit(edit);
+        Ok(())
+    }
+
+    /// Create a new [TreeCursor] starting from the root of the tree.
+    pub fn walk(&self) -> TreeCursor {
+        self.root_node().walk()
+    }
+
+    /// Iterate over all of the nodes in the tree in pre-order, starting with the root
+    /// node. Each node is paired with its depth, which is zero for the root node.
+    pub fn preorder(&self) -> impl Iterator<Item = (Node<'_>, u32)> {
+        let mut cursor = self.walk();
+        let mut depth = 0;
+        let mut done = false;
+        iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let result = (cursor.node(), depth);
+
+            // Advance the cursor to the next node, stopping once it returns to the root.
+            if cursor.goto_first_child() {
+                depth += 1;
+            } else {
+                while !cursor.goto_next_sibling() {
+                    if !cursor.goto_parent() {
+                        done = true;
+                        break;
+                    }
+                    depth -= 1;
+                }
+            }
+            Some(result)
+        })
+    }
+
+    /// Iterate over all of the nodes in the tree that are *extra*, such as comments,
+    /// in the order that they appear in the document.
+    ///
+    /// Extra nodes can appear anywhere in the tree, so this visits every node. See
+    /// [Node::is_extra].
+    pub fn extras(&self) -> impl Iterator<Item = Node<'_>> {
+        self.preorder()
+            .map(|(node, _)| node)
+            .filter(|node| node.is_extra())
+    }
+
+    /// Get the named nodes that start on the given row, in pre-order.
+    ///
+    /// Nodes that span several lines are included if they start on the given row, and
+    /// ancestors of these nodes are included if they start on the same row. Only the
+    /// parts of the tree that span the given row are visited.
+    pub fn nodes_on_line(&self, row: usize) -> Vec<Node<'_>> {
+        let mut result = Vec::new();
+        let mut cursor = self.walk();
+        'outer: loop {
+            let node = cursor.node();
+            let start_row = node.start_position().row;
+            if start_row > row {
+                // The remaining siblings all start after the row, so move on from the parent.
+                if !cursor.goto_parent() {
+                    break;
+                }
+            } else {
+                if start_row == row && node.is_named() {
+                    result.push(node);
+                }
+                if node.end_position().row >= row && cursor.goto_first_child() {
+                    continue;
+                }
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'outer;
+                }
+            }
+        }
+        result
+    }
+
+    /// Create a shallow copy of the syntax tree. This is equivalent to [Tree::clone].
+    ///
+    /// This is very fast, and it doesn't depend on the size of the tree: the copy
+    /// shares its structure with the original tree. You need a copy of a tree in
+    /// order to use it on more than one thread at a time, as syntax trees are not
+    /// thread safe.
+    #[doc(alias = "ts_tree_copy")]
+    pub fn copy(&self) -> Tree {
+        self.clone()
     }
 
     /// Compare this old edited syntax tree to a new syntax tree representing the same
@@ -763,6 +1657,64 @@ impl Tree {
             util::CBufferIter::new(ptr, count as usize).map(|r| r.into())
         }
     }
+
+    /// Write a human-readable description of the changes between this old edited
+    /// syntax tree and a new syntax tree, for debugging incremental parsing.
+    ///
+    /// Each range returned by [Tree::changed_ranges] is written on its own line,
+    /// along with the kinds of the smallest nodes that cover it in the old tree
+    /// and in the new tree.
+    pub fn print_changes<W: io::Write>(&self, other: &Tree, writer: &mut W) -> io::Result<()> {
+        let old_root = self.root_node();
+        let new_root = other.root_node();
+        for range in self.changed_ranges(other) {
+            let old_node = old_root
+                .descendant_for_byte_range(range.start_byte, range.end_byte)
+                .unwrap_or(old_root);
+            let new_node = new_root
+                .descendant_for_byte_range(range.start_byte, range.end_byte)
+                .unwrap_or(new_root);
+            writeln!(
+                writer,
+                "{} - {}: {} -> {}",
+                range.start_point,
+                range.end_point,
+                old_node.kind(),
+                new_node.kind()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Estimate how much of this new syntax tree was reused from the given old tree,
+    /// which must have been edited and passed to [Parser::parse] to produce this tree.
+    ///
+    /// The bytes covered by the [changed ranges](Tree::changed_ranges) between the two
+    /// trees are counted as reparsed, and the rest of this tree's bytes as reused.
+    pub fn reuse_stats(&self, old: &Tree) -> ReuseStats {
+        let total_bytes = self.root_node().end_byte();
+        let reparsed_bytes = old
+            .changed_ranges(self)
+            .map(|range| range.end_byte - range.start_byte)
+            .sum::<usize>()
+            .min(total_bytes);
+        ReuseStats {
+            reused_bytes: total_bytes - reparsed_bytes,
+            reparsed_bytes,
+        }
+    }
+}
+
+impl ReuseStats {
+    /// Get the fraction of the tree's bytes that were reused, between `0.0` and `1.0`.
+    pub fn reuse_ratio(&self) -> f64 {
+        let total_bytes = self.reused_bytes + self.reparsed_bytes;
+        if total_bytes == 0 {
+            1.0
+        } else {
+            self.reused_bytes as f64 / total_bytes as f64
+        }
+    }
 }
 
 impl fmt::Debug for Tree {
@@ -778,8 +1730,78 @@ impl Drop for Tree {
 }
 
 impl Clone for Tree {
+    /// Create a shallow copy of the syntax tree in constant time, sharing its
+    /// structure with the original tree.
+    #[doc(alias = "ts_tree_copy")]
     fn clone(&self) -> Tree {
-        unsafe { Tree(NonNull::new_unchecked(ffi::ts_tree_copy(self.0.as_ptr()))) }
+        unsafe { Tree::new(NonNull::new_unchecked(ffi::ts_tree_copy(self.0.as_ptr()))) }
+    }
+}
+
+impl TreeHistory {
+    /// Create a history whose only snapshot is the given tree.
+    ///
+    /// At most `capacity` snapshots are kept, including the current one. When the
+    /// history is full, the oldest snapshot is discarded.
+    pub fn new(tree: Tree, capacity: usize) -> Self {
+        let mut snapshots = VecDeque::new();
+        snapshots.push_back((tree, None));
+        TreeHistory {
+            snapshots,
+            current: 0,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Get the tree for the current snapshot.
+    pub fn current(&self) -> &Tree {
+        &self.snapshots[self.current].0
+    }
+
+    /// Get the edit that led from the previous snapshot to the current one, or `None` if
+    /// the current snapshot is the oldest one.
+    pub fn current_edit(&self) -> Option<&InputEdit> {
+        self.snapshots[self.current].1.as_ref()
+    }
+
+    /// Record a new snapshot, produced by applying the given edit to the current one.
+    ///
+    /// Any snapshots that were undone are discarded, so they can no longer be redone.
+    pub fn push(&mut self, edit: InputEdit, tree: Tree) {
+        self.snapshots.truncate(self.current + 1);
+        self.snapshots.push_back((tree, Some(edit)));
+        if self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+
+            // The edit that led to the new oldest snapshot can no longer be undone.
+            if let Some(front) = self.snapshots.front_mut() {
+                front.1 = None;
+            }
+        }
+        self.current = self.snapshots.len() - 1;
+    }
+
+    /// Move back to the previous snapshot, returning its tree, or `None` if there is
+    /// nothing to undo.
+    ///
+    /// The edit that is being undone is available from [TreeHistory::current_edit]
+    /// before calling this method.
+    pub fn undo(&mut self) -> Option<&Tree> {
+        if self.current == 0 {
+            return None;
+        }
+        self.current -= 1;
+        Some(self.current())
+    }
+
+    /// Move forward to the next snapshot, returning its tree, or `None` if there is
+    /// nothing to redo.
+    pub fn redo(&mut self) -> Option<&Tree> {
+        if self.current + 1 >= self.snapshots.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.current())
     }
 }
 
@@ -808,6 +1830,15 @@ impl<'tree> Node<'tree> {
         unsafe { ffi::ts_node_symbol(self.0) }
     }
 
+    /// Get this node's symbol as a numerical id, as it appears in the grammar.
+    ///
+    /// Unlike [Node::kind_id], this ignores aliases: an aliased node has the
+    /// `kind_id` of its alias, but the `symbol` of the rule that produced it.
+    #[doc(alias = "ts_node_grammar_symbol")]
+    pub fn symbol(&self) -> u16 {
+        unsafe { ffi::ts_node_grammar_symbol(self.0) }
+    }
+
     /// Get this node's type as a string.
     #[doc(alias = "ts_node_type")]
     pub fn kind(&self) -> &'static str {
@@ -841,6 +1872,10 @@ impl<'tree> Node<'tree> {
     }
 
     /// Check if this node has been edited.
+    ///
+    /// After a call to [Tree::edit], this returns `true` for the nodes that were affected by
+    /// the edit and for all of their ancestors. When walking the edited tree, any node for
+    /// which this returns `false` can be skipped, along with all of its descendants.
     #[doc(alias = "ts_node_has_changes")]
     pub fn has_changes(&self) -> bool {
         unsafe { ffi::ts_node_has_changes(self.0) }
@@ -870,6 +1905,21 @@ impl<'tree> Node<'tree> {
         unsafe { ffi::ts_node_is_missing(self.0) }
     }
 
+    /// Check how this node was produced during error recovery.
+    ///
+    /// When the parser skips some unexpected input, it wraps the skipped tokens in an
+    /// [error](Node::is_error) node, and when it inserts a token that is expected but
+    /// absent, it produces a [missing](Node::is_missing) node.
+    pub fn recovery_kind(&self) -> RecoveryKind {
+        if self.is_missing() {
+            RecoveryKind::Missing
+        } else if self.is_error() {
+            RecoveryKind::Skipped
+        } else {
+            RecoveryKind::None
+        }
+    }
+
     /// Get the byte offsets where this node starts.
     #[doc(alias = "ts_node_start_byte")]
     pub fn start_byte(&self) -> usize {
@@ -887,6 +1937,16 @@ impl<'tree> Node<'tree> {
         self.start_byte()..self.end_byte()
     }
 
+    /// Get the range of source code that this node represents, measured in UTF16 code
+    /// units rather than bytes.
+    ///
+    /// The given `source` must be the UTF8 text that was parsed. It is scanned from the
+    /// beginning on every call, so this takes time proportional to the node's end byte.
+    pub fn utf16_range(&self, source: &[u8]) -> std::ops::Range<usize> {
+        let start = utf16_len(&source[..self.start_byte()]);
+        start..start + utf16_len(&source[self.start_byte()..self.end_byte()])
+    }
+
     /// Get the range of source code that this node represents, both in terms of raw bytes
     /// and of row/column coordinates.
     pub fn range(&self) -> Range {
@@ -923,12 +1983,51 @@ impl<'tree> Node<'tree> {
         Self::new(unsafe { ffi::ts_node_child(self.0, i as u32) })
     }
 
+    /// Get the node's child at the given index like [Node::child], but report why
+    /// there is no usable child at that index.
+    ///
+    /// Returns an error if the index is not less than the node's [child
+    /// count](Node::child_count), or if the child at that index is a *missing* node
+    /// that the parser inserted to recover from an error.
+    pub fn try_child(&self, i: usize) -> Result<Self, ChildError> {
+        match self.child(i) {
+            Some(child) if child.is_missing() => Err(ChildError::Missing { index: i }),
+            Some(child) => Ok(child),
+            None => Err(ChildError::OutOfRange {
+                index: i,
+                child_count: self.child_count(),
+            }),
+        }
+    }
+
     /// Get this node's number of children.
+    ///
+    /// This includes any *missing* children, which the parser inserted to recover
+    /// from an error and which take up no space in the source. See
+    /// [Node::child_count_excluding_missing].
     #[doc(alias = "ts_node_child_count")]
     pub fn child_count(&self) -> usize {
         unsafe { ffi::ts_node_child_count(self.0) as usize }
     }
 
+    /// Get this node's number of children, not counting any *missing* children that
+    /// the parser inserted to recover from an error. See [Node::is_missing].
+    pub fn child_count_excluding_missing(&self) -> usize {
+        let mut cursor = self.walk();
+        let mut count = 0;
+        if cursor.goto_first_child() {
+            loop {
+                if !cursor.node().is_missing() {
+                    count += 1;
+                }
+                if !cursor.goto_next_sibling() {
+                    break;
+                }
+            }
+        }
+        count
+    }
+
     /// Get this node's *named* child at the given index.
     ///
     /// See also [Node::is_named].
@@ -1008,6 +2107,40 @@ impl<'tree> Node<'tree> {
         })
     }
 
+    /// Collect this node's children into the given buffer, replacing its previous
+    /// contents, and return them as a slice.
+    ///
+    /// Unlike collecting the iterator returned by [Node::children] into a new `Vec`,
+    /// this lets you reuse the same buffer when visiting many nodes. The children are
+    /// found with a cursor that is kept for each thread and reused on every call.
+    pub fn collect_children<'a>(&self, buf: &'a mut Vec<Node<'tree>>) -> &'a [Node<'tree>] {
+        buf.clear();
+        CHILDREN_CURSOR.with(|cursor| {
+            let mut cursor = cursor.borrow_mut();
+            unsafe {
+                let cursor = match cursor.as_mut() {
+                    Some(cursor) => {
+                        ffi::ts_tree_cursor_reset(&mut cursor.0, self.0);
+                        cursor
+                    }
+                    None => cursor.insert(TreeCursor(ffi::ts_tree_cursor_new(self.0), PhantomData)),
+                };
+                if ffi::ts_tree_cursor_goto_first_child(&mut cursor.0) {
+                    loop {
+                        buf.push(Node(
+                            ffi::ts_tree_cursor_current_node(&cursor.0),
+                            PhantomData,
+                        ));
+                        if !ffi::ts_tree_cursor_goto_next_sibling(&mut cursor.0) {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        buf
+    }
+
     /// Iterate over this node's named children.
     ///
     /// See also [Node::children].
@@ -1029,6 +2162,68 @@ impl<'tree> Node<'tree> {
         })
     }
 
+    /// Iterate over all of this node's named descendants, not including the node
+    /// itself, in document order.
+    ///
+    /// See also [Node::named_children].
+    pub fn named_descendants(&self) -> impl Iterator<Item = Node<'tree>> {
+        self.descendants_where(|node| node.is_named())
+    }
+
+    /// Get this node's named descendant with the given index, numbering the named
+    /// descendants in pre-order from zero, and not counting the node itself.
+    ///
+    /// This is the same node as `self.named_descendants().nth(n)`. It gives a simple way
+    /// to refer to a node, such as in tests, that is stable as long as the tree's
+    /// structure doesn't change.
+    pub fn nth_named_descendant(&self, n: usize) -> Option<Node<'tree>> {
+        self.named_descendants().nth(n)
+    }
+
+    /// Iterate over all of this node's descendants whose kinds are in the given set, not
+    /// including the node itself, in document order.
+    pub fn descendants_matching<'a>(
+        &self,
+        kinds: &'a KindSet,
+    ) -> impl Iterator<Item = Node<'tree>> + 'a
+    where
+        'tree: 'a,
+    {
+        self.descendants_where(move |node| kinds.contains_node(*node))
+    }
+
+    fn descendants_where<'a>(
+        &self,
+        mut predicate: impl FnMut(&Node<'tree>) -> bool + 'a,
+    ) -> impl Iterator<Item = Node<'tree>> + 'a
+    where
+        'tree: 'a,
+    {
+        let mut cursor = self.walk();
+        let mut done = !cursor.goto_first_child();
+        iter::from_fn(move || {
+            while !done {
+                let node = cursor.node();
+
+                // Advance the cursor to the next node in document order, stopping once
+                // it returns to the node where it started.
+                if !cursor.goto_first_child() {
+                    while !cursor.goto_next_sibling() {
+                        if !cursor.goto_parent() {
+                            done = true;
+                            break;
+                        }
+                    }
+                }
+
+                if predicate(&node) {
+                    return Some(node);
+                }
+            }
+            None
+        })
+    }
+
     /// Iterate over this node's children with a given field name.
     ///
     /// See also [Node::children].
@@ -1075,6 +2270,36 @@ impl<'tree> Node<'tree> {
         Self::new(unsafe { ffi::ts_node_parent(self.0) })
     }
 
+    /// Get this node's nearest ancestor of the given kind, not including the node
+    /// itself.
+    pub fn parent_until(&self, kind: &str) -> Option<Self> {
+        let mut node = self.parent();
+        while let Some(parent) = node {
+            if parent.kind() == kind {
+                return Some(parent);
+            }
+            node = parent.parent();
+        }
+        None
+    }
+
+    /// Walk up from this node's parent for as long as the given predicate returns
+    /// `true`, and get the outermost ancestor that was reached.
+    ///
+    /// Returns `None` if the predicate returns `false` for this node's parent.
+    pub fn parent_while(&self, mut predicate: impl FnMut(&Node<'tree>) -> bool) -> Option<Self> {
+        let mut result = None;
+        let mut node = self.parent();
+        while let Some(parent) = node {
+            if !predicate(&parent) {
+                break;
+            }
+            result = Some(parent);
+            node = parent.parent();
+        }
+        result
+    }
+
     /// Get this node's next sibling.
     #[doc(alias = "ts_node_next_sibling")]
     pub fn next_sibling(&self) -> Option<Self> {
@@ -1087,6 +2312,26 @@ impl<'tree> Node<'tree> {
         Self::new(unsafe { ffi::ts_node_prev_sibling(self.0) })
     }
 
+    /// Get this node's next sibling that is not an [extra](Node::is_extra) node,
+    /// such as a comment.
+    pub fn next_sibling_skipping_extras(&self) -> Option<Self> {
+        let mut sibling = self.next_sibling()?;
+        while sibling.is_extra() {
+            sibling = sibling.next_sibling()?;
+        }
+        Some(sibling)
+    }
+
+    /// Get this node's previous sibling that is not an [extra](Node::is_extra) node,
+    /// such as a comment.
+    pub fn prev_sibling_skipping_extras(&self) -> Option<Self> {
+        let mut sibling = self.prev_sibling()?;
+        while sibling.is_extra() {
+            sibling = sibling.prev_sibling()?;
+        }
+        Some(sibling)
+    }
+
     /// Get this node's next named sibling.
     #[doc(alias = "ts_node_next_named_sibling")]
     pub fn next_named_sibling(&self) -> Option<Self> {
@@ -1099,6 +2344,21 @@ impl<'tree> Node<'tree> {
         Self::new(unsafe { ffi::ts_node_prev_named_sibling(self.0) })
     }
 
+    /// Get this node's first child that extends beyond the given byte offset.
+    ///
+    /// This is equivalent to [TreeCursor::goto_first_child_for_byte], without
+    /// needing to create a cursor.
+    #[doc(alias = "ts_node_first_child_for_byte")]
+    pub fn first_child_for_byte(&self, byte: usize) -> Option<Self> {
+        Self::new(unsafe { ffi::ts_node_first_child_for_byte(self.0, byte as u32) })
+    }
+
+    /// Get this node's first *named* child that extends beyond the given byte offset.
+    #[doc(alias = "ts_node_first_named_child_for_byte")]
+    pub fn first_named_child_for_byte(&self, byte: usize) -> Option<Self> {
+        Self::new(unsafe { ffi::ts_node_first_named_child_for_byte(self.0, byte as u32) })
+    }
+
     /// Get the smallest node within this node that spans the given range.
     #[doc(alias = "ts_node_descendant_for_byte_range")]
     pub fn descendant_for_byte_range(&self, start: usize, end: usize) -> Option<Self> {
@@ -1107,6 +2367,31 @@ impl<'tree> Node<'tree> {
         })
     }
 
+    /// Get the chain of nodes from this node down to the smallest node within it that
+    /// spans the given range, which is the one returned by
+    /// [Node::descendant_for_byte_range].
+    ///
+    /// The path starts with this node and ends with that descendant, and each node in
+    /// it is the parent of the next one. If this node is itself the smallest node that
+    /// spans the range, the path only contains this node.
+    pub fn path_to_descendant_for_byte_range(&self, start: usize, end: usize) -> Vec<Self> {
+        let mut result = Vec::new();
+        let mut node = match self.descendant_for_byte_range(start, end) {
+            Some(node) => node,
+            None => return result,
+        };
+        result.push(node);
+        while node != *self {
+            match node.parent() {
+                Some(parent) => node = parent,
+                None => break,
+            }
+            result.push(node);
+        }
+        result.reverse();
+        result
+    }
+
     /// Get the smallest named node within this node that spans the given range.
     #[doc(alias = "ts_node_named_descendant_for_byte_range")]
     pub fn named_descendant_for_byte_range(&self, start: usize, end: usize) -> Option<Self> {
@@ -1142,6 +2427,60 @@ impl<'tree> Node<'tree> {
         result
     }
 
+    /// Format this node as an S-expression like [Node::to_sexp], but with the source text
+    /// of each leaf node written after it, as a quoted string like `(identifier) "foo"`.
+    ///
+    /// Special characters in the text, such as newlines, are escaped.
+    pub fn to_sexp_with_text(&self, source: &[u8]) -> String {
+        fn write_node(cursor: &mut TreeCursor, source: &[u8], is_root: bool, result: &mut String) {
+            let node = cursor.node();
+            let is_visible = node.is_named() || node.is_missing();
+            if is_visible {
+                if !is_root {
+                    result.push(' ');
+                    if let Some(field_name) = cursor.field_name() {
+                        result.push_str(field_name);
+                        result.push_str(": ");
+                    }
+                }
+                if node.is_missing() {
+                    if node.is_named() {
+                        result.push_str(&format!("(MISSING {}", node.kind()));
+                    } else {
+                        result.push_str(&format!("(MISSING \"{}\"", node.kind()));
+                    }
+                } else {
+                    result.push('(');
+                    result.push_str(node.kind());
+                }
+            } else if is_root {
+                result.push_str(&format!("(\"{}\")", node.kind()));
+            }
+
+            if cursor.goto_first_child() {
+                loop {
+                    write_node(cursor, source, false, result);
+                    if !cursor.goto_next_sibling() {
+                        break;
+                    }
+                }
+                cursor.goto_parent();
+            }
+
+            if is_visible {
+                result.push(')');
+                if node.child_count() == 0 && !node.is_missing() {
+                    let text = String::from_utf8_lossy(&source[node.byte_range()]);
+                    result.push_str(&format!(" {:?}", text));
+                }
+            }
+        }
+
+        let mut result = String::new();
+        write_node(&mut self.walk(), source, true, &mut result);
+        result
+    }
+
     pub fn utf8_text<'a>(&self, source: &'a [u8]) -> Result<&'a str, str::Utf8Error> {
         str::from_utf8(&source[self.start_byte()..self.end_byte()])
     }
@@ -1150,12 +2489,85 @@ impl<'tree> Node<'tree> {
         &source.as_ref()[self.start_byte()..self.end_byte()]
     }
 
+    /// Get the source text strictly between two nodes, such as the separator
+    /// between two siblings.
+    ///
+    /// Returns `None` if `a` does not end before `b` starts, if the text in between
+    /// extends past the end of `source`, or if it is not valid UTF8.
+    pub fn text_between<'s>(a: Node, b: Node, source: &'s [u8]) -> Option<&'s str> {
+        let (start, end) = (a.end_byte(), b.start_byte());
+        if start > end {
+            return None;
+        }
+        str::from_utf8(source.get(start..end)?).ok()
+    }
+
+    /// Count the newline characters in the whitespace between two nodes.
+    ///
+    /// Only the whitespace immediately preceding `b` is considered, so if there
+    /// is other text between the nodes, such as a comment, only the newlines after
+    /// that text are counted. Returns 0 if `a` does not end before `b` starts.
+    pub fn blank_lines_between(a: Node, b: Node, source: &[u8]) -> usize {
+        let gap = match Node::text_between(a, b, source) {
+            Some(gap) => gap,
+            None => return 0,
+        };
+        gap[gap.trim_end().len()..].matches('\n').count()
+    }
+
+    /// Get the whitespace immediately preceding this node, back to the end of the
+    /// previous token or the start of the source.
+    ///
+    /// Returns an empty string if the preceding text is not valid UTF8.
+    pub fn leading_whitespace<'s>(&self, source: &'s [u8]) -> &'s str {
+        let mut node = *self;
+        let previous_end = loop {
+            if let Some(sibling) = node.prev_sibling() {
+                break sibling.end_byte();
+            }
+            match node.parent() {
+                Some(parent) => node = parent,
+                None => break 0,
+            }
+        };
+        let gap = match str::from_utf8(&source[previous_end..self.start_byte()]) {
+            Ok(gap) => gap,
+            Err(_) => return "",
+        };
+        &gap[gap.trim_end().len()..]
+    }
+
     /// Create a new [TreeCursor] starting from this node.
     #[doc(alias = "ts_tree_cursor_new")]
     pub fn walk(&self) -> TreeCursor<'tree> {
         TreeCursor(unsafe { ffi::ts_tree_cursor_new(self.0) }, PhantomData)
     }
 
+    /// Iterate over the leaves of this node, which are its descendants that have no
+    /// children, in document order. This includes anonymous tokens, and also missing
+    /// and error nodes that have no children. A node with no children is its own only
+    /// leaf.
+    pub fn leaves(&self) -> impl Iterator<Item = Node<'tree>> {
+        let mut cursor = self.walk();
+        let mut done = false;
+        iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            while cursor.goto_first_child() {}
+            let result = cursor.node();
+
+            // Advance the cursor to the next subtree, stopping once it returns to this node.
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    done = true;
+                    break;
+                }
+            }
+            Some(result)
+        })
+    }
+
     /// Edit this node to keep it in-sync with source code that has been edited.
     ///
     /// This function is only rarely needed. When you edit a syntax tree with the
@@ -1170,9 +2582,13 @@ impl<'tree> Node<'tree> {
     }
 }
 
+/// Nodes are equal when they are the same node in the same `Tree`. A node that is
+/// reused by a new tree has the same [id](Node::id) in both trees, but is not equal
+/// to its counterpart in the other tree.
 impl<'a> PartialEq for Node<'a> {
+    #[doc(alias = "ts_node_eq")]
     fn eq(&self, other: &Self) -> bool {
-        self.0.id == other.0.id
+        unsafe { ffi::ts_node_eq(self.0, other.0) }
     }
 }
 
@@ -1181,10 +2597,7 @@ impl<'a> Eq for Node<'a> {}
 impl<'a> hash::Hash for Node<'a> {
     fn hash<H: hash::Hasher>(&self, state: &mut H) {
         self.0.id.hash(state);
-        self.0.context[0].hash(state);
-        self.0.context[1].hash(state);
-        self.0.context[2].hash(state);
-        self.0.context[3].hash(state);
+        self.0.tree.hash(state);
     }
 }
 
@@ -1200,6 +2613,20 @@ impl<'a> fmt::Debug for Node<'a> {
     }
 }
 
+/// Formats a node concisely as its kind and byte range, such as `identifier [4..7]`.
+/// Use [Node::to_sexp] to format the node's entire subtree.
+impl<'a> fmt::Display for Node<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{} [{}..{}]",
+            self.kind(),
+            self.start_byte(),
+            self.end_byte()
+        )
+    }
+}
+
 impl<'a> TreeCursor<'a> {
     /// Get the tree cursor's current [Node].
     #[doc(alias = "ts_tree_cursor_current_node")]
@@ -1305,6 +2732,9 @@ impl<'a> TreeCursor<'a> {
 }
 
 impl<'a> Clone for TreeCursor<'a> {
+    /// Create a copy of this tree cursor, including the full path from the
+    /// starting node to its current node, so that it can be moved independently.
+    #[doc(alias = "ts_tree_cursor_copy")]
     fn clone(&self) -> Self {
         TreeCursor(unsafe { ffi::ts_tree_cursor_copy(&self.0) }, PhantomData)
     }
@@ -1316,18 +2746,116 @@ impl<'a> Drop for TreeCursor<'a> {
     }
 }
 
-impl Query {
-    /// Create a new query from a string containing one or more S-expression
-    /// patterns.
-    ///
-    /// The query is associated with a particular language, and can only be run
-    /// on syntax nodes parsed with that language. References to Queries can be
-    /// shared between multiple threads.
-    pub fn new(language: Language, source: &str) -> Result<Self, QueryError> {
-        let mut error_offset = 0u32;
-        let mut error_type: ffi::TSQueryError = 0;
-        let bytes = source.as_bytes();
-
+impl<'a> ProfiledCursor<'a> {
+    /// Create a profiled cursor that starts at the given node, counting it as the
+    /// first visit.
+    pub fn new(node: Node<'a>) -> Self {
+        let mut result = ProfiledCursor {
+            cursor: node.walk(),
+            path: Vec::new(),
+            stats: HashMap::new(),
+        };
+        result.enter();
+        result
+    }
+
+    /// Get the cursor's current [Node].
+    pub fn node(&self) -> Node<'a> {
+        self.cursor.node()
+    }
+
+    /// Get the field name of the cursor's current node.
+    pub fn field_name(&self) -> Option<&'static str> {
+        self.cursor.field_name()
+    }
+
+    /// Move the cursor to the first child of its current node.
+    ///
+    /// See [TreeCursor::goto_first_child].
+    pub fn goto_first_child(&mut self) -> bool {
+        if self.cursor.goto_first_child() {
+            self.enter();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move the cursor to the parent of its current node.
+    ///
+    /// See [TreeCursor::goto_parent].
+    pub fn goto_parent(&mut self) -> bool {
+        if self.cursor.goto_parent() {
+            self.leave();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move the cursor to the next sibling of its current node.
+    ///
+    /// See [TreeCursor::goto_next_sibling].
+    pub fn goto_next_sibling(&mut self) -> bool {
+        if self.cursor.goto_next_sibling() {
+            self.leave();
+            self.enter();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Finish profiling, and get the statistics for each node kind that was visited,
+    /// sorted by decreasing duration.
+    ///
+    /// The nodes that are still on the cursor's path are treated as being left now.
+    /// Because durations include descendants, the time spent within a node is counted
+    /// once for each of its ancestors that has the same kind.
+    pub fn summary(mut self) -> Vec<(&'static str, NodeKindStats)> {
+        while !self.path.is_empty() {
+            self.leave();
+        }
+        let mut result = self.stats.into_iter().collect::<Vec<_>>();
+        result.sort_unstable_by(|(kind1, stats1), (kind2, stats2)| {
+            stats2
+                .duration
+                .cmp(&stats1.duration)
+                .then_with(|| kind1.cmp(kind2))
+        });
+        result
+    }
+
+    fn enter(&mut self) {
+        let kind = self.cursor.node().kind();
+        self.stats.entry(kind).or_default().visits += 1;
+        self.path.push((kind, Instant::now()));
+    }
+
+    fn leave(&mut self) {
+        if let Some((kind, start)) = self.path.pop() {
+            if let Some(stats) = self.stats.get_mut(kind) {
+                stats.duration += start.elapsed();
+            }
+        }
+    }
+}
+
+impl Query {
+    /// Create a new query from a string containing one or more S-expression
+    /// patterns. A `;` starts a comment that continues to the end of the line.
+    ///
+    /// A pattern whose node kind is one of the grammar's supertypes, like `(expression)`,
+    /// matches any node that belongs to that supertype, whatever its concrete kind.
+    ///
+    /// The query is associated with a particular language, and can only be run
+    /// on syntax nodes parsed with that language. References to Queries can be
+    /// shared between multiple threads.
+    pub fn new(language: Language, source: &str) -> Result<Self, QueryError> {
+        let mut error_offset = 0u32;
+        let mut error_type: ffi::TSQueryError = 0;
+        let bytes = source.as_bytes();
+
         // Compile the query.
         let ptr = unsafe {
             ffi::ts_query_new(
@@ -1348,6 +2876,7 @@ impl Query {
                     offset: 0,
                     message: LanguageError {
                         version: language.version(),
+                        inconsistency: None,
                     }
                     .to_string(),
                     kind: QueryErrorKind::Language,
@@ -1420,9 +2949,11 @@ impl Query {
             capture_names: Vec::with_capacity(capture_count as usize),
             capture_quantifiers: Vec::with_capacity(pattern_count as usize),
             text_predicates: Vec::with_capacity(pattern_count),
+            text_predicate_sources: Vec::with_capacity(pattern_count),
             property_predicates: Vec::with_capacity(pattern_count),
             property_settings: Vec::with_capacity(pattern_count),
             general_predicates: Vec::with_capacity(pattern_count),
+            warnings: Vec::new(),
         };
 
         // Build a vector of strings to store the capture names.
@@ -1485,8 +3016,26 @@ impl Query {
             let type_done = ffi::TSQueryPredicateStepType_TSQueryPredicateStepTypeDone;
             let type_capture = ffi::TSQueryPredicateStepType_TSQueryPredicateStepTypeCapture;
             let type_string = ffi::TSQueryPredicateStepType_TSQueryPredicateStepTypeString;
+            let predicate_for_steps = |p: &[ffi::TSQueryPredicateStep]| QueryPredicate {
+                operator: string_values[p[0].value_id as usize]
+                    .clone()
+                    .into_boxed_str(),
+                args: p[1..]
+                    .iter()
+                    .map(|a| {
+                        if a.type_ == type_capture {
+                            QueryPredicateArg::Capture(a.value_id)
+                        } else {
+                            QueryPredicateArg::String(
+                                string_values[a.value_id as usize].clone().into_boxed_str(),
+                            )
+                        }
+                    })
+                    .collect(),
+            };
 
             let mut text_predicates = Vec::new();
+            let mut text_predicate_sources = Vec::new();
             let mut property_predicates = Vec::new();
             let mut property_settings = Vec::new();
             let mut general_predicates = Vec::new();
@@ -1508,70 +3057,219 @@ impl Query {
                 // Build a predicate for each of the known predicate function names.
                 let operator_name = &string_values[p[0].value_id as usize];
                 match operator_name.as_str() {
-                    "eq?" | "not-eq?" => {
+                    "eq?" | "not-eq?" | "eq-ignore-case?" | "not-eq-ignore-case?" => {
                         if p.len() != 3 {
                             return Err(predicate_error(
                                 row,
                                 format!(
-                                "Wrong number of arguments to #eq? predicate. Expected 2, got {}.",
+                                "Wrong number of arguments to #{} predicate. Expected 2, got {}.",
+                                operator_name,
                                 p.len() - 1
                             ),
                             ));
                         }
                         if p[1].type_ != type_capture {
                             return Err(predicate_error(row, format!(
-                                "First argument to #eq? predicate must be a capture name. Got literal \"{}\".",
+                                "First argument to #{} predicate must be a capture name. Got literal \"{}\".",
+                                operator_name,
                                 string_values[p[1].value_id as usize],
                             )));
                         }
 
-                        let is_positive = operator_name == "eq?";
-                        text_predicates.push(if p[2].type_ == type_capture {
-                            TextPredicate::CaptureEqCapture(
+                        let is_positive = !operator_name.starts_with("not-");
+                        let ignore_case = operator_name.ends_with("-ignore-case?");
+                        text_predicates.push(match (p[2].type_ == type_capture, ignore_case) {
+                            (true, false) => TextPredicate::CaptureEqCapture(
                                 p[1].value_id,
                                 p[2].value_id,
                                 is_positive,
-                            )
-                        } else {
-                            TextPredicate::CaptureEqString(
+                            ),
+                            (true, true) => TextPredicate::CaptureEqCaptureIgnoreCase(
+                                p[1].value_id,
+                                p[2].value_id,
+                                is_positive,
+                            ),
+                            (false, false) => TextPredicate::CaptureEqString(
                                 p[1].value_id,
                                 string_values[p[2].value_id as usize].clone(),
                                 is_positive,
-                            )
+                            ),
+                            (false, true) => TextPredicate::CaptureEqStringIgnoreCase(
+                                p[1].value_id,
+                                string_values[p[2].value_id as usize].clone(),
+                                is_positive,
+                            ),
                         });
                     }
 
-                    "match?" | "not-match?" => {
+                    "match?"
+                    | "not-match?"
+                    | "match-full?"
+                    | "not-match-full?"
+                    | "match-ignore-case?"
+                    | "not-match-ignore-case?" => {
                         if p.len() != 3 {
-                            return Err(predicate_error(row, format!(
-                                "Wrong number of arguments to #match? predicate. Expected 2, got {}.",
+                            return Err(predicate_error(
+                                row,
+                                format!(
+                                "Wrong number of arguments to #{} predicate. Expected 2, got {}.",
+                                operator_name,
                                 p.len() - 1
-                            )));
+                            ),
+                            ));
                         }
                         if p[1].type_ != type_capture {
                             return Err(predicate_error(row, format!(
-                                "First argument to #match? predicate must be a capture name. Got literal \"{}\".",
+                                "First argument to #{} predicate must be a capture name. Got literal \"{}\".",
+                                operator_name,
                                 string_values[p[1].value_id as usize],
                             )));
                         }
                         if p[2].type_ == type_capture {
                             return Err(predicate_error(row, format!(
-                                "Second argument to #match? predicate must be a literal. Got capture @{}.",
+                                "Second argument to #{} predicate must be a literal. Got capture @{}.",
+                                operator_name,
                                 result.capture_names[p[2].value_id as usize],
                             )));
                         }
 
-                        let is_positive = operator_name == "match?";
+                        let is_positive = !operator_name.starts_with("not-");
                         let regex = &string_values[p[2].value_id as usize];
+
+                        // The `full` variants require the regex to match the entire text
+                        // of the capture, rather than just some part of it, and the
+                        // `ignore-case` variants match letters regardless of their case.
+                        let compiled_regex = if operator_name.ends_with("match-full?") {
+                            regex::bytes::Regex::new(&format!("\\A(?:{})\\z", regex))
+                        } else if operator_name.ends_with("match-ignore-case?") {
+                            regex::bytes::Regex::new(&format!("(?i:{})", regex))
+                        } else {
+                            regex::bytes::Regex::new(regex)
+                        };
                         text_predicates.push(TextPredicate::CaptureMatchString(
                             p[1].value_id,
-                            regex::bytes::Regex::new(regex).map_err(|_| {
+                            Box::new(compiled_regex.map_err(|_| {
                                 predicate_error(row, format!("Invalid regex '{}'", regex))
-                            })?,
+                            })?),
                             is_positive,
                         ));
                     }
 
+                    "glob?" | "not-glob?" => {
+                        if p.len() != 3 {
+                            return Err(predicate_error(
+                                row,
+                                format!(
+                                "Wrong number of arguments to #{} predicate. Expected 2, got {}.",
+                                operator_name,
+                                p.len() - 1
+                            ),
+                            ));
+                        }
+                        if p[1].type_ != type_capture {
+                            return Err(predicate_error(row, format!(
+                                "First argument to #{} predicate must be a capture name. Got literal \"{}\".",
+                                operator_name,
+                                string_values[p[1].value_id as usize],
+                            )));
+                        }
+                        if p[2].type_ == type_capture {
+                            return Err(predicate_error(row, format!(
+                                "Second argument to #{} predicate must be a literal. Got capture @{}.",
+                                operator_name,
+                                result.capture_names[p[2].value_id as usize],
+                            )));
+                        }
+
+                        text_predicates.push(TextPredicate::CaptureGlobString(
+                            p[1].value_id,
+                            string_values[p[2].value_id as usize].chars().collect(),
+                            operator_name == "glob?",
+                        ));
+                    }
+
+                    "is-at-line-start?" => {
+                        if p.len() != 2 && p.len() != 3 {
+                            return Err(predicate_error(row, format!(
+                                "Wrong number of arguments to #is-at-line-start? predicate. Expected 1 or 2, got {}.",
+                                p.len() - 1
+                            )));
+                        }
+                        if p[1].type_ != type_capture {
+                            return Err(predicate_error(row, format!(
+                                "First argument to #is-at-line-start? predicate must be a capture name. Got literal \"{}\".",
+                                string_values[p[1].value_id as usize],
+                            )));
+                        }
+
+                        // By default, the capture must start in the first column. With the
+                        // `ignore-whitespace` option, it can be preceded by whitespace.
+                        let ignore_whitespace = p.len() == 3;
+                        if ignore_whitespace
+                            && (p[2].type_ == type_capture
+                                || string_values[p[2].value_id as usize] != "ignore-whitespace")
+                        {
+                            return Err(predicate_error(
+                                row,
+                                "Second argument to #is-at-line-start? predicate must be \"ignore-whitespace\".".to_string(),
+                            ));
+                        }
+                        text_predicates.push(TextPredicate::CaptureAtLineStart(
+                            p[1].value_id,
+                            ignore_whitespace,
+                        ));
+                    }
+
+                    "has-ancestor?" | "has-parent?" => {
+                        if p.len() < 3 {
+                            return Err(predicate_error(row, format!(
+                                "Wrong number of arguments to #{} predicate. Expected at least 2, got {}.",
+                                operator_name,
+                                p.len() - 1
+                            )));
+                        }
+                        if p[1].type_ != type_capture {
+                            return Err(predicate_error(row, format!(
+                                "First argument to #{} predicate must be a capture name. Got literal \"{}\".",
+                                operator_name,
+                                string_values[p[1].value_id as usize],
+                            )));
+                        }
+
+                        // A kind can belong to both a named and an anonymous node.
+                        let mut kind_ids = Vec::new();
+                        for arg in &p[2..] {
+                            if arg.type_ == type_capture {
+                                return Err(predicate_error(row, format!(
+                                    "Arguments to #{} predicate must be node kinds. Got capture @{}.",
+                                    operator_name,
+                                    result.capture_names[arg.value_id as usize],
+                                )));
+                            }
+                            let kind = &string_values[arg.value_id as usize];
+                            let ids = [true, false]
+                                .iter()
+                                .map(|named| language.id_for_node_kind(kind, *named))
+                                .filter(|id| *id != 0)
+                                .collect::<Vec<_>>();
+                            if ids.is_empty() {
+                                return Err(predicate_error(
+                                    row,
+                                    format!(
+                                        "Invalid node kind \"{}\" in #{} predicate.",
+                                        kind, operator_name,
+                                    ),
+                                ));
+                            }
+                            kind_ids.extend(ids);
+                        }
+                        text_predicates.push(if operator_name == "has-ancestor?" {
+                            TextPredicate::CaptureHasAncestor(p[1].value_id, kind_ids)
+                        } else {
+                            TextPredicate::CaptureHasParent(p[1].value_id, kind_ids)
+                        });
+                    }
+
                     "set!" => property_settings.push(Self::parse_property(
                         row,
                         &operator_name,
@@ -1591,27 +3289,21 @@ impl Query {
                         operator_name == "is?",
                     )),
 
-                    _ => general_predicates.push(QueryPredicate {
-                        operator: operator_name.clone().into_boxed_str(),
-                        args: p[1..]
-                            .iter()
-                            .map(|a| {
-                                if a.type_ == type_capture {
-                                    QueryPredicateArg::Capture(a.value_id)
-                                } else {
-                                    QueryPredicateArg::String(
-                                        string_values[a.value_id as usize].clone().into_boxed_str(),
-                                    )
-                                }
-                            })
-                            .collect(),
-                    }),
+                    _ => general_predicates.push(predicate_for_steps(p)),
+                }
+
+                // Keep each text predicate as it was written, to report it if it fails.
+                if text_predicates.len() > text_predicate_sources.len() {
+                    text_predicate_sources.push(predicate_for_steps(p));
                 }
             }
 
             result
                 .text_predicates
                 .push(text_predicates.into_boxed_slice());
+            result
+                .text_predicate_sources
+                .push(text_predicate_sources.into_boxed_slice());
             result
                 .property_predicates
                 .push(property_predicates.into_boxed_slice());
@@ -1622,9 +3314,159 @@ impl Query {
                 .general_predicates
                 .push(general_predicates.into_boxed_slice());
         }
+        result.warnings = result.find_warnings(source);
         Ok(result)
     }
 
+    // Look for patterns that are valid, but that are probably mistakes: patterns whose
+    // text predicates can't all be satisfied by any capture text, patterns that are
+    // written exactly like an earlier pattern, and patterns that only match a subset of
+    // what another pattern matches.
+    fn find_warnings(&self, source: &str) -> Vec<QueryWarning> {
+        let pattern_starts = (0..self.pattern_count())
+            .map(|i| self.start_byte_for_pattern(i))
+            .collect::<Vec<_>>();
+        let row_for_pattern = |i: usize| source[..pattern_starts[i]].matches('\n').count();
+
+        let mut result = Vec::new();
+        for (i, predicates) in self.text_predicates.iter().enumerate() {
+            for predicate in predicates.iter() {
+                if let TextPredicate::CaptureEqString(capture, text, true) = predicate {
+                    let contradiction = predicates
+                        .iter()
+                        .position(|other| other.accepts_text(*capture, text) == Some(false));
+                    if let Some(contradiction) = contradiction {
+                        result.push(QueryWarning {
+                            pattern_index: i,
+                            row: row_for_pattern(i),
+                            message: format!(
+                                "Capture @{} can't both equal \"{}\" and satisfy #{}",
+                                self.capture_names[*capture as usize],
+                                text,
+                                self.text_predicate_sources[i][contradiction].operator,
+                            ),
+                            kind: QueryWarningKind::Unsatisfiable,
+                        });
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut pattern_texts = HashMap::new();
+        let mut shapes = Vec::new();
+        for range in pattern_ranges(source) {
+            let i = match pattern_starts
+                .iter()
+                .position(|start| *start == range.start)
+            {
+                Some(i) => i,
+                None => continue,
+            };
+            shapes.extend(PatternShape::parse(&source[range.clone()]).map(|shape| (i, shape)));
+            let text = source[range]
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if let Some(original) = pattern_texts.get(&text) {
+                result.push(QueryWarning {
+                    pattern_index: i,
+                    row: row_for_pattern(i),
+                    message: format!("Pattern is identical to pattern {}", original),
+                    kind: QueryWarningKind::Duplicate,
+                });
+            } else {
+                pattern_texts.insert(text, i);
+            }
+        }
+
+        // A pattern with predicates can match fewer nodes than its structure suggests,
+        // so only patterns without any predicates are compared against.
+        for (i, shape) in &shapes {
+            let superset = shapes.iter().find(|(j, other)| {
+                !other.has_predicates && other.covers(shape) && !shape.covers(other) && i != j
+            });
+            if let Some((j, _)) = superset {
+                result.push(QueryWarning {
+                    pattern_index: *i,
+                    row: row_for_pattern(*i),
+                    message: format!("Pattern only matches a subset of pattern {}", j),
+                    kind: QueryWarningKind::Subset,
+                });
+            }
+        }
+        result.sort_by_key(|warning| warning.pattern_index);
+        result
+    }
+
+    /// Get the warnings that were found when this query was created.
+    ///
+    /// Unlike errors, warnings don't prevent a query from being used. They point out
+    /// patterns that can never match because their text predicates contradict each
+    /// other, patterns that are written exactly like an earlier pattern, and patterns
+    /// whose structure is strictly more specific than that of another pattern without
+    /// predicates, so that they only match a subset of its nodes. Subsets are only
+    /// detected between patterns made of nodes, fields and captures. Patterns whose
+    /// structure is impossible in the language are reported as errors instead.
+    pub fn warnings(&self) -> &[QueryWarning] {
+        &self.warnings
+    }
+
+    /// Create a new query like [Query::new], but report every invalid pattern
+    /// in the query instead of only the first one.
+    ///
+    /// When the query is invalid, each of its top-level patterns is checked on its
+    /// own, and the errors are returned in the order of the patterns. Their positions
+    /// are relative to the whole query source.
+    pub fn validate_all(language: Language, source: &str) -> Result<Self, Vec<QueryError>> {
+        let error = match Query::new(language, source) {
+            Ok(query) => return Ok(query),
+            Err(error) => error,
+        };
+        if error.kind == QueryErrorKind::Language {
+            return Err(vec![error]);
+        }
+
+        // Check each pattern with the rest of the source blanked out, so that the
+        // error positions don't need to be adjusted.
+        let mut errors = Vec::new();
+        for range in pattern_ranges(source) {
+            let pattern_source = source
+                .char_indices()
+                .map(|(i, c)| {
+                    if range.contains(&i) || c == '\n' {
+                        c.to_string()
+                    } else {
+                        " ".repeat(c.len_utf8())
+                    }
+                })
+                .collect::<String>();
+            if let Err(error) = Query::new(language, &pattern_source) {
+                errors.push(error);
+            }
+        }
+
+        // Fall back to the original error if it can't be attributed to a single pattern.
+        if errors.is_empty() {
+            errors.push(error);
+        }
+        Err(errors)
+    }
+
+    /// Recompile this query in place from new source, for the same language, such as
+    /// when a query file is reloaded after it changes.
+    ///
+    /// The query keeps its location, so it can be reloaded while it is shared, such as
+    /// in an `Arc<RwLock<Query>>`, and existing `QueryCursor`s can be used with it.
+    /// Everything else is replaced: capture and pattern indices refer to the new
+    /// source, and any disabled patterns or captures are enabled again. If the new
+    /// source is invalid, an error is returned and the query is left unchanged.
+    pub fn replace_source(&mut self, source: &str) -> Result<(), QueryError> {
+        let language = Language(unsafe { ffi::ts_query_language(self.ptr.as_ptr()) });
+        *self = Query::new(language, source)?;
+        Ok(())
+    }
+
     /// Get the byte offset where the given pattern starts in the query's source.
     #[doc(alias = "ts_query_start_byte_for_pattern")]
     pub fn start_byte_for_pattern(&self, pattern_index: usize) -> usize {
@@ -1664,6 +3506,28 @@ impl Query {
             .map(|ix| ix as u32)
     }
 
+    /// Rename a capture without changing the nodes that it captures.
+    ///
+    /// The new name is reported by [Query::capture_names] and is used to look
+    /// up the capture in methods like [Query::capture_index_for_name], so names
+    /// can be adapted to a particular theme without editing the query source. If
+    /// the query has no capture named `from`, then this does nothing.
+    ///
+    /// Capture names must stay unique, so this returns an error if the query already
+    /// has a different capture named `to`.
+    pub fn remap_capture(&mut self, from: &str, to: &str) -> Result<(), CaptureNameConflictError> {
+        if let Some(index) = self.capture_index_for_name(from) {
+            if self
+                .capture_index_for_name(to)
+                .is_some_and(|other_index| other_index != index)
+            {
+                return Err(CaptureNameConflictError(to.to_string()));
+            }
+            self.capture_names[index as usize] = to.to_string();
+        }
+        Ok(())
+    }
+
     /// Get the properties that are checked for the given pattern index.
     ///
     /// This includes predicates with the operators `is?` and `is-not?`.
@@ -1695,12 +3559,17 @@ impl Query {
     /// resource usage associated with recording the capture.
     #[doc(alias = "ts_query_disable_capture")]
     pub fn disable_capture(&mut self, name: &str) {
-        unsafe {
-            ffi::ts_query_disable_capture(
-                self.ptr.as_ptr(),
-                name.as_bytes().as_ptr() as *const c_char,
-                name.len() as u32,
-            );
+        // The capture may have been renamed, so disable it by its original name.
+        if let Some(index) = self.capture_index_for_name(name) {
+            unsafe {
+                let mut length = 0u32;
+                let name = ffi::ts_query_capture_name_for_id(
+                    self.ptr.as_ptr(),
+                    index,
+                    &mut length as *mut u32,
+                );
+                ffi::ts_query_disable_capture(self.ptr.as_ptr(), name, length);
+            }
         }
     }
 
@@ -1793,6 +3662,65 @@ impl Query {
     }
 }
 
+impl QueryBuilder {
+    /// Create a pattern that matches a named node of the given kind.
+    pub fn node(kind: impl Into<String>) -> Self {
+        QueryBuilder {
+            kind: Some(kind.into()),
+            named: true,
+            field: None,
+            capture: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Create a pattern that matches an anonymous node with the given text.
+    pub fn anonymous(kind: impl Into<String>) -> Self {
+        QueryBuilder {
+            named: false,
+            ..Self::node(kind)
+        }
+    }
+
+    /// Create a pattern that matches any named node.
+    pub fn wildcard() -> Self {
+        QueryBuilder {
+            kind: None,
+            ..Self::node("")
+        }
+    }
+
+    /// Require that the node matched by this pattern is associated with the
+    /// given field of its parent.
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.field = Some(name.into());
+        self
+    }
+
+    /// Capture the node matched by this pattern with the given name.
+    pub fn capture(mut self, name: impl Into<String>) -> Self {
+        self.capture = Some(name.into());
+        self
+    }
+
+    /// Require that the node matched by this pattern has a child that matches
+    /// the given pattern. Children are matched in the order that they are added.
+    pub fn child(mut self, child: QueryBuilder) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Get the query source for this pattern.
+    pub fn to_source(&self) -> String {
+        self.to_string()
+    }
+
+    /// Compile this pattern into a `Query` for the given language.
+    pub fn build(&self, language: Language) -> Result<Query, QueryError> {
+        Query::new(language, &self.to_source())
+    }
+}
+
 impl QueryCursor {
     /// Create a new cursor for executing a given query.
     ///
@@ -1801,9 +3729,70 @@ impl QueryCursor {
     pub fn new() -> Self {
         QueryCursor {
             ptr: unsafe { NonNull::new_unchecked(ffi::ts_query_cursor_new()) },
+            skip_gap_captures: false,
+            capture_allowlist: None,
+            match_order: MatchOrder::Document,
+            sorted_matches: Vec::new(),
+            sorted_captures: Vec::new(),
         }
     }
 
+    /// Set whether to skip captured nodes that extend into the gaps between a tree's
+    /// [included ranges](Tree::included_ranges).
+    ///
+    /// When a tree is parsed with several disjoint included ranges, some of its
+    /// nodes may span from one range to another, across text that was never parsed.
+    /// If this is enabled, every method that iterates over this cursor's matches or
+    /// captures skips any match that captures such a node. Because
+    /// [captures](QueryCursor::captures) can return the first captures of a match before
+    /// the rest are found, only the captures after the one spanning the gap are skipped.
+    /// This is disabled by default.
+    pub fn set_skip_gap_captures(&mut self, skip: bool) {
+        self.skip_gap_captures = skip;
+    }
+
+    /// Restrict [captures](QueryCursor::captures) to the captures with the given names.
+    ///
+    /// Any other capture is skipped without evaluating the text predicates of its
+    /// match. Unlike [Query::disable_capture], this doesn't modify the query, and the
+    /// other captures can still be used by the patterns' predicates. Names that don't
+    /// belong to the query are ignored.
+    ///
+    /// The cursor still finds the matches of every pattern, including the patterns
+    /// that have none of the given captures, so this doesn't make running the query
+    /// any faster. To avoid that work, use [Query::disable_pattern] instead.
+    ///
+    /// Only [captures](QueryCursor::captures) is restricted. The methods that iterate
+    /// over matches, such as [matches](QueryCursor::matches), ignore the allowlist and
+    /// always return all of a match's captures.
+    pub fn set_capture_allowlist(&mut self, names: &[&str]) {
+        self.capture_allowlist = Some(names.iter().map(|name| name.to_string()).collect());
+    }
+
+    /// Remove any restriction set by [QueryCursor::set_capture_allowlist], so that all
+    /// captures are returned again.
+    pub fn clear_capture_allowlist(&mut self) {
+        self.capture_allowlist = None;
+    }
+
+    /// Get the order in which [matches](QueryCursor::matches) returns matches.
+    pub fn match_order(&self) -> MatchOrder {
+        self.match_order
+    }
+
+    /// Set the order in which [matches](QueryCursor::matches) returns matches.
+    ///
+    /// With [MatchOrder::Document], which is the default, matches are produced lazily as
+    /// the tree is walked. With [MatchOrder::Pattern], all of the matches are found up
+    /// front and then sorted by pattern index, keeping matches of the same pattern in
+    /// document order. Because of this, any byte or point range must be set on this
+    /// cursor *before* calling `matches`, and [QueryMatch::remove] has no effect on
+    /// the returned matches, since the cursor has already finished all of them.
+    /// [QueryCursor::find_match] always uses document order, so that it can stop early.
+    pub fn set_match_order(&mut self, order: MatchOrder) {
+        self.match_order = order;
+    }
+
     /// Return the maximum number of in-progress matches for this cursor.
     #[doc(alias = "ts_query_cursor_match_limit")]
     pub fn match_limit(&self) -> u32 {
@@ -1826,6 +3815,65 @@ impl QueryCursor {
         unsafe { ffi::ts_query_cursor_did_exceed_match_limit(self.ptr.as_ptr()) }
     }
 
+    /// Get the duration in microseconds that query execution is allowed to take.
+    ///
+    /// This is set via [set_timeout_micros](QueryCursor::set_timeout_micros).
+    #[doc(alias = "ts_query_cursor_timeout_micros")]
+    pub fn timeout_micros(&self) -> u64 {
+        unsafe { ffi::ts_query_cursor_timeout_micros(self.ptr.as_ptr()) }
+    }
+
+    /// Set the maximum duration in microseconds that query execution should be allowed to
+    /// take before halting.
+    ///
+    /// The timer starts when [matches](QueryCursor::matches) or
+    /// [captures](QueryCursor::captures) is called. If execution takes longer than this, the
+    /// returned iterator will end early, and [timed_out](QueryCursor::timed_out) will return
+    /// `true`. A timeout of zero, which is the default, means that execution is never halted.
+    #[doc(alias = "ts_query_cursor_set_timeout_micros")]
+    pub fn set_timeout_micros(&mut self, timeout_micros: u64) {
+        unsafe { ffi::ts_query_cursor_set_timeout_micros(self.ptr.as_ptr(), timeout_micros) }
+    }
+
+    /// Check if, on its last execution, this cursor was halted because it exceeded its
+    /// timeout.
+    #[doc(alias = "ts_query_cursor_did_exceed_timeout")]
+    pub fn timed_out(&self) -> bool {
+        unsafe { ffi::ts_query_cursor_did_exceed_timeout(self.ptr.as_ptr()) }
+    }
+
+    /// Get the maximum number of bytes that query execution is allowed to allocate.
+    ///
+    /// This is set via [set_max_memory](QueryCursor::set_max_memory).
+    #[doc(alias = "ts_query_cursor_max_memory")]
+    pub fn max_memory(&self) -> usize {
+        unsafe { ffi::ts_query_cursor_max_memory(self.ptr.as_ptr()) as usize }
+    }
+
+    /// Set the maximum number of bytes that query execution should be allowed to
+    /// allocate for its in-progress matches before halting.
+    ///
+    /// Only the memory allocated during each execution is counted, so memory that this
+    /// cursor retained from earlier executions is reused without counting towards the
+    /// limit.
+    ///
+    /// If execution needs more memory than this, the iterator returned by
+    /// [matches](QueryCursor::matches) or [captures](QueryCursor::captures) will end
+    /// early, and [did_exceed_max_memory](QueryCursor::did_exceed_max_memory) will return
+    /// `true`. A limit of zero, which is the default, means that memory usage is not
+    /// limited.
+    #[doc(alias = "ts_query_cursor_set_max_memory")]
+    pub fn set_max_memory(&mut self, max_memory: usize) {
+        unsafe { ffi::ts_query_cursor_set_max_memory(self.ptr.as_ptr(), max_memory as u64) }
+    }
+
+    /// Check if, on its last execution, this cursor was halted because it exceeded
+    /// its memory limit.
+    #[doc(alias = "ts_query_cursor_did_exceed_max_memory")]
+    pub fn did_exceed_max_memory(&self) -> bool {
+        unsafe { ffi::ts_query_cursor_did_exceed_max_memory(self.ptr.as_ptr()) }
+    }
+
     /// Iterate over all of the matches in the order that they were found.
     ///
     /// Each match contains the index of the pattern that matched, and a list of captures.
@@ -1837,6 +3885,34 @@ impl QueryCursor {
         query: &'a Query,
         node: Node<'tree>,
         text_provider: T,
+    ) -> QueryMatches<'a, 'tree, T> {
+        let mut result = self.matches_in_document_order(query, node, text_provider);
+        if self.match_order == MatchOrder::Pattern {
+            self.sorted_matches.clear();
+            self.sorted_captures.clear();
+            for m in &mut result {
+                let start = self.sorted_captures.len();
+                self.sorted_captures
+                    .extend(m.captures.iter().map(|capture| QueryCapture {
+                        node: Node(capture.node.0, PhantomData),
+                        index: capture.index,
+                    }));
+                let end = self.sorted_captures.len();
+                self.sorted_matches
+                    .push((m.pattern_index, m.id, start..end));
+            }
+            self.sorted_matches.sort_by_key(|m| m.0);
+            result.sorted_matches = Some(self.sorted_matches.iter());
+            result.sorted_captures = &self.sorted_captures;
+        }
+        result
+    }
+
+    fn matches_in_document_order<'a, 'tree: 'a, T: TextProvider<'a> + 'a>(
+        &self,
+        query: &'a Query,
+        node: Node<'tree>,
+        text_provider: T,
     ) -> QueryMatches<'a, 'tree, T> {
         let ptr = self.ptr.as_ptr();
         unsafe { ffi::ts_query_cursor_exec(ptr, query.ptr.as_ptr(), node.0) };
@@ -1846,14 +3922,95 @@ impl QueryCursor {
             text_provider,
             buffer1: Default::default(),
             buffer2: Default::default(),
+            included_ranges: self.included_ranges_for(node),
+            sorted_matches: None,
+            sorted_captures: &[],
             _tree: PhantomData,
         }
     }
 
-    /// Iterate over all of the individual captures in the order that they appear.
+    /// Iterate over all of the matches in the order that they were found, including
+    /// the matches that fail the query's text predicates.
     ///
-    /// This is useful if you don't care about which pattern matched, and just want a single,
-    /// ordered sequence of captures.
+    /// Each match is paired with the first text predicate that rejected it, or `None`
+    /// if the match satisfies all of them and would be returned by [QueryCursor::matches].
+    /// This is useful for finding out why a pattern doesn't match some text.
+    #[doc(alias = "ts_query_cursor_exec")]
+    pub fn matches_with_diagnostics<'a, 'tree: 'a, T: TextProvider<'a> + 'a>(
+        &'a mut self,
+        query: &'a Query,
+        node: Node<'tree>,
+        text_provider: T,
+    ) -> QueryMatchesWithDiagnostics<'a, 'tree, T> {
+        let ptr = self.ptr.as_ptr();
+        unsafe { ffi::ts_query_cursor_exec(ptr, query.ptr.as_ptr(), node.0) };
+        QueryMatchesWithDiagnostics {
+            ptr,
+            query,
+            text_provider,
+            buffer1: Default::default(),
+            buffer2: Default::default(),
+            included_ranges: self.included_ranges_for(node),
+            _tree: PhantomData,
+        }
+    }
+
+    /// Iterate over all of the matches in several syntax trees, in the order that they
+    /// were found.
+    ///
+    /// Each item of `trees` consists of an identifier for the tree, the node to search
+    /// beneath, and the text of the tree's source. The trees are searched one after the
+    /// other, reusing this cursor, and each match is returned along with the identifier
+    /// of the tree in which it was found. Any byte or point range set on this cursor
+    /// applies to every tree.
+    #[doc(alias = "ts_query_cursor_exec")]
+    pub fn matches_over<'a, 'tree: 'a, T, I>(
+        &'a mut self,
+        query: &'a Query,
+        trees: I,
+    ) -> QueryMatchesOver<'a, 'tree, T, I>
+    where
+        T: TextProvider<'a> + 'a,
+        I: Iterator<Item = (usize, Node<'tree>, T)>,
+    {
+        QueryMatchesOver {
+            ptr: self.ptr.as_ptr(),
+            query,
+            trees,
+            current: None,
+            buffer1: Default::default(),
+            buffer2: Default::default(),
+            skip_gap_captures: self.skip_gap_captures,
+            included_ranges: Vec::new(),
+            _tree: PhantomData,
+        }
+    }
+
+    /// Find the first match for which the given predicate returns `true`.
+    ///
+    /// Matches are searched in document order, regardless of the cursor's
+    /// [match order](QueryCursor::set_match_order). They are produced lazily, so
+    /// iteration stops as soon as the predicate succeeds and none of the later matches
+    /// are computed.
+    pub fn find_match<'a, 'tree: 'a, T: TextProvider<'a> + 'a>(
+        &'a mut self,
+        query: &'a Query,
+        node: Node<'tree>,
+        text_provider: T,
+        mut predicate: impl FnMut(&QueryMatch<'_, 'tree>) -> bool,
+    ) -> Option<OwnedMatch<'tree>> {
+        self.matches_in_document_order(query, node, text_provider)
+            .find(|m| predicate(m))
+            .map(|m| OwnedMatch {
+                pattern_index: m.pattern_index,
+                captures: m.captures.to_vec(),
+            })
+    }
+
+    /// Iterate over all of the individual captures in the order that they appear.
+    ///
+    /// This is useful if you don't care about which pattern matched, and just want a single,
+    /// ordered sequence of captures.
     #[doc(alias = "ts_query_cursor_exec")]
     pub fn captures<'a, 'tree: 'a, T: TextProvider<'a> + 'a>(
         &'a mut self,
@@ -1869,10 +4026,60 @@ impl QueryCursor {
             text_provider,
             buffer1: Default::default(),
             buffer2: Default::default(),
+            included_ranges: self.included_ranges_for(node),
+            allowed_captures: self.allowed_captures_for(query),
             _tree: PhantomData,
         }
     }
 
+    /// Iterate over all of the individual captures, across all patterns, sorted by
+    /// their start byte.
+    ///
+    /// Each capture is returned along with the index of the pattern that produced it.
+    /// Captures that start at the same position are ordered with the larger nodes first,
+    /// and then by pattern index.
+    ///
+    /// Unlike [QueryCursor::captures], which may return captures slightly out of order
+    /// when patterns overlap, the order here is guaranteed. To provide it, every match
+    /// is found and its captures are buffered and sorted before the first one is
+    /// returned, so memory use grows with the total number of captures, and none of
+    /// the work can be skipped by stopping iteration early.
+    #[doc(alias = "ts_query_cursor_exec")]
+    pub fn captures_sorted<'a, 'tree: 'a, T: TextProvider<'a> + 'a>(
+        &'a mut self,
+        query: &'a Query,
+        node: Node<'tree>,
+        text_provider: T,
+    ) -> impl Iterator<Item = (usize, QueryCapture<'tree>)> {
+        let mut captures = Vec::new();
+        for m in self.matches(query, node, text_provider) {
+            captures.extend(m.captures.iter().map(|c| (m.pattern_index, *c)));
+        }
+        captures.sort_by_key(|(pattern_index, capture)| {
+            (
+                capture.node.start_byte(),
+                cmp::Reverse(capture.node.end_byte()),
+                *pattern_index,
+            )
+        });
+        captures.into_iter()
+    }
+
+    fn allowed_captures_for(&self, query: &Query) -> Option<Vec<bool>> {
+        let names = self.capture_allowlist.as_ref()?;
+        let mut allowed = vec![false; query.capture_names().len()];
+        for name in names {
+            if let Some(index) = query.capture_index_for_name(name) {
+                allowed[index as usize] = true;
+            }
+        }
+        Some(allowed)
+    }
+
+    fn included_ranges_for(&self, node: Node) -> Vec<Range> {
+        gap_checked_ranges(self.skip_gap_captures, node)
+    }
+
     /// Set the range in which the query will be executed, in terms of byte offsets.
     #[doc(alias = "ts_query_cursor_set_byte_range")]
     pub fn set_byte_range(&mut self, range: ops::Range<usize>) -> &mut Self {
@@ -1905,11 +4112,22 @@ impl<'a, 'tree> QueryMatch<'a, 'tree> {
         self.id
     }
 
+    /// Remove this match from its cursor, so that none of its remaining captures are
+    /// returned.
+    ///
+    /// This only has an effect while the cursor still holds the match, as it does
+    /// between the captures returned by [QueryCursor::captures]. In particular, it does
+    /// nothing for the matches that are buffered for [MatchOrder::Pattern], which the
+    /// cursor has already finished.
     #[doc(alias = "ts_query_cursor_remove_match")]
     pub fn remove(self) {
         unsafe { ffi::ts_query_cursor_remove_match(self.cursor, self.id) }
     }
 
+    /// Iterate over all of the nodes captured with the given capture index.
+    ///
+    /// A quantified capture such as `(_)+ @item` can capture several nodes in a
+    /// single match; these are yielded in the order in which they were matched.
     pub fn nodes_for_capture_index(
         &self,
         capture_ix: u32,
@@ -1923,9 +4141,143 @@ impl<'a, 'tree> QueryMatch<'a, 'tree> {
         })
     }
 
-    fn new(m: ffi::TSQueryMatch, cursor: *mut ffi::TSQueryCursor) -> Self {
+    /// Get the number of nodes captured with the given capture index.
+    ///
+    /// For a quantified capture such as `(_)+ @item`, this is the number of times that
+    /// the quantified pattern was repeated in this match.
+    pub fn capture_count(&self, capture_ix: u32) -> usize {
+        self.nodes_for_capture_index(capture_ix).count()
+    }
+
+    /// Get the node that anchors this match, which is the node matched by the
+    /// outermost step of the pattern.
+    ///
+    /// The outermost step doesn't need to be captured: each captured node is followed
+    /// up to the depth of the pattern's outermost step. If the pattern has several
+    /// outermost steps, as in `((comment) @doc . (function_declaration) @function)`,
+    /// the node that starts first and, of those, ends last is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the match has no captures, since none of its nodes are known then.
+    pub fn root_node(&self) -> Node<'tree> {
+        let roots = self
+            .captures
+            .iter()
+            .map(|capture| {
+                let depth = unsafe {
+                    ffi::ts_query_capture_depth_for_id(
+                        self.query,
+                        self.pattern_index as u32,
+                        capture.index,
+                    )
+                };
+                let mut node = capture.node;
+                for _ in 0..depth {
+                    match node.parent() {
+                        Some(parent) => node = parent,
+                        None => break,
+                    }
+                }
+                node
+            })
+            .collect::<Vec<_>>();
+        let mut result = *roots
+            .iter()
+            .min_by_key(|node| (node.start_byte(), cmp::Reverse(node.end_byte())))
+            .expect("Query match has no captures");
+        let mut node = result;
+        while let Some(parent) = node.parent() {
+            if parent.byte_range() != result.byte_range() {
+                break;
+            }
+            if roots.contains(&parent) {
+                result = parent;
+            }
+            node = parent;
+        }
+        result
+    }
+
+    /// Arrange this match's captures into a tree, in which each capture's children are
+    /// the captures whose nodes it contains.
+    ///
+    /// Captures are ordered by their position in the document. When two captured nodes
+    /// span the same range, the ancestor is treated as containing the descendant, and
+    /// two captures of the same node are nested in the order they appear in the match.
+    pub fn capture_tree(&self) -> CaptureTree<'tree> {
+        fn depth(node: Node) -> usize {
+            let mut depth = 0;
+            let mut node = node;
+            while let Some(parent) = node.parent() {
+                depth += 1;
+                node = parent;
+            }
+            depth
+        }
+
+        fn build<'tree>(
+            captures: &[QueryCapture<'tree>],
+            index: &mut usize,
+            parent: Option<Node>,
+        ) -> Vec<CaptureTreeNode<'tree>> {
+            let mut result = Vec::new();
+            while let Some(capture) = captures.get(*index) {
+                if let Some(parent) = parent {
+                    if capture.node.start_byte() < parent.start_byte()
+                        || capture.node.end_byte() > parent.end_byte()
+                    {
+                        break;
+                    }
+                }
+                *index += 1;
+                result.push(CaptureTreeNode {
+                    capture: *capture,
+                    children: build(captures, index, Some(capture.node)),
+                });
+            }
+            result
+        }
+
+        let mut captures = self
+            .captures
+            .iter()
+            .map(|capture| (depth(capture.node), *capture))
+            .collect::<Vec<_>>();
+        captures.sort_by_key(|(depth, capture)| {
+            (
+                capture.node.start_byte(),
+                cmp::Reverse(capture.node.end_byte()),
+                *depth,
+            )
+        });
+        let captures = captures
+            .into_iter()
+            .map(|(_, capture)| capture)
+            .collect::<Vec<_>>();
+        CaptureTree {
+            roots: build(&captures, &mut 0, None),
+        }
+    }
+
+    /// Iterate over this match's captures, sorted by their capture index.
+    ///
+    /// Captures that share an index remain in the order in which they were
+    /// matched.
+    pub fn captures_by_index(&self) -> impl Iterator<Item = (u32, Node<'tree>)> {
+        let mut captures = self
+            .captures
+            .iter()
+            .map(|capture| (capture.index, capture.node))
+            .collect::<Vec<_>>();
+        captures.sort_by_key(|(index, _)| *index);
+        captures.into_iter()
+    }
+
+    fn new(m: ffi::TSQueryMatch, cursor: *mut ffi::TSQueryCursor, query: &Query) -> Self {
         QueryMatch {
             cursor,
+            query: query.ptr.as_ptr(),
             id: m.id,
             pattern_index: m.pattern_index as usize,
             captures: if m.capture_count > 0 {
@@ -1941,6 +4293,12 @@ impl<'a, 'tree> QueryMatch<'a, 'tree> {
         }
     }
 
+    fn is_within_ranges(&self, ranges: &[Range]) -> bool {
+        self.captures
+            .iter()
+            .all(|c| is_within_ranges(c.node, ranges))
+    }
+
     fn satisfies_text_predicates(
         &self,
         query: &Query,
@@ -1948,6 +4306,18 @@ impl<'a, 'tree> QueryMatch<'a, 'tree> {
         buffer2: &mut Vec<u8>,
         text_provider: &mut impl TextProvider<'a>,
     ) -> bool {
+        self.failed_text_predicate(query, buffer1, buffer2, text_provider)
+            .is_none()
+    }
+
+    // Find the index of the first text predicate that this match doesn't satisfy.
+    fn failed_text_predicate(
+        &self,
+        query: &Query,
+        buffer1: &mut Vec<u8>,
+        buffer2: &mut Vec<u8>,
+        text_provider: &mut impl TextProvider<'a>,
+    ) -> Option<usize> {
         fn get_text<'a, 'b: 'a, I: Iterator<Item = &'b [u8]>>(
             buffer: &'a mut Vec<u8>,
             mut chunks: I,
@@ -1968,41 +4338,111 @@ impl<'a, 'tree> QueryMatch<'a, 'tree> {
 
         query.text_predicates[self.pattern_index]
             .iter()
-            .all(|predicate| match predicate {
-                TextPredicate::CaptureEqCapture(i, j, is_positive) => {
-                    let node1 = self.nodes_for_capture_index(*i).next();
-                    let node2 = self.nodes_for_capture_index(*j).next();
-                    match (node1, node2) {
-                        (Some(node1), Some(node2)) => {
-                            let text1 = get_text(buffer1, text_provider.text(node1));
-                            let text2 = get_text(buffer2, text_provider.text(node2));
-                            (text1 == text2) == *is_positive
-                        }
-                        _ => true,
-                    }
-                }
-                TextPredicate::CaptureEqString(i, s, is_positive) => {
-                    let node = self.nodes_for_capture_index(*i).next();
-                    match node {
-                        Some(node) => {
-                            let text = get_text(buffer1, text_provider.text(node));
-                            (text == s.as_bytes()) == *is_positive
-                        }
-                        None => true,
-                    }
-                }
-                TextPredicate::CaptureMatchString(i, r, is_positive) => {
-                    let node = self.nodes_for_capture_index(*i).next();
-                    match node {
-                        Some(node) => {
-                            let text = get_text(buffer1, text_provider.text(node));
-                            r.is_match(text) == *is_positive
-                        }
-                        None => true,
+            .position(|predicate| match self.nodes_for_predicate(predicate) {
+                Some((node1, node2)) => {
+                    if let Some(accepted) = predicate.accepts_node(node1) {
+                        return !accepted;
                     }
+                    let text1 = get_text(buffer1, text_provider.text(node1));
+                    let text2 = match node2 {
+                        Some(node2) => get_text(buffer2, text_provider.text(node2)),
+                        None => &[],
+                    };
+                    !predicate.accepts(text1, text2)
                 }
+                None => false,
             })
     }
+
+    // Get the nodes whose text a predicate depends on, or `None` if any of the
+    // predicate's captures are missing from this match, in which case the predicate
+    // is satisfied.
+    fn nodes_for_predicate(
+        &self,
+        predicate: &TextPredicate,
+    ) -> Option<(Node<'tree>, Option<Node<'tree>>)> {
+        match predicate {
+            TextPredicate::CaptureEqCapture(i, j, _)
+            | TextPredicate::CaptureEqCaptureIgnoreCase(i, j, _) => {
+                let node1 = self.nodes_for_capture_index(*i).next()?;
+                let node2 = self.nodes_for_capture_index(*j).next()?;
+                Some((node1, Some(node2)))
+            }
+            TextPredicate::CaptureEqString(i, ..)
+            | TextPredicate::CaptureEqStringIgnoreCase(i, ..)
+            | TextPredicate::CaptureMatchString(i, ..)
+            | TextPredicate::CaptureGlobString(i, ..)
+            | TextPredicate::CaptureAtLineStart(i, ..)
+            | TextPredicate::CaptureHasAncestor(i, ..)
+            | TextPredicate::CaptureHasParent(i, ..) => {
+                Some((self.nodes_for_capture_index(*i).next()?, None))
+            }
+        }
+    }
+}
+
+impl TextPredicate {
+    // Check whether the given text for the given capture satisfies this predicate,
+    // or return `None` if this predicate doesn't depend only on that capture.
+    fn accepts_text(&self, capture: u32, text: &str) -> Option<bool> {
+        match self {
+            TextPredicate::CaptureEqString(i, ..)
+            | TextPredicate::CaptureEqStringIgnoreCase(i, ..)
+            | TextPredicate::CaptureMatchString(i, ..)
+            | TextPredicate::CaptureGlobString(i, ..)
+                if *i == capture =>
+            {
+                Some(self.accepts(text.as_bytes(), &[]))
+            }
+            _ => None,
+        }
+    }
+
+    // Check whether the given node satisfies this predicate, or return `None` if this
+    // predicate depends on the text of the node rather than on its position.
+    fn accepts_node(&self, node: Node) -> Option<bool> {
+        match self {
+            TextPredicate::CaptureAtLineStart(_, ignore_whitespace) => {
+                Some(is_at_line_start(node, *ignore_whitespace))
+            }
+            TextPredicate::CaptureHasAncestor(_, kind_ids) => {
+                Some(has_ancestor_of_kind(node, kind_ids))
+            }
+            TextPredicate::CaptureHasParent(_, kind_ids) => Some(
+                node.parent()
+                    .is_some_and(|parent| kind_ids.contains(&parent.kind_id())),
+            ),
+            _ => None,
+        }
+    }
+
+    // Check whether this predicate is satisfied by the text of its first capture and,
+    // for predicates that compare two captures, the text of its second capture.
+    fn accepts(&self, text1: &[u8], text2: &[u8]) -> bool {
+        match self {
+            TextPredicate::CaptureEqCapture(_, _, is_positive) => (text1 == text2) == *is_positive,
+            TextPredicate::CaptureEqString(_, s, is_positive) => {
+                (text1 == s.as_bytes()) == *is_positive
+            }
+            TextPredicate::CaptureEqCaptureIgnoreCase(_, _, is_positive) => {
+                eq_ignore_case(text1, text2) == *is_positive
+            }
+            TextPredicate::CaptureEqStringIgnoreCase(_, s, is_positive) => {
+                eq_ignore_case(text1, s.as_bytes()) == *is_positive
+            }
+            TextPredicate::CaptureMatchString(_, r, is_positive) => {
+                r.is_match(text1) == *is_positive
+            }
+            TextPredicate::CaptureGlobString(_, g, is_positive) => {
+                let text = String::from_utf8_lossy(text1).chars().collect::<Vec<_>>();
+                glob_match(g, &text) == *is_positive
+            }
+            // Position predicates are checked by `accepts_node`.
+            TextPredicate::CaptureAtLineStart(..)
+            | TextPredicate::CaptureHasAncestor(..)
+            | TextPredicate::CaptureHasParent(..) => true,
+        }
+    }
 }
 
 impl QueryProperty {
@@ -2019,17 +4459,30 @@ impl<'a, 'tree, T: TextProvider<'a>> Iterator for QueryMatches<'a, 'tree, T> {
     type Item = QueryMatch<'a, 'tree>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(sorted_matches) = &mut self.sorted_matches {
+            let captures: &'a [QueryCapture<'tree>] = self.sorted_captures;
+            return sorted_matches
+                .next()
+                .map(|(pattern_index, id, range)| QueryMatch {
+                    cursor: self.ptr,
+                    query: self.query.ptr.as_ptr(),
+                    id: *id,
+                    pattern_index: *pattern_index,
+                    captures: &captures[range.clone()],
+                });
+        }
         unsafe {
             loop {
                 let mut m = MaybeUninit::<ffi::TSQueryMatch>::uninit();
                 if ffi::ts_query_cursor_next_match(self.ptr, m.as_mut_ptr()) {
-                    let result = QueryMatch::new(m.assume_init(), self.ptr);
+                    let result = QueryMatch::new(m.assume_init(), self.ptr, self.query);
                     if result.satisfies_text_predicates(
                         self.query,
                         &mut self.buffer1,
                         &mut self.buffer2,
                         &mut self.text_provider,
-                    ) {
+                    ) && result.is_within_ranges(&self.included_ranges)
+                    {
                         return Some(result);
                     }
                 } else {
@@ -2040,6 +4493,81 @@ impl<'a, 'tree, T: TextProvider<'a>> Iterator for QueryMatches<'a, 'tree, T> {
     }
 }
 
+impl<'a, 'tree, T: TextProvider<'a>> Iterator for QueryMatchesWithDiagnostics<'a, 'tree, T> {
+    type Item = (QueryMatch<'a, 'tree>, Option<QueryPredicateFailure>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            loop {
+                let mut m = MaybeUninit::<ffi::TSQueryMatch>::uninit();
+                if ffi::ts_query_cursor_next_match(self.ptr, m.as_mut_ptr()) {
+                    let result = QueryMatch::new(m.assume_init(), self.ptr, self.query);
+                    if !result.is_within_ranges(&self.included_ranges) {
+                        continue;
+                    }
+                    let failure = result
+                        .failed_text_predicate(
+                            self.query,
+                            &mut self.buffer1,
+                            &mut self.buffer2,
+                            &mut self.text_provider,
+                        )
+                        .map(|index| {
+                            let source =
+                                &self.query.text_predicate_sources[result.pattern_index][index];
+                            QueryPredicateFailure {
+                                predicate_index: index,
+                                operator: source.operator.clone(),
+                                args: source.args.clone(),
+                            }
+                        });
+                    return Some((result, failure));
+                } else {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl<'a, 'tree, T, I> Iterator for QueryMatchesOver<'a, 'tree, T, I>
+where
+    T: TextProvider<'a>,
+    I: Iterator<Item = (usize, Node<'tree>, T)>,
+{
+    type Item = (usize, QueryMatch<'a, 'tree>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            loop {
+                if let Some((tree_id, text_provider)) = &mut self.current {
+                    let mut m = MaybeUninit::<ffi::TSQueryMatch>::uninit();
+                    if ffi::ts_query_cursor_next_match(self.ptr, m.as_mut_ptr()) {
+                        let result = QueryMatch::new(m.assume_init(), self.ptr, self.query);
+                        if result.is_within_ranges(&self.included_ranges)
+                            && result.satisfies_text_predicates(
+                                self.query,
+                                &mut self.buffer1,
+                                &mut self.buffer2,
+                                text_provider,
+                            )
+                        {
+                            return Some((*tree_id, result));
+                        }
+                        continue;
+                    }
+                }
+
+                // Once the current tree has no more matches, move on to the next one.
+                let (tree_id, node, text_provider) = self.trees.next()?;
+                self.included_ranges = gap_checked_ranges(self.skip_gap_captures, node);
+                ffi::ts_query_cursor_exec(self.ptr, self.query.ptr.as_ptr(), node.0);
+                self.current = Some((tree_id, text_provider));
+            }
+        }
+    }
+}
+
 impl<'a, 'tree, T: TextProvider<'a>> Iterator for QueryCaptures<'a, 'tree, T> {
     type Item = (QueryMatch<'a, 'tree>, usize);
 
@@ -2053,17 +4581,24 @@ impl<'a, 'tree, T: TextProvider<'a>> Iterator for QueryCaptures<'a, 'tree, T> {
                     m.as_mut_ptr(),
                     &mut capture_index as *mut u32,
                 ) {
-                    let result = QueryMatch::new(m.assume_init(), self.ptr);
-                    if result.satisfies_text_predicates(
-                        self.query,
-                        &mut self.buffer1,
-                        &mut self.buffer2,
-                        &mut self.text_provider,
-                    ) {
+                    let result = QueryMatch::new(m.assume_init(), self.ptr, self.query);
+                    if let Some(allowed) = &self.allowed_captures {
+                        let index = result.captures[capture_index as usize].index;
+                        if !allowed[index as usize] {
+                            continue;
+                        }
+                    }
+                    if result.is_within_ranges(&self.included_ranges)
+                        && result.satisfies_text_predicates(
+                            self.query,
+                            &mut self.buffer1,
+                            &mut self.buffer2,
+                            &mut self.text_provider,
+                        )
+                    {
                         return Some((result, capture_index as usize));
-                    } else {
-                        result.remove();
                     }
+                    result.remove();
                 } else {
                     return None;
                 }
@@ -2152,12 +4687,130 @@ impl Drop for QueryCursor {
     }
 }
 
+impl LookaheadIterator {
+    /// Get the current symbol of the lookahead iterator.
+    #[doc(alias = "ts_lookahead_iterator_current_symbol")]
+    pub fn current_symbol(&self) -> u16 {
+        unsafe { ffi::ts_lookahead_iterator_current_symbol(self.ptr.as_ptr()) }
+    }
+}
+
+impl Iterator for LookaheadIterator {
+    type Item = u16;
+
+    #[doc(alias = "ts_lookahead_iterator_next")]
+    fn next(&mut self) -> Option<Self::Item> {
+        if unsafe { ffi::ts_lookahead_iterator_next(self.ptr.as_ptr()) } {
+            Some(self.current_symbol())
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for LookaheadIterator {
+    fn drop(&mut self) {
+        unsafe { ffi::ts_lookahead_iterator_delete(self.ptr.as_ptr()) }
+    }
+}
+
 impl Point {
     pub fn new(row: usize, column: usize) -> Self {
         Point { row, column }
     }
 }
 
+impl Range {
+    /// Get the range covered by both this range and another, or `None` if they don't
+    /// overlap. Ranges that are merely adjacent, where one ends where the other starts,
+    /// don't overlap.
+    pub fn intersect(&self, other: &Range) -> Option<Range> {
+        let (start_byte, start_point) = if self.start_byte >= other.start_byte {
+            (self.start_byte, self.start_point)
+        } else {
+            (other.start_byte, other.start_point)
+        };
+        let (end_byte, end_point) = if self.end_byte <= other.end_byte {
+            (self.end_byte, self.end_point)
+        } else {
+            (other.end_byte, other.end_point)
+        };
+        if start_byte < end_byte {
+            Some(Range {
+                start_byte,
+                end_byte,
+                start_point,
+                end_point,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get the smallest range that covers both this range and another, including any
+    /// gap between them.
+    pub fn union(&self, other: &Range) -> Range {
+        let (start_byte, start_point) = if self.start_byte <= other.start_byte {
+            (self.start_byte, self.start_point)
+        } else {
+            (other.start_byte, other.start_point)
+        };
+        let (end_byte, end_point) = if self.end_byte >= other.end_byte {
+            (self.end_byte, self.end_point)
+        } else {
+            (other.end_byte, other.end_point)
+        };
+        Range {
+            start_byte,
+            end_byte,
+            start_point,
+            end_point,
+        }
+    }
+}
+
+impl InputEdit {
+    /// Get the edit that undoes this one, by replacing the new text with the old.
+    ///
+    /// Applying this edit's inverse to a tree after applying this edit restores the
+    /// original coordinates of the tree's nodes. The edit itself contains everything
+    /// that's needed, so the text that was replaced doesn't need to be provided.
+    pub fn inverse(&self) -> InputEdit {
+        InputEdit {
+            start_byte: self.start_byte,
+            old_end_byte: self.new_end_byte,
+            new_end_byte: self.old_end_byte,
+            start_position: self.start_position,
+            old_end_position: self.new_end_position,
+            new_end_position: self.old_end_position,
+        }
+    }
+}
+
+impl fmt::Display for QueryBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(field) = &self.field {
+            write!(f, "{}: ", field)?;
+        }
+
+        let kind = self.kind.as_deref().unwrap_or("_");
+        if self.named {
+            write!(f, "({}", kind)?;
+            for child in &self.children {
+                write!(f, " {}", child)?;
+            }
+            write!(f, ")")?;
+        } else {
+            write!(f, "\"{}\"", kind.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+
+        if let Some(capture) = &self.capture {
+            write!(f, " @{}", capture)?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Display for Point {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(f, "({}, {})", self.row, self.column)
@@ -2263,6 +4916,299 @@ impl<'a> Iterator for LossyUtf8<'a> {
     }
 }
 
+// Split query source into the byte ranges of its top-level patterns, skipping over
+// strings and comments. Unbalanced parentheses are tolerated, so that this can be
+// used on invalid queries.
+fn pattern_ranges(source: &str) -> Vec<ops::Range<usize>> {
+    let mut result: Vec<ops::Range<usize>> = Vec::new();
+    let mut depth = 0usize;
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let end = match c {
+            ';' => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                continue;
+            }
+            '"' => {
+                let mut end = source.len();
+                while let Some((j, c)) = chars.next() {
+                    if c == '\\' {
+                        chars.next();
+                    } else if c == '"' {
+                        end = j + 1;
+                        break;
+                    }
+                }
+                end
+            }
+            _ if c.is_whitespace() => continue,
+            _ => i + c.len_utf8(),
+        };
+
+        // An opening bracket or a string at the top level starts a new pattern, unless
+        // it is a predicate.
+        let starts_pattern = depth == 0
+            && matches!(c, '(' | '[' | '"')
+            && !(c == '(' && matches!(chars.peek(), Some((_, '#'))));
+        match result.last_mut() {
+            Some(range) if !starts_pattern => range.end = end,
+            _ => result.push(i..end),
+        }
+
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    result
+}
+
+/// The structure of a pattern that consists only of nodes, fields and captures, which is
+/// used to check whether one pattern matches a subset of the nodes that another matches.
+#[derive(Debug)]
+struct PatternShape {
+    kind: String,
+    is_named: bool,
+    field: Option<String>,
+    children: Vec<PatternShape>,
+    has_predicates: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum PatternToken<'a> {
+    Open,
+    Close,
+    String(String),
+    Word(&'a str),
+    Field(&'a str),
+    Capture,
+    Predicate,
+    Other,
+}
+
+impl PatternShape {
+    /// Parse the structure of a top-level pattern. Returns `None` if the pattern uses
+    /// any other syntax, like alternations, quantifiers, anchors or negated fields,
+    /// because these can't be compared in this way.
+    fn parse(source: &str) -> Option<Self> {
+        let tokens = pattern_tokens(source);
+        let mut index = 0;
+        let mut has_predicates = false;
+
+        // A pattern can be grouped with its predicates.
+        let mut result = if tokens.first() == Some(&PatternToken::Open)
+            && matches!(
+                tokens.get(1),
+                Some(PatternToken::Open) | Some(PatternToken::String(_))
+            )
+            && tokens.get(2) != Some(&PatternToken::Predicate)
+        {
+            index = 1;
+            let mut nodes = Vec::new();
+            loop {
+                match tokens.get(index)? {
+                    PatternToken::Close => break,
+                    PatternToken::Open
+                        if tokens.get(index + 1) == Some(&PatternToken::Predicate) =>
+                    {
+                        index = skip_pattern_group(&tokens, index)?;
+                        has_predicates = true;
+                    }
+                    _ => nodes.push(Self::parse_node(&tokens, &mut index, &mut has_predicates)?),
+                }
+            }
+            index += 1;
+            if nodes.len() != 1 || !skip_pattern_captures(&tokens, &mut index) {
+                return None;
+            }
+            nodes.pop()?
+        } else {
+            Self::parse_node(&tokens, &mut index, &mut has_predicates)?
+        };
+
+        while index < tokens.len() {
+            if tokens[index] != PatternToken::Open
+                || tokens.get(index + 1) != Some(&PatternToken::Predicate)
+            {
+                return None;
+            }
+            index = skip_pattern_group(&tokens, index)?;
+            has_predicates = true;
+        }
+        result.has_predicates = has_predicates;
+        Some(result)
+    }
+
+    fn parse_node(
+        tokens: &[PatternToken],
+        index: &mut usize,
+        has_predicates: &mut bool,
+    ) -> Option<Self> {
+        let result = match tokens.get(*index)? {
+            PatternToken::String(text) => {
+                *index += 1;
+                Self {
+                    kind: text.clone(),
+                    is_named: false,
+                    field: None,
+                    children: Vec::new(),
+                    has_predicates: false,
+                }
+            }
+            PatternToken::Open => {
+                let kind = match tokens.get(*index + 1)? {
+                    PatternToken::Word(kind) if *kind != "MISSING" => kind.to_string(),
+                    _ => return None,
+                };
+                *index += 2;
+                let mut children = Vec::new();
+                loop {
+                    match tokens.get(*index)? {
+                        PatternToken::Close => break,
+                        PatternToken::Field(field) => {
+                            *index += 1;
+                            let mut child = Self::parse_node(tokens, index, has_predicates)?;
+                            child.field = Some(field.to_string());
+                            children.push(child);
+                        }
+                        PatternToken::Open
+                            if tokens.get(*index + 1) == Some(&PatternToken::Predicate) =>
+                        {
+                            *index = skip_pattern_group(tokens, *index)?;
+                            *has_predicates = true;
+                        }
+                        _ => children.push(Self::parse_node(tokens, index, has_predicates)?),
+                    }
+                }
+                *index += 1;
+                Self {
+                    kind,
+                    is_named: true,
+                    field: None,
+                    children,
+                    has_predicates: false,
+                }
+            }
+            _ => return None,
+        };
+        if !skip_pattern_captures(tokens, index) {
+            return None;
+        }
+        Some(result)
+    }
+
+    /// Check if every node that matches the other pattern's structure also matches
+    /// this pattern's structure. The children of a pattern match any subsequence of
+    /// a node's children, so this is the case if this pattern's children match a
+    /// subsequence of the other pattern's children, with the same fields.
+    fn covers(&self, other: &Self) -> bool {
+        if self.is_named != other.is_named
+            || (self.kind != other.kind && !(self.is_named && self.kind == "_"))
+        {
+            return false;
+        }
+        let mut other_children = other.children.iter();
+        self.children.iter().all(|child| {
+            other_children.any(|other_child| {
+                child
+                    .field
+                    .as_ref()
+                    .is_none_or(|field| other_child.field.as_ref() == Some(field))
+                    && child.covers(other_child)
+            })
+        })
+    }
+}
+
+/// Split a pattern into the tokens that are needed to find its structure.
+fn pattern_tokens(source: &str) -> Vec<PatternToken<'_>> {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut result = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let token = match c {
+            ';' => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                continue;
+            }
+            '(' => PatternToken::Open,
+            ')' => PatternToken::Close,
+            '"' => {
+                let mut text = String::new();
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some((_, 'n')) => text.push('\n'),
+                            Some((_, 'r')) => text.push('\r'),
+                            Some((_, 't')) => text.push('\t'),
+                            Some((_, '0')) => text.push('\0'),
+                            Some((_, c)) => text.push(c),
+                            None => {}
+                        },
+                        _ => text.push(c),
+                    }
+                }
+                PatternToken::String(text)
+            }
+            '@' | '#' => {
+                while chars
+                    .next_if(|(_, c)| is_word_char(*c) || matches!(c, '.' | '?' | '!'))
+                    .is_some()
+                {}
+                if c == '@' {
+                    PatternToken::Capture
+                } else {
+                    PatternToken::Predicate
+                }
+            }
+            _ if c.is_whitespace() => continue,
+            _ if is_word_char(c) => {
+                let mut end = i + c.len_utf8();
+                while let Some((j, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    end = j + c.len_utf8();
+                }
+                if chars.next_if(|(_, c)| *c == ':').is_some() {
+                    PatternToken::Field(&source[i..end])
+                } else {
+                    PatternToken::Word(&source[i..end])
+                }
+            }
+            _ => PatternToken::Other,
+        };
+        result.push(token);
+    }
+    result
+}
+
+/// Skip the captures after a node, returning `false` if it is followed by a quantifier
+/// or any other syntax.
+fn skip_pattern_captures(tokens: &[PatternToken], index: &mut usize) -> bool {
+    while tokens.get(*index) == Some(&PatternToken::Capture) {
+        *index += 1;
+    }
+    tokens.get(*index) != Some(&PatternToken::Other)
+}
+
+/// Find the end of the parenthesized group that starts at the given token.
+fn skip_pattern_group(tokens: &[PatternToken], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            PatternToken::Open => depth += 1,
+            PatternToken::Close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
 fn predicate_error(row: usize, message: String) -> QueryError {
     QueryError {
         kind: QueryErrorKind::Predicate,
@@ -2273,14 +5219,247 @@ fn predicate_error(row: usize, message: String) -> QueryError {
     }
 }
 
+fn tree_included_ranges(tree: *const ffi::TSTree) -> Vec<Range> {
+    let mut count = 0u32;
+    unsafe {
+        let ptr = ffi::ts_tree_included_ranges(tree, &mut count as *mut u32);
+        util::CBufferIter::new(ptr, count as usize)
+            .map(|r| r.into())
+            .collect()
+    }
+}
+
+/// Get the included ranges that captured nodes must lie within, or an empty list if
+/// there is nothing to check, because skipping is disabled or the tree has no gaps.
+fn gap_checked_ranges(skip_gap_captures: bool, node: Node) -> Vec<Range> {
+    if !skip_gap_captures {
+        return Vec::new();
+    }
+    let ranges = tree_included_ranges(node.0.tree);
+    if ranges.len() > 1 {
+        ranges
+    } else {
+        Vec::new()
+    }
+}
+
+/// Check that a node lies within a single one of the given ranges. An empty list of
+/// ranges is treated as containing everything.
+fn is_within_ranges(node: Node, ranges: &[Range]) -> bool {
+    ranges.is_empty()
+        || ranges
+            .iter()
+            .any(|r| r.start_byte <= node.start_byte() && node.end_byte() <= r.end_byte)
+}
+
+/// Check if a node starts at the beginning of a line. If whitespace is ignored, the node
+/// only needs to be the first token on its line, which is the case if the token before
+/// it ends on an earlier line.
+fn is_at_line_start(node: Node, ignore_whitespace: bool) -> bool {
+    let start = node.start_position();
+    if start.column == 0 {
+        return true;
+    }
+    if !ignore_whitespace {
+        return false;
+    }
+    let mut node = node;
+    loop {
+        if let Some(previous) = node.prev_sibling() {
+            return previous.end_position().row < start.row;
+        }
+        match node.parent() {
+            Some(parent) => node = parent,
+            None => return true,
+        }
+    }
+}
+
+/// Check if any of a node's ancestors has one of the given kinds. Finding a node's
+/// parent requires searching down from the root of its tree, so instead of finding
+/// each ancestor separately, this searches down from the root once, in the same way
+/// as [Node::parent], and checks every node along the way.
+fn has_ancestor_of_kind(node: Node, kind_ids: &[u16]) -> bool {
+    let mut ancestor = unsafe { Node::new(ffi::ts_tree_root_node(node.0.tree)) }.unwrap();
+    let mut cursor = ancestor.walk();
+    while ancestor.id() != node.id() {
+        if kind_ids.contains(&ancestor.kind_id()) {
+            return true;
+        }
+        let child = ancestor
+            .children(&mut cursor)
+            .take_while(|child| child.start_byte() <= node.start_byte() && child.id() != node.id())
+            .find(|child| child.end_byte() >= node.end_byte());
+        match child {
+            Some(child) => ancestor = child,
+            None => break,
+        }
+    }
+    false
+}
+
+/// Detect the encoding of some text from its byte order mark or, if it has none, from
+/// the positions of its zero bytes. In mostly-ASCII UTF16 text, more than half of the
+/// code units have a zero high byte. Some other characters, like U+0100 or U+4E00,
+/// have a zero low byte instead, so these zeros only need to be less common.
+fn detect_encoding(bytes: &[u8]) -> InputEncoding {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return InputEncoding::Utf8;
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return InputEncoding::Utf16LE;
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return InputEncoding::Utf16BE;
+    }
+
+    if bytes.is_empty() || !bytes.len().is_multiple_of(2) {
+        return InputEncoding::Utf8;
+    }
+    let unit_count = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if odd_zeros * 2 > unit_count && even_zeros < odd_zeros {
+        InputEncoding::Utf16LE
+    } else if even_zeros * 2 > unit_count && odd_zeros < even_zeros {
+        InputEncoding::Utf16BE
+    } else {
+        InputEncoding::Utf8
+    }
+}
+
+/// Check if two texts are equal when the case of their letters is ignored.
+fn eq_ignore_case(text1: &[u8], text2: &[u8]) -> bool {
+    if text1.is_ascii() && text2.is_ascii() {
+        return text1.eq_ignore_ascii_case(text2);
+    }
+    String::from_utf8_lossy(text1).to_lowercase() == String::from_utf8_lossy(text2).to_lowercase()
+}
+
+/// Match some text against a glob pattern, in which `*` stands for any sequence of
+/// characters and `?` stands for any single character. Like in file paths, neither
+/// of these wildcards matches a `/`.
+fn glob_match(glob: &[char], text: &[char]) -> bool {
+    // Only a literal `/` can match a `/`, so the parts of the glob and the text between
+    // slashes can be matched separately.
+    let mut glob_parts = glob.split(|c| *c == '/');
+    let mut text_parts = text.split(|c| *c == '/');
+    loop {
+        match (glob_parts.next(), text_parts.next()) {
+            (Some(glob_part), Some(text_part)) => {
+                if !glob_match_part(glob_part, text_part) {
+                    return false;
+                }
+            }
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Match some text that contains no `/` against a glob pattern. When a character
+/// doesn't match, only the most recent `*` needs to be extended, so this takes time
+/// proportional to the product of the lengths rather than growing exponentially with
+/// the number of wildcards.
+fn glob_match_part(glob: &[char], text: &[char]) -> bool {
+    let (mut g, mut t) = (0, 0);
+    let mut last_star = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                last_star = Some((g, t));
+                g += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match last_star {
+                Some((star_g, star_t)) => {
+                    last_star = Some((star_g, star_t + 1));
+                    g = star_g + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
+
+/// Count the UTF16 code units needed to encode some UTF8 text. Each character takes one
+/// unit, except for those encoded in four bytes, which need a surrogate pair.
+fn utf16_len(utf8: &[u8]) -> usize {
+    utf8.iter()
+        .map(|&byte| match byte {
+            0x80..=0xBF => 0,
+            0xF0..=0xFF => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
 impl fmt::Display for IncludedRangesError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Incorrect range by index: {}", self.0)
     }
 }
 
+impl fmt::Display for ParserBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParserBuilderError::Language(error) => error.fmt(f),
+            ParserBuilderError::IncludedRanges(error) => error.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for TreeDeserializationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid serialized tree for this language")
+    }
+}
+
+impl fmt::Display for CaptureNameConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The query already has a capture named @{}", self.0)
+    }
+}
+
+impl fmt::Display for ChildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChildError::OutOfRange { index, child_count } => write!(
+                f,
+                "Child index {} is out of range for a node with {} children",
+                index, child_count
+            ),
+            ChildError::Missing { index } => write!(f, "Child {} is missing", index),
+        }
+    }
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::InvalidRange => write!(f, "Edit starts after it ends"),
+            EditError::OutOfBounds {
+                end_byte,
+                end_position,
+            } => write!(
+                f,
+                "Edit extends beyond the end of the tree at byte {}, position {}",
+                end_byte, end_position
+            ),
+        }
+    }
+}
+
 impl fmt::Display for LanguageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(inconsistency) = self.inconsistency {
+            return write!(f, "Inconsistent language. {}", inconsistency);
+        }
         write!(
             f,
             "Incompatible language version {}. Expected minimum {}, maximum {}",
@@ -2289,6 +5468,12 @@ impl fmt::Display for LanguageError {
     }
 }
 
+impl fmt::Display for QueryWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Query warning at {}. {}", self.row + 1, self.message)
+    }
+}
+
 impl fmt::Display for QueryError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let msg = match self.kind {
@@ -2319,6 +5504,12 @@ extern "C" {
     fn free(ptr: *mut c_void);
 }
 
+thread_local! {
+    // The cursor used by [Node::collect_children]. It is reset to the given node on
+    // every call, so the nodes it pointed to before are never read again.
+    static CHILDREN_CURSOR: RefCell<Option<TreeCursor<'static>>> = const { RefCell::new(None) };
+}
+
 static mut FREE_FN: unsafe extern "C" fn(ptr: *mut c_void) = free;
 
 #[doc(alias = "ts_set_allocator")]
@@ -2332,11 +5523,35 @@ pub unsafe fn set_allocator(
     ffi::ts_set_allocator(new_malloc, new_calloc, new_realloc, new_free);
 }
 
+/// Remove a UTF8 byte order mark from the beginning of some text, if there is one.
+///
+/// Returns the remaining text, along with the number of bytes that were removed, which
+/// is either 0 or 3. Parsing doesn't require this, because the parser already skips a
+/// byte order mark at the start of the document: the resulting tree's root node simply
+/// starts after it, and all positions are relative to the original text, with the byte
+/// order mark counted as three columns. Stripping it first is only needed to obtain
+/// positions that are relative to the text that follows the byte order mark. Those
+/// positions can be converted back by adding the returned length.
+pub fn strip_bom(text: &[u8]) -> (&[u8], usize) {
+    const BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+    if text.starts_with(BOM) {
+        (&text[BOM.len()..], BOM.len())
+    } else {
+        (text, 0)
+    }
+}
+
+impl error::Error for CaptureNameConflictError {}
+impl error::Error for ChildError {}
+impl error::Error for EditError {}
 impl error::Error for IncludedRangesError {}
 impl error::Error for LanguageError {}
+impl error::Error for ParserBuilderError {}
 impl error::Error for QueryError {}
+impl error::Error for TreeDeserializationError {}
 
 unsafe impl Send for Language {}
+unsafe impl Send for LookaheadIterator {}
 unsafe impl Send for Parser {}
 unsafe impl Send for Query {}
 unsafe impl Send for QueryCursor {}
@@ -33,6 +33,7 @@ extern "C" {
 /* Section - Types */
 /*******************/
 
+typedef uint16_t TSStateId;
 typedef uint16_t TSSymbol;
 typedef uint16_t TSFieldId;
 typedef struct TSLanguage TSLanguage;
@@ -40,6 +41,7 @@ typedef struct TSParser TSParser;
 typedef struct TSTree TSTree;
 typedef struct TSQuery TSQuery;
 typedef struct TSQueryCursor TSQueryCursor;
+typedef struct TSLookaheadIterator TSLookaheadIterator;
 
 typedef enum {
   TSInputEncodingUTF8,
@@ -64,6 +66,13 @@ typedef struct {
   uint32_t end_byte;
 } TSRange;
 
+typedef struct {
+  uint32_t bytes_parsed;
+  uint32_t token_count;
+  uint32_t reduction_count;
+  bool had_errors;
+} TSParseStats;
+
 typedef struct {
   void *payload;
   const char *(*read)(void *payload, uint32_t byte_index, TSPoint position, uint32_t *bytes_read);
@@ -165,6 +174,11 @@ void ts_parser_delete(TSParser *parser);
  * Tree-sitter CLI. Check the language's version using `ts_language_version`
  * and compare it to this library's `TREE_SITTER_LANGUAGE_VERSION` and
  * `TREE_SITTER_MIN_COMPATIBLE_LANGUAGE_VERSION` constants.
+ *
+ * Switching languages resets the parser, but keeps the memory that it has
+ * allocated for parsing, such as its parse stack and its pool of syntax tree
+ * nodes, so that it can be reused for the new language. Only the external
+ * scanner is recreated, and that is skipped if the language is unchanged.
  */
 bool ts_parser_set_language(TSParser *self, const TSLanguage *language);
 
@@ -237,7 +251,7 @@ const TSRange *ts_parser_included_ranges(
  *    `TSInputEncodingUTF8` or `TSInputEncodingUTF16`.
  *
  * This function returns a syntax tree on success, and `NULL` on failure. There
- * are three possible reasons for failure:
+ * are four possible reasons for failure:
  * 1. The parser does not have a language assigned. Check for this using the
       `ts_parser_language` function.
  * 2. Parsing was cancelled due to a timeout that was set by an earlier call to
@@ -249,6 +263,9 @@ const TSRange *ts_parser_included_ranges(
  *    earlier call to `ts_parser_set_cancellation_flag`. You can resume parsing
  *    from where the parser left out by calling `ts_parser_parse` again with
  *    the same arguments.
+ * 4. The parse stack grew deeper than the limit that was set by an earlier
+ *    call to `ts_parser_set_max_stack_depth`. In this case, the parser is
+ *    reset, so the next call to `ts_parser_parse` will start from scratch.
  */
 TSTree *ts_parser_parse(
   TSParser *self,
@@ -308,6 +325,33 @@ void ts_parser_set_timeout_micros(TSParser *self, uint64_t timeout);
  */
 uint64_t ts_parser_timeout_micros(const TSParser *self);
 
+/**
+ * Set the maximum number of entries that the parse stack is allowed to
+ * contain before halting.
+ *
+ * Deeply nested input requires a correspondingly deep parse stack. If the
+ * stack grows deeper than this, parsing will halt early, returning NULL.
+ * A value of zero, which is the default, means that there is no limit.
+ * See `ts_parser_parse` for more information.
+ */
+void ts_parser_set_max_stack_depth(TSParser *self, uint32_t max_stack_depth);
+
+/**
+ * Get the maximum number of entries that the parse stack is allowed to contain.
+ */
+uint32_t ts_parser_max_stack_depth(const TSParser *self);
+
+/**
+ * Get statistics about the work that was done by the most recent call to
+ * `ts_parser_parse`: the number of bytes in the resulting tree, the number of
+ * tokens that were lexed, the number of reductions that were performed, and
+ * whether error recovery occurred.
+ *
+ * Returns `false`, leaving `stats` untouched, if the most recent call to
+ * `ts_parser_parse` did not complete.
+ */
+bool ts_parser_last_parse_stats(const TSParser *self, TSParseStats *stats);
+
 /**
  * Set the parser's current cancellation flag pointer.
  *
@@ -381,6 +425,13 @@ TSNode ts_tree_root_node_with_offset(
  */
 const TSLanguage *ts_tree_language(const TSTree *);
 
+/**
+ * Get the array of included ranges that was used to parse the syntax tree.
+ *
+ * The returned pointer must be freed by the caller.
+ */
+TSRange *ts_tree_included_ranges(const TSTree *, uint32_t *length);
+
 /**
  * Edit the syntax tree to keep it in sync with source code that has been
  * edited.
@@ -415,6 +466,25 @@ TSRange *ts_tree_get_changed_ranges(
  */
 void ts_tree_print_dot_graph(const TSTree *, FILE *);
 
+/**
+ * Serialize the syntax tree to a compact binary format, so that it can be
+ * stored and later restored using `ts_tree_deserialize` without reparsing.
+ *
+ * The returned buffer is allocated using the library's allocator, which can be
+ * changed with `ts_set_allocator`, and the caller is responsible for freeing it
+ * using the matching `free` function. The length of the buffer will be written
+ * to the given `length` pointer.
+ */
+char *ts_tree_serialize(const TSTree *self, uint32_t *length);
+
+/**
+ * Restore a syntax tree that was serialized using `ts_tree_serialize`.
+ *
+ * The language must be the one that was used to parse the original tree.
+ * Returns `NULL` if the data is not a valid serialized tree for that language.
+ */
+TSTree *ts_tree_deserialize(const char *data, uint32_t length, const TSLanguage *language);
+
 /******************/
 /* Section - Node */
 /******************/
@@ -429,6 +499,12 @@ const char *ts_node_type(TSNode);
  */
 TSSymbol ts_node_symbol(TSNode);
 
+/**
+ * Get the node's type as a numerical id as it appears in the grammar,
+ * ignoring aliases. For aliased nodes, this differs from `ts_node_symbol`.
+ */
+TSSymbol ts_node_grammar_symbol(TSNode);
+
 /**
  * Get the node's start byte.
  */
@@ -706,6 +782,11 @@ TSQuery *ts_query_new(
  */
 void ts_query_delete(TSQuery *);
 
+/**
+ * Get the language that the query was created for.
+ */
+const TSLanguage *ts_query_language(const TSQuery *);
+
 /**
  * Get the number of patterns, captures, or string literals in the query.
  */
@@ -774,6 +855,17 @@ TSQuantifier ts_query_capture_quantifier_for_id(
   uint32_t capture_id
 );
 
+/**
+ * Get the depth, within the given pattern, of the node that is captured with
+ * the given id. The pattern's outermost nodes have a depth of zero. If the
+ * pattern does not contain the capture, `UINT32_MAX` is returned.
+ */
+uint32_t ts_query_capture_depth_for_id(
+  const TSQuery *,
+  uint32_t pattern_id,
+  uint32_t capture_id
+);
+
 const char *ts_query_string_value_for_id(
   const TSQuery *,
   uint32_t id,
@@ -846,6 +938,57 @@ bool ts_query_cursor_did_exceed_match_limit(const TSQueryCursor *);
 uint32_t ts_query_cursor_match_limit(const TSQueryCursor *);
 void ts_query_cursor_set_match_limit(TSQueryCursor *, uint32_t);
 
+/**
+ * Set the maximum duration in microseconds that query execution should be
+ * allowed to take before halting.
+ *
+ * If query execution takes longer than this, it will halt early, and
+ * `ts_query_cursor_next_match` and `ts_query_cursor_next_capture` will return
+ * `false`. Use `ts_query_cursor_did_exceed_timeout` to distinguish this from
+ * the query having found all of its matches. The timer starts when
+ * `ts_query_cursor_exec` is called. A timeout of zero, which is the default,
+ * means that execution is never halted.
+ */
+void ts_query_cursor_set_timeout_micros(TSQueryCursor *self, uint64_t timeout_micros);
+
+/**
+ * Get the duration in microseconds that query execution is allowed to take.
+ */
+uint64_t ts_query_cursor_timeout_micros(const TSQueryCursor *self);
+
+/**
+ * Check if, on its last execution, this cursor was halted because it exceeded
+ * its timeout.
+ */
+bool ts_query_cursor_did_exceed_timeout(const TSQueryCursor *self);
+
+/**
+ * Set the maximum number of bytes that the query cursor is allowed to allocate
+ * for its in-progress matches.
+ *
+ * If query execution needs more memory than this, it will halt early, and
+ * `ts_query_cursor_next_match` and `ts_query_cursor_next_capture` will return
+ * `false`. Use `ts_query_cursor_did_exceed_max_memory` to distinguish this
+ * from the query having found all of its matches. A limit of zero, which is
+ * the default, means that the memory usage is not limited.
+ *
+ * Only the memory allocated during each execution is counted. Memory that the
+ * cursor retained from earlier executions is reused without counting towards
+ * the limit.
+ */
+void ts_query_cursor_set_max_memory(TSQueryCursor *self, uint64_t max_memory);
+
+/**
+ * Get the maximum number of bytes that the query cursor is allowed to allocate.
+ */
+uint64_t ts_query_cursor_max_memory(const TSQueryCursor *self);
+
+/**
+ * Check if, on its last execution, this cursor was halted because it exceeded
+ * its memory limit.
+ */
+bool ts_query_cursor_did_exceed_max_memory(const TSQueryCursor *self);
+
 /**
  * Set the range of bytes or (row, column) positions in which the query
  * will be executed.
@@ -930,6 +1073,63 @@ TSSymbolType ts_language_symbol_type(const TSLanguage *, TSSymbol);
  */
 uint32_t ts_language_version(const TSLanguage *);
 
+/**
+ * Get the number of valid parse states in the language.
+ */
+uint32_t ts_language_state_count(const TSLanguage *);
+
+/**
+ * Check that the language's tables are internally consistent, so that it can be
+ * used safely. Languages that were loaded from untrusted or possibly corrupt
+ * sources can be checked before they are assigned to a parser.
+ *
+ * This returns `NULL` if the language is consistent. Otherwise, it returns a
+ * static string describing the first inconsistency that was found. The ABI
+ * version is not checked.
+ */
+const char *ts_language_inconsistency(const TSLanguage *);
+
+/**
+ * Get the next parse state, given a current parse state and a symbol.
+ *
+ * Combine this with lookahead iterators to generate completion suggestions or
+ * valid symbols in error nodes. This returns `0` if the symbol cannot follow
+ * the given state.
+ */
+TSStateId ts_language_next_state(const TSLanguage *, TSStateId, TSSymbol);
+
+/********************************/
+/* Section - Lookahead Iterator */
+/********************************/
+
+/**
+ * Create a new lookahead iterator for the given language and parse state.
+ *
+ * This returns `NULL` if the state is invalid for the language.
+ *
+ * Repeatedly using `ts_lookahead_iterator_next` and
+ * `ts_lookahead_iterator_current_symbol` will generate valid symbols in the
+ * given parse state. Both terminal and non-terminal symbols are included.
+ */
+TSLookaheadIterator *ts_lookahead_iterator_new(const TSLanguage *self, TSStateId state);
+
+/**
+ * Delete a lookahead iterator, freeing all the memory used.
+ */
+void ts_lookahead_iterator_delete(TSLookaheadIterator *self);
+
+/**
+ * Advance the lookahead iterator to the next symbol.
+ *
+ * This returns `true` if there is a new symbol and `false` otherwise.
+ */
+bool ts_lookahead_iterator_next(TSLookaheadIterator *self);
+
+/**
+ * Get the current symbol of the lookahead iterator.
+ */
+TSSymbol ts_lookahead_iterator_current_symbol(const TSLookaheadIterator *self);
+
 /**********************************/
 /* Section - Global Configuration */
 /**********************************/
@@ -1,3 +1,4 @@
+#include "./alloc.h"
 #include "./language.h"
 #include "./subtree.h"
 #include "./error_costs.h"
@@ -15,6 +16,58 @@ uint32_t ts_language_field_count(const TSLanguage *self) {
   return self->field_count;
 }
 
+uint32_t ts_language_state_count(const TSLanguage *self) {
+  return self->state_count;
+}
+
+const char *ts_language_inconsistency(const TSLanguage *self) {
+  if (self->symbol_count == 0) return "The language has no symbols";
+  if (self->token_count > self->symbol_count) {
+    return "The language has more tokens than symbols";
+  }
+  if (self->external_token_count > self->token_count) {
+    return "The language has more external tokens than tokens";
+  }
+  if (self->state_count == 0) return "The language has no parse states";
+  if (self->large_state_count > self->state_count) {
+    return "The language has more large parse states than parse states";
+  }
+  if (self->large_state_count > 0 && !self->parse_table) {
+    return "The language is missing its parse table";
+  }
+  if (
+    self->state_count > self->large_state_count &&
+    (!self->small_parse_table || !self->small_parse_table_map)
+  ) return "The language is missing its small parse table";
+  if (!self->parse_actions) return "The language is missing its parse actions";
+  if (!self->lex_modes || !self->lex_fn) {
+    return "The language is missing its lexer";
+  }
+  if (!self->symbol_names || !self->symbol_metadata || !self->public_symbol_map) {
+    return "The language is missing its symbol tables";
+  }
+  for (unsigned i = 0; i < self->symbol_count; i++) {
+    if (!self->symbol_names[i]) return "The language has a symbol without a name";
+    if (self->public_symbol_map[i] >= self->symbol_count) {
+      return "The language maps a symbol to an invalid public symbol";
+    }
+  }
+  if (self->field_count > 0) {
+    if (!self->field_names) return "The language is missing its field names";
+    if (self->production_id_count > 0 && (!self->field_map_slices || !self->field_map_entries)) {
+      return "The language is missing its field maps";
+    }
+  }
+  if (self->alias_count > 0 && self->max_alias_sequence_length > 0 && !self->alias_sequences) {
+    return "The language is missing its alias sequences";
+  }
+  if (
+    self->external_token_count > 0 &&
+    (!self->external_scanner.states || !self->external_scanner.symbol_map || !self->external_scanner.scan)
+  ) return "The language is missing its external scanner";
+  return NULL;
+}
+
 void ts_language_table_entry(
   const TSLanguage *self,
   TSStateId state,
@@ -135,3 +188,46 @@ TSFieldId ts_language_field_id_for_name(
   }
   return 0;
 }
+
+TSStateId ts_language_next_state(
+  const TSLanguage *self,
+  TSStateId state,
+  TSSymbol symbol
+) {
+  if (symbol == ts_builtin_sym_error || symbol == ts_builtin_sym_error_repeat) {
+    return 0;
+  } else if (symbol < self->token_count) {
+    uint32_t count;
+    const TSParseAction *actions = ts_language_actions(self, state, symbol, &count);
+    if (count > 0) {
+      TSParseAction action = actions[count - 1];
+      if (action.type == TSParseActionTypeShift) {
+        return action.shift.extra ? state : action.shift.state;
+      }
+    }
+    return 0;
+  } else {
+    return ts_language_lookup(self, state, symbol);
+  }
+}
+
+TSLookaheadIterator *ts_lookahead_iterator_new(const TSLanguage *self, TSStateId state) {
+  if (state >= self->state_count) return NULL;
+  LookaheadIterator *iterator = ts_malloc(sizeof(LookaheadIterator));
+  *iterator = ts_language_lookaheads(self, state);
+  return (TSLookaheadIterator *)iterator;
+}
+
+void ts_lookahead_iterator_delete(TSLookaheadIterator *self) {
+  ts_free(self);
+}
+
+bool ts_lookahead_iterator_next(TSLookaheadIterator *self) {
+  LookaheadIterator *iterator = (LookaheadIterator *)self;
+  return ts_lookahead_iterator__next(iterator);
+}
+
+TSSymbol ts_lookahead_iterator_current_symbol(const TSLookaheadIterator *self) {
+  const LookaheadIterator *iterator = (const LookaheadIterator *)self;
+  return iterator->symbol;
+}
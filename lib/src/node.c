@@ -417,6 +417,10 @@ TSSymbol ts_node_symbol(TSNode self) {
   return ts_language_public_symbol(self.tree->language, symbol);
 }
 
+TSSymbol ts_node_grammar_symbol(TSNode self) {
+  return ts_subtree_symbol(ts_node__subtree(self));
+}
+
 const char *ts_node_type(TSNode self) {
   TSSymbol symbol = ts_node__alias(&self);
   if (!symbol) symbol = ts_subtree_symbol(ts_node__subtree(self));
@@ -99,6 +99,9 @@ struct TSParser {
   FILE *dot_graph_file;
   TSClock end_clock;
   TSDuration timeout_duration;
+  unsigned max_stack_depth;
+  TSParseStats stats;
+  bool has_stats;
   unsigned accept_count;
   unsigned operation_count;
   const volatile size_t *cancellation_flag;
@@ -579,6 +582,7 @@ static Subtree ts_parser__lex(
     SYM_NAME(ts_subtree_symbol(result)),
     ts_subtree_total_size(result).bytes
   );
+  self->stats.token_count++;
   return result;
 }
 
@@ -821,6 +825,7 @@ static StackVersion ts_parser__reduce(
   bool end_of_non_terminal_extra
 ) {
   uint32_t initial_version_count = ts_stack_version_count(self->stack);
+  self->stats.reduction_count++;
 
   // Pop the given number of nodes from the given version of the parse stack.
   // If stack versions have previously merged, then there may be more than one
@@ -1310,6 +1315,7 @@ static void ts_parser__handle_error(
   Subtree lookahead
 ) {
   uint32_t previous_version_count = ts_stack_version_count(self->stack);
+  self->stats.had_errors = true;
 
   // Perform any reductions that can happen in this state, regardless of the lookahead. After
   // skipping one or more invalid tokens, the parser might find a token that would have allowed
@@ -1738,6 +1744,9 @@ TSParser *ts_parser_new(void) {
   self->dot_graph_file = NULL;
   self->cancellation_flag = NULL;
   self->timeout_duration = 0;
+  self->max_stack_depth = 0;
+  self->stats = (TSParseStats) {0};
+  self->has_stats = false;
   self->end_clock = clock_null();
   self->operation_count = 0;
   self->old_tree = NULL_SUBTREE;
@@ -1782,6 +1791,13 @@ bool ts_parser_set_language(TSParser *self, const TSLanguage *language) {
     if (language->version < TREE_SITTER_MIN_COMPATIBLE_LANGUAGE_VERSION) return false;
   }
 
+  // When the language is unchanged, keep its external scanner, whose state is
+  // cleared when the parser is reset.
+  if (language == self->language) {
+    ts_parser_reset(self);
+    return true;
+  }
+
   if (self->external_scanner_payload && self->language->external_scanner.destroy) {
     self->language->external_scanner.destroy(self->external_scanner_payload);
   }
@@ -1833,6 +1849,20 @@ void ts_parser_set_timeout_micros(TSParser *self, uint64_t timeout_micros) {
   self->timeout_duration = duration_from_micros(timeout_micros);
 }
 
+uint32_t ts_parser_max_stack_depth(const TSParser *self) {
+  return self->max_stack_depth;
+}
+
+void ts_parser_set_max_stack_depth(TSParser *self, uint32_t max_stack_depth) {
+  self->max_stack_depth = max_stack_depth;
+}
+
+bool ts_parser_last_parse_stats(const TSParser *self, TSParseStats *stats) {
+  if (!self->has_stats) return false;
+  *stats = self->stats;
+  return true;
+}
+
 bool ts_parser_set_included_ranges(
   TSParser *self,
   const TSRange *ranges,
@@ -1878,6 +1908,12 @@ TSTree *ts_parser_parse(
   array_clear(&self->included_range_differences);
   self->included_range_difference_index = 0;
 
+  // Unless a previous parse is being resumed, start collecting new statistics.
+  if (!ts_parser_has_outstanding_parse(self)) {
+    self->stats = (TSParseStats) {0};
+    self->has_stats = false;
+  }
+
   if (ts_parser_has_outstanding_parse(self)) {
     LOG("resume_parsing");
   } else if (old_tree) {
@@ -1929,6 +1965,15 @@ TSTree *ts_parser_parse(
         if (!ts_parser__advance(self, version, allow_node_reuse)) return NULL;
         LOG_STACK();
 
+        if (
+          self->max_stack_depth &&
+          ts_stack_depth(self->stack, version) > self->max_stack_depth
+        ) {
+          LOG("exceeded_max_stack_depth depth:%u", ts_stack_depth(self->stack, version));
+          ts_parser_reset(self);
+          return NULL;
+        }
+
         position = ts_stack_position(self->stack, version).bytes;
         if (position > last_position || (version > 0 && position == last_position)) {
           last_position = position;
@@ -1954,6 +1999,8 @@ TSTree *ts_parser_parse(
 
   assert(self->finished_tree.ptr);
   ts_subtree_balance(self->finished_tree, &self->tree_pool, self->language);
+  self->stats.bytes_parsed = ts_subtree_total_bytes(self->finished_tree);
+  self->has_stats = true;
   LOG("done");
   LOG_TREE(self->finished_tree);
 
@@ -1,6 +1,7 @@
 #include "tree_sitter/api.h"
 #include "./alloc.h"
 #include "./array.h"
+#include "./clock.h"
 #include "./language.h"
 #include "./point.h"
 #include "./tree_cursor.h"
@@ -24,6 +25,8 @@ typedef struct {
   const char *input;
   const char *start;
   const char *end;
+  const char *token_end;
+  const char *whitespace_end;
   int32_t next;
   uint8_t next_size;
 } Stream;
@@ -297,15 +300,23 @@ struct TSQueryCursor {
   TSPoint start_point;
   TSPoint end_point;
   uint32_t next_state_id;
+  TSClock end_clock;
+  TSDuration timeout_duration;
+  uint64_t max_memory;
+  uint64_t allocated_memory;
+  unsigned operation_count;
   bool ascending;
   bool halted;
   bool did_exceed_match_limit;
+  bool did_exceed_timeout;
+  bool did_exceed_max_memory;
 };
 
 static const TSQueryError PARENT_DONE = -1;
 static const uint16_t PATTERN_DONE_MARKER = UINT16_MAX;
 static const uint16_t NONE = UINT16_MAX;
 static const TSSymbol WILDCARD_SYMBOL = 0;
+static const unsigned OP_COUNT_PER_QUERY_TIMEOUT_CHECK = 100;
 
 /**********
  * Stream
@@ -345,12 +356,18 @@ static Stream stream_new(const char *string, uint32_t length) {
     .input = string,
     .start = string,
     .end = string + length,
+    .token_end = string,
+    .whitespace_end = string,
   };
   stream_advance(&self);
   return self;
 }
 
 static void stream_skip_whitespace(Stream *self) {
+  // Keep track of where the last token ended, so that errors at the end
+  // of the input can point at it, rather than at trailing whitespace or
+  // comments.
+  if (self->input != self->whitespace_end) self->token_end = self->input;
   for (;;) {
     if (iswspace(self->next)) {
       stream_advance(self);
@@ -364,6 +381,7 @@ static void stream_skip_whitespace(Stream *self) {
       break;
     }
   }
+  self->whitespace_end = self->input;
 }
 
 static bool stream_is_ident_start(Stream *self) {
@@ -1182,7 +1200,7 @@ static bool ts_query__analyze_patterns(TSQuery *self, unsigned *error_offset) {
   for (TSStateId state = 1; state < self->language->state_count; state++) {
     unsigned subgraph_index, exists;
     LookaheadIterator lookahead_iterator = ts_language_lookaheads(self->language, state);
-    while (ts_lookahead_iterator_next(&lookahead_iterator)) {
+    while (ts_lookahead_iterator__next(&lookahead_iterator)) {
       if (lookahead_iterator.action_count) {
         for (unsigned i = 0; i < lookahead_iterator.action_count; i++) {
           const TSParseAction *action = &lookahead_iterator.actions[i];
@@ -1478,7 +1496,7 @@ static bool ts_query__analyze_patterns(TSQuery *self, unsigned *error_offset) {
         // Follow every possible path in the parse table, but only visit states that
         // are part of the subgraph for the current symbol.
         LookaheadIterator lookahead_iterator = ts_language_lookaheads(self->language, parse_state);
-        while (ts_lookahead_iterator_next(&lookahead_iterator)) {
+        while (ts_lookahead_iterator__next(&lookahead_iterator)) {
           TSSymbol sym = lookahead_iterator.symbol;
 
           AnalysisSubgraphNode successor = {
@@ -2602,6 +2620,13 @@ TSQuery *ts_query_new(
     if (*error_type) {
       if (*error_type == PARENT_DONE) *error_type = TSQueryErrorSyntax;
       *error_offset = stream_offset(&stream);
+
+      // If the input ended in the middle of a pattern, report the error at the
+      // end of the pattern's last token.
+      if (*error_type == TSQueryErrorSyntax && stream.next == 0) {
+        stream_skip_whitespace(&stream);
+        *error_offset = stream.token_end - stream.start;
+      }
       capture_quantifiers_delete(&capture_quantifiers);
       ts_query_delete(self);
       return NULL;
@@ -2696,6 +2721,10 @@ void ts_query_delete(TSQuery *self) {
   }
 }
 
+const TSLanguage *ts_query_language(const TSQuery *self) {
+  return self->language;
+}
+
 uint32_t ts_query_pattern_count(const TSQuery *self) {
   return self->patterns.size;
 }
@@ -2725,6 +2754,24 @@ TSQuantifier ts_query_capture_quantifier_for_id(
   return capture_quantifier_for_id(capture_quantifiers, capture_index);
 }
 
+uint32_t ts_query_capture_depth_for_id(
+  const TSQuery *self,
+  uint32_t pattern_index,
+  uint32_t capture_index
+) {
+  if (pattern_index >= self->patterns.size) return UINT32_MAX;
+  Slice steps = self->patterns.contents[pattern_index].steps;
+  for (unsigned i = steps.offset; i < steps.offset + steps.length; i++) {
+    QueryStep *step = &self->steps.contents[i];
+    if (step->depth == PATTERN_DONE_MARKER) break;
+    for (unsigned j = 0; j < MAX_STEP_CAPTURE_COUNT; j++) {
+      if (step->capture_ids[j] == NONE) break;
+      if (step->capture_ids[j] == capture_index) return step->depth;
+    }
+  }
+  return UINT32_MAX;
+}
+
 const char *ts_query_string_value_for_id(
   const TSQuery *self,
   uint32_t index,
@@ -2836,6 +2883,13 @@ TSQueryCursor *ts_query_cursor_new(void) {
   TSQueryCursor *self = ts_malloc(sizeof(TSQueryCursor));
   *self = (TSQueryCursor) {
     .did_exceed_match_limit = false,
+    .did_exceed_timeout = false,
+    .did_exceed_max_memory = false,
+    .timeout_duration = 0,
+    .max_memory = 0,
+    .allocated_memory = 0,
+    .end_clock = clock_null(),
+    .operation_count = 0,
     .ascending = false,
     .halted = false,
     .states = array_new(),
@@ -2871,6 +2925,43 @@ void ts_query_cursor_set_match_limit(TSQueryCursor *self, uint32_t limit) {
   self->capture_list_pool.max_capture_list_count = limit;
 }
 
+uint64_t ts_query_cursor_timeout_micros(const TSQueryCursor *self) {
+  return duration_to_micros(self->timeout_duration);
+}
+
+void ts_query_cursor_set_timeout_micros(TSQueryCursor *self, uint64_t timeout_micros) {
+  self->timeout_duration = duration_from_micros(timeout_micros);
+}
+
+bool ts_query_cursor_did_exceed_timeout(const TSQueryCursor *self) {
+  return self->did_exceed_timeout;
+}
+
+uint64_t ts_query_cursor_max_memory(const TSQueryCursor *self) {
+  return self->max_memory;
+}
+
+void ts_query_cursor_set_max_memory(TSQueryCursor *self, uint64_t max_memory) {
+  self->max_memory = max_memory;
+}
+
+bool ts_query_cursor_did_exceed_max_memory(const TSQueryCursor *self) {
+  return self->did_exceed_max_memory;
+}
+
+// Count the bytes that were allocated when one of the cursor's arrays grew
+// from `old_capacity`. Memory that was retained from a previous execution is
+// reused without being counted, so only the growth during this execution is
+// checked against the cursor's memory limit.
+static void ts_query_cursor__did_grow(
+  TSQueryCursor *self,
+  uint32_t old_capacity,
+  uint32_t new_capacity,
+  size_t element_size
+) {
+  self->allocated_memory += (uint64_t)(new_capacity - old_capacity) * element_size;
+}
+
 void ts_query_cursor_exec(
   TSQueryCursor *self,
   const TSQuery *query,
@@ -2886,6 +2977,15 @@ void ts_query_cursor_exec(
   self->halted = false;
   self->query = query;
   self->did_exceed_match_limit = false;
+  self->did_exceed_timeout = false;
+  self->did_exceed_max_memory = false;
+  self->allocated_memory = 0;
+  self->operation_count = 0;
+  if (self->timeout_duration) {
+    self->end_clock = clock_after(clock_now(), self->timeout_duration);
+  } else {
+    self->end_clock = clock_null();
+  }
 }
 
 void ts_query_cursor_set_byte_range(
@@ -3095,6 +3195,7 @@ static void ts_query_cursor__add_state(
     pattern->pattern_index,
     pattern->step_index
   );
+  uint32_t capacity = self->states.capacity;
   array_insert(&self->states, index, ((QueryState) {
     .id = UINT32_MAX,
     .capture_list_id = NONE,
@@ -3107,6 +3208,7 @@ static void ts_query_cursor__add_state(
     .needs_parent = step->depth == 1,
     .dead = false,
   }));
+  ts_query_cursor__did_grow(self, capacity, self->states.capacity, sizeof(QueryState));
 }
 
 // Acquire a capture list for this state. If there are no capture lists left in the
@@ -3118,7 +3220,14 @@ static CaptureList *ts_query_cursor__prepare_to_capture(
   unsigned state_index_to_preserve
 ) {
   if (state->capture_list_id == NONE) {
+    uint32_t capacity = self->capture_list_pool.list.capacity;
     state->capture_list_id = capture_list_pool_acquire(&self->capture_list_pool);
+    ts_query_cursor__did_grow(
+      self,
+      capacity,
+      self->capture_list_pool.list.capacity,
+      sizeof(CaptureList)
+    );
 
     // If there are no capture lists left in the pool, then terminate whichever
     // state has captured the earliest node in the document, and steal its
@@ -3175,7 +3284,9 @@ static void ts_query_cursor__capture(
   for (unsigned j = 0; j < MAX_STEP_CAPTURE_COUNT; j++) {
     uint16_t capture_id = step->capture_ids[j];
     if (step->capture_ids[j] == NONE) break;
+    uint32_t capacity = capture_list->capacity;
     array_push(capture_list, ((TSQueryCapture) { node, capture_id }));
+    ts_query_cursor__did_grow(self, capacity, capture_list->capacity, sizeof(TSQueryCapture));
     LOG(
       "  capture node. type:%s, pattern:%u, capture_id:%u, capture_count:%u\n",
       ts_node_type(node),
@@ -3206,10 +3317,14 @@ static QueryState *ts_query_cursor__copy_state(
       &self->capture_list_pool,
       state->capture_list_id
     );
+    uint32_t capacity = new_captures->capacity;
     array_push_all(new_captures, old_captures);
+    ts_query_cursor__did_grow(self, capacity, new_captures->capacity, sizeof(TSQueryCapture));
   }
 
+  uint32_t capacity = self->states.capacity;
   array_insert(&self->states, state_index + 1, copy);
+  ts_query_cursor__did_grow(self, capacity, self->states.capacity, sizeof(QueryState));
   *state_ref = &self->states.contents[state_index];
   return &self->states.contents[state_index + 1];
 }
@@ -3236,6 +3351,41 @@ static inline bool ts_query_cursor__advance(
 
     if (did_match || self->halted) return did_match;
 
+    // If the timeout has elapsed, halt, discarding any matches that have not
+    // been returned yet.
+    if (++self->operation_count == OP_COUNT_PER_QUERY_TIMEOUT_CHECK) {
+      self->operation_count = 0;
+      if (!clock_is_null(self->end_clock) && clock_is_gt(clock_now(), self->end_clock)) {
+        LOG("halt due to timeout\n");
+        self->halted = true;
+        self->did_exceed_timeout = true;
+        while (self->finished_states.size > 0) {
+          QueryState state = array_pop(&self->finished_states);
+          capture_list_pool_release(
+            &self->capture_list_pool,
+            state.capture_list_id
+          );
+        }
+        continue;
+      }
+    }
+
+    // If the cursor has allocated more memory than it is allowed to use, halt,
+    // discarding any matches that have not been returned yet.
+    if (self->max_memory && self->allocated_memory > self->max_memory) {
+      LOG("halt due to memory limit\n");
+      self->halted = true;
+      self->did_exceed_max_memory = true;
+      while (self->finished_states.size > 0) {
+        QueryState state = array_pop(&self->finished_states);
+        capture_list_pool_release(
+          &self->capture_list_pool,
+          state.capture_list_id
+        );
+      }
+      continue;
+    }
+
     // Exit the current node.
     if (self->ascending) {
       LOG(
@@ -3265,7 +3415,14 @@ static inline bool ts_query_cursor__advance(
         if (step->depth == PATTERN_DONE_MARKER) {
           if (state->start_depth > self->depth || self->halted) {
             LOG("  finish pattern %u\n", state->pattern_index);
+            uint32_t capacity = self->finished_states.capacity;
             array_push(&self->finished_states, *state);
+            ts_query_cursor__did_grow(
+              self,
+              capacity,
+              self->finished_states.capacity,
+              sizeof(QueryState)
+            );
             did_match = true;
             deleted_count++;
             continue;
@@ -3668,7 +3825,14 @@ static inline bool ts_query_cursor__advance(
               LOG("  defer finishing pattern %u\n", state->pattern_index);
             } else {
               LOG("  finish pattern %u\n", state->pattern_index);
+              uint32_t capacity = self->finished_states.capacity;
               array_push(&self->finished_states, *state);
+              ts_query_cursor__did_grow(
+                self,
+                capacity,
+                self->finished_states.capacity,
+                sizeof(QueryState)
+              );
               array_erase(&self->states, state - self->states.contents);
               did_match = true;
               i--;
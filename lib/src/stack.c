@@ -33,6 +33,7 @@ struct StackNode {
   uint32_t ref_count;
   unsigned error_cost;
   unsigned node_count;
+  unsigned depth;
   int dynamic_precedence;
 };
 
@@ -148,6 +149,7 @@ static StackNode *stack_node_new(
     node->error_cost = previous_node->error_cost;
     node->dynamic_precedence = previous_node->dynamic_precedence;
     node->node_count = previous_node->node_count;
+    node->depth = previous_node->depth + 1;
 
     if (subtree.ptr) {
       node->error_cost += ts_subtree_error_cost(subtree);
@@ -158,6 +160,7 @@ static StackNode *stack_node_new(
   } else {
     node->position = length_zero();
     node->error_cost = 0;
+    node->depth = 0;
   }
 
   return node;
@@ -244,6 +247,7 @@ static void stack_node_add_link(
   }
 
   if (node_count > self->node_count) self->node_count = node_count;
+  if (link.node->depth + 1 > self->depth) self->depth = link.node->depth + 1;
   if (dynamic_precedence > self->dynamic_precedence) self->dynamic_precedence = dynamic_precedence;
 }
 
@@ -452,6 +456,10 @@ Length ts_stack_position(const Stack *self, StackVersion version) {
   return array_get(&self->heads, version)->node->position;
 }
 
+unsigned ts_stack_depth(const Stack *self, StackVersion version) {
+  return array_get(&self->heads, version)->node->depth;
+}
+
 Subtree ts_stack_last_external_token(const Stack *self, StackVersion version) {
   return array_get(&self->heads, version)->last_external_token;
 }
@@ -50,6 +50,9 @@ void ts_stack_set_last_external_token(Stack *, StackVersion, Subtree );
 // Get the position of the given version of the stack within the document.
 Length ts_stack_position(const Stack *, StackVersion);
 
+// Get the maximum number of entries on the given version of the stack.
+unsigned ts_stack_depth(const Stack *, StackVersion);
+
 // Push a tree and state onto the given version of the stack.
 //
 // This transfers ownership of the tree to the Stack. Callers that
@@ -1,6 +1,7 @@
 #include "tree_sitter/api.h"
 #include "./array.h"
 #include "./get_changed_ranges.h"
+#include "./language.h"
 #include "./length.h"
 #include "./subtree.h"
 #include "./tree_cursor.h"
@@ -51,6 +52,13 @@ const TSLanguage *ts_tree_language(const TSTree *self) {
   return self->language;
 }
 
+TSRange *ts_tree_included_ranges(const TSTree *self, uint32_t *length) {
+  *length = self->included_range_count;
+  TSRange *ranges = ts_calloc(self->included_range_count, sizeof(TSRange));
+  memcpy(ranges, self->included_ranges, self->included_range_count * sizeof(TSRange));
+  return ranges;
+}
+
 void ts_tree_edit(TSTree *self, const TSInputEdit *edit) {
   for (unsigned i = 0; i < self->included_range_count; i++) {
     TSRange *range = &self->included_ranges[i];
@@ -113,3 +121,354 @@ TSRange *ts_tree_get_changed_ranges(const TSTree *self, const TSTree *other, uin
 void ts_tree_print_dot_graph(const TSTree *self, FILE *file) {
   ts_subtree_print_dot_graph(self->root, self->language, file);
 }
+
+// The format of serialized trees. The subtrees are written in pre-order, each with
+// a set of flags, and with all integers in little-endian byte order. Parent nodes'
+// positions are not stored, because they are recomputed from their children.
+#define TREE_SERIALIZATION_VERSION 1
+
+enum {
+  SerializedSubtreeNonterminal = 1 << 0,
+  SerializedSubtreeExtra = 1 << 1,
+  SerializedSubtreeMissing = 1 << 2,
+  SerializedSubtreeKeyword = 1 << 3,
+  SerializedSubtreeHasChanges = 1 << 4,
+  SerializedSubtreeFragileLeft = 1 << 5,
+  SerializedSubtreeFragileRight = 1 << 6,
+  SerializedSubtreeHasExternalTokens = 1 << 7,
+  SerializedSubtreeHasExternalScannerStateChange = 1 << 8,
+  SerializedSubtreeDependsOnColumn = 1 << 9,
+};
+
+typedef Array(char) SerializationBuffer;
+
+typedef struct {
+  const char *data;
+  uint32_t length;
+  uint32_t position;
+  bool failed;
+} SerializationReader;
+
+typedef struct {
+  TSSymbol symbol;
+  uint16_t flags;
+  TSStateId parse_state;
+  uint16_t production_id;
+  int32_t dynamic_precedence;
+  uint32_t child_count;
+  SubtreeArray children;
+} DeserializationFrame;
+
+static void ts_tree__write_int(SerializationBuffer *self, uint32_t value, unsigned size) {
+  for (unsigned i = 0; i < size; i++) {
+    array_push(self, (char)(value >> (8 * i)));
+  }
+}
+
+static void ts_tree__write_length(SerializationBuffer *self, Length length) {
+  ts_tree__write_int(self, length.bytes, 4);
+  ts_tree__write_int(self, length.extent.row, 4);
+  ts_tree__write_int(self, length.extent.column, 4);
+}
+
+static uint32_t ts_tree__read_int(SerializationReader *self, unsigned size) {
+  if (self->failed || self->length - self->position < size) {
+    self->failed = true;
+    return 0;
+  }
+  uint32_t result = 0;
+  for (unsigned i = 0; i < size; i++) {
+    result |= (uint32_t)(uint8_t)self->data[self->position++] << (8 * i);
+  }
+  return result;
+}
+
+static Length ts_tree__read_length(SerializationReader *self) {
+  Length result;
+  result.bytes = ts_tree__read_int(self, 4);
+  result.extent.row = ts_tree__read_int(self, 4);
+  result.extent.column = ts_tree__read_int(self, 4);
+  return result;
+}
+
+// Check whether a subtree is a nonterminal node, which is written along with its
+// children. This includes nodes without any children, such as the root node of
+// an empty document. Error leaves are the only terminals whose symbol isn't a token.
+static bool ts_tree__is_nonterminal(Subtree self, const TSLanguage *language) {
+  if (ts_subtree_child_count(self) > 0) return true;
+  TSSymbol symbol = ts_subtree_symbol(self);
+  return symbol >= language->token_count && symbol != ts_builtin_sym_error;
+}
+
+static uint16_t ts_tree__subtree_flags(Subtree self, const TSLanguage *language) {
+  uint16_t result = 0;
+  if (ts_tree__is_nonterminal(self, language)) result |= SerializedSubtreeNonterminal;
+  if (ts_subtree_extra(self)) result |= SerializedSubtreeExtra;
+  if (ts_subtree_missing(self)) result |= SerializedSubtreeMissing;
+  if (ts_subtree_is_keyword(self)) result |= SerializedSubtreeKeyword;
+  if (ts_subtree_has_changes(self)) result |= SerializedSubtreeHasChanges;
+  if (ts_subtree_fragile_left(self)) result |= SerializedSubtreeFragileLeft;
+  if (ts_subtree_fragile_right(self)) result |= SerializedSubtreeFragileRight;
+  if (ts_subtree_has_external_tokens(self)) result |= SerializedSubtreeHasExternalTokens;
+  if (ts_subtree_has_external_scanner_state_change(self)) {
+    result |= SerializedSubtreeHasExternalScannerStateChange;
+  }
+  if (ts_subtree_depends_on_column(self)) result |= SerializedSubtreeDependsOnColumn;
+  return result;
+}
+
+// Restore the flags that are not determined by the way that a subtree was created.
+static void ts_tree__restore_subtree_flags(MutableSubtree *self, uint16_t flags) {
+  ts_subtree_set_extra(self, flags & SerializedSubtreeExtra);
+  if (self->data.is_inline) {
+    self->data.is_missing = flags & SerializedSubtreeMissing;
+    self->data.has_changes = flags & SerializedSubtreeHasChanges;
+  } else {
+    self->ptr->is_missing = flags & SerializedSubtreeMissing;
+    self->ptr->has_changes = flags & SerializedSubtreeHasChanges;
+    self->ptr->fragile_left = flags & SerializedSubtreeFragileLeft;
+    self->ptr->fragile_right = flags & SerializedSubtreeFragileRight;
+    self->ptr->has_external_scanner_state_change =
+      flags & SerializedSubtreeHasExternalScannerStateChange;
+    self->ptr->depends_on_column = flags & SerializedSubtreeDependsOnColumn;
+  }
+}
+
+char *ts_tree_serialize(const TSTree *self, uint32_t *length) {
+  SerializationBuffer buffer = array_new();
+  ts_tree__write_int(&buffer, TREE_SERIALIZATION_VERSION, 4);
+  ts_tree__write_int(&buffer, self->language->symbol_count, 4);
+  ts_tree__write_int(&buffer, self->included_range_count, 4);
+  for (unsigned i = 0; i < self->included_range_count; i++) {
+    TSRange *range = &self->included_ranges[i];
+    ts_tree__write_int(&buffer, range->start_byte, 4);
+    ts_tree__write_int(&buffer, range->end_byte, 4);
+    ts_tree__write_int(&buffer, range->start_point.row, 4);
+    ts_tree__write_int(&buffer, range->start_point.column, 4);
+    ts_tree__write_int(&buffer, range->end_point.row, 4);
+    ts_tree__write_int(&buffer, range->end_point.column, 4);
+  }
+
+  SubtreeArray stack = array_new();
+  array_push(&stack, self->root);
+  while (stack.size > 0) {
+    Subtree subtree = array_pop(&stack);
+    uint16_t flags = ts_tree__subtree_flags(subtree, self->language);
+    ts_tree__write_int(&buffer, ts_subtree_symbol(subtree), 2);
+    ts_tree__write_int(&buffer, flags, 2);
+    ts_tree__write_int(&buffer, ts_subtree_parse_state(subtree), 2);
+
+    if (flags & SerializedSubtreeNonterminal) {
+      uint32_t child_count = ts_subtree_child_count(subtree);
+      ts_tree__write_int(&buffer, child_count, 4);
+      ts_tree__write_int(&buffer, ts_subtree_production_id(subtree), 2);
+      ts_tree__write_int(&buffer, ts_subtree_dynamic_precedence(subtree), 4);
+
+      // Push the children in reverse order, so that they are written in order.
+      Subtree *children = ts_subtree_children(subtree);
+      for (uint32_t i = child_count; i > 0; i--) {
+        array_push(&stack, children[i - 1]);
+      }
+    } else {
+      ts_tree__write_length(&buffer, ts_subtree_padding(subtree));
+      ts_tree__write_length(&buffer, ts_subtree_size(subtree));
+      ts_tree__write_int(&buffer, ts_subtree_lookahead_bytes(subtree), 4);
+      if (ts_subtree_symbol(subtree) == ts_builtin_sym_error) {
+        ts_tree__write_int(&buffer, subtree.ptr->lookahead_char, 4);
+      }
+      if (flags & SerializedSubtreeHasExternalTokens) {
+        const ExternalScannerState *state = &subtree.ptr->external_scanner_state;
+        ts_tree__write_int(&buffer, state->length, 4);
+        array_extend(&buffer, state->length, ts_external_scanner_state_data(state));
+      }
+    }
+  }
+  array_delete(&stack);
+
+  *length = buffer.size;
+  return buffer.contents;
+}
+
+// Read the data of a leaf, after its symbol, flags and parse state.
+static Subtree ts_tree__read_leaf(
+  SerializationReader *reader,
+  SubtreePool *pool,
+  TSSymbol symbol,
+  uint16_t flags,
+  TSStateId parse_state,
+  const TSLanguage *language
+) {
+  Length padding = ts_tree__read_length(reader);
+  Length size = ts_tree__read_length(reader);
+  uint32_t lookahead_bytes = ts_tree__read_int(reader, 4);
+  int32_t lookahead_char = 0;
+  if (symbol == ts_builtin_sym_error) lookahead_char = ts_tree__read_int(reader, 4);
+  uint32_t state_length = 0;
+  const char *state_data = NULL;
+  if (flags & SerializedSubtreeHasExternalTokens) {
+    state_length = ts_tree__read_int(reader, 4);
+    if (
+      symbol == ts_builtin_sym_error ||
+      reader->length - reader->position < state_length
+    ) reader->failed = true;
+    if (reader->failed) return NULL_SUBTREE;
+    state_data = &reader->data[reader->position];
+    reader->position += state_length;
+  }
+  if (reader->failed) return NULL_SUBTREE;
+
+  Subtree subtree;
+  if (symbol == ts_builtin_sym_error) {
+    subtree = ts_subtree_new_error(
+      pool, lookahead_char, padding, size, lookahead_bytes, parse_state, language
+    );
+  } else {
+    subtree = ts_subtree_new_leaf(
+      pool, symbol, padding, size, lookahead_bytes, parse_state,
+      flags & SerializedSubtreeHasExternalTokens,
+      flags & SerializedSubtreeDependsOnColumn,
+      flags & SerializedSubtreeKeyword,
+      language
+    );
+  }
+  MutableSubtree mut_subtree = ts_subtree_to_mut_unsafe(subtree);
+  ts_tree__restore_subtree_flags(&mut_subtree, flags);
+  if (state_data) {
+    ts_external_scanner_state_init(
+      &mut_subtree.ptr->external_scanner_state, state_data, state_length
+    );
+  }
+  return ts_subtree_from_mut(mut_subtree);
+}
+
+// Check that a node's children fit its production. Each of the node's structural
+// children, which are the ones that aren't extras, is looked up in the language's
+// alias sequence for that production, which only has room for a fixed number of them.
+static bool ts_tree__frame_fits_production(
+  const DeserializationFrame *self,
+  const TSLanguage *language
+) {
+  if (self->production_id == 0) return true;
+  uint32_t structural_child_count = 0;
+  for (unsigned i = 0; i < self->children.size; i++) {
+    if (!ts_subtree_extra(self->children.contents[i])) structural_child_count++;
+  }
+  return structural_child_count <= language->max_alias_sequence_length;
+}
+
+// Create a node from a frame whose children have all been read. This takes
+// ownership of the frame's children.
+static Subtree ts_tree__build_node(DeserializationFrame *self, const TSLanguage *language) {
+  MutableSubtree node = ts_subtree_new_node(
+    self->symbol, &self->children, self->production_id, language
+  );
+  node.ptr->parse_state = self->parse_state;
+  node.ptr->dynamic_precedence = self->dynamic_precedence;
+  ts_tree__restore_subtree_flags(&node, self->flags);
+  return ts_subtree_from_mut(node);
+}
+
+TSTree *ts_tree_deserialize(const char *data, uint32_t length, const TSLanguage *language) {
+  SerializationReader reader = {data, length, 0, false};
+  if (
+    ts_tree__read_int(&reader, 4) != TREE_SERIALIZATION_VERSION ||
+    ts_tree__read_int(&reader, 4) != language->symbol_count
+  ) return NULL;
+
+  uint32_t included_range_count = ts_tree__read_int(&reader, 4);
+  if (reader.failed || included_range_count > (length - reader.position) / 24) return NULL;
+  TSRange *included_ranges = ts_calloc(included_range_count, sizeof(TSRange));
+  for (unsigned i = 0; i < included_range_count; i++) {
+    TSRange *range = &included_ranges[i];
+    range->start_byte = ts_tree__read_int(&reader, 4);
+    range->end_byte = ts_tree__read_int(&reader, 4);
+    range->start_point.row = ts_tree__read_int(&reader, 4);
+    range->start_point.column = ts_tree__read_int(&reader, 4);
+    range->end_point.row = ts_tree__read_int(&reader, 4);
+    range->end_point.column = ts_tree__read_int(&reader, 4);
+  }
+
+  SubtreePool pool = ts_subtree_pool_new(0);
+  Array(DeserializationFrame) stack = array_new();
+  Subtree root = NULL_SUBTREE;
+  bool done = false;
+  while (!done && !reader.failed) {
+    TSSymbol symbol = ts_tree__read_int(&reader, 2);
+    uint16_t flags = ts_tree__read_int(&reader, 2);
+    TSStateId parse_state = ts_tree__read_int(&reader, 2);
+    if (
+      symbol >= language->symbol_count &&
+      symbol != ts_builtin_sym_error &&
+      symbol != ts_builtin_sym_error_repeat
+    ) break;
+
+    Subtree subtree;
+    if (flags & SerializedSubtreeNonterminal) {
+      uint32_t child_count = ts_tree__read_int(&reader, 4);
+      uint16_t production_id = ts_tree__read_int(&reader, 2);
+      int32_t dynamic_precedence = ts_tree__read_int(&reader, 4);
+      if (
+        reader.failed ||
+        child_count > UINT16_MAX ||
+        production_id >= language->production_id_count
+      ) break;
+      DeserializationFrame frame = {
+        .symbol = symbol,
+        .flags = flags,
+        .parse_state = parse_state,
+        .production_id = production_id,
+        .dynamic_precedence = dynamic_precedence,
+        .child_count = child_count,
+        .children = array_new(),
+      };
+
+      // Parent nodes are created once all of their children have been read.
+      if (child_count > 0) {
+        array_push(&stack, frame);
+        continue;
+      }
+      subtree = ts_tree__build_node(&frame, language);
+    } else {
+      subtree = ts_tree__read_leaf(&reader, &pool, symbol, flags, parse_state, language);
+      if (reader.failed) break;
+    }
+
+    // Add the subtree to its parent, and create every parent node that is now complete.
+    for (;;) {
+      if (stack.size == 0) {
+        root = subtree;
+        done = true;
+        break;
+      }
+      DeserializationFrame *frame = array_back(&stack);
+      array_push(&frame->children, subtree);
+      if (frame->children.size < frame->child_count) break;
+      if (!ts_tree__frame_fits_production(frame, language)) {
+        reader.failed = true;
+        break;
+      }
+
+      DeserializationFrame completed = array_pop(&stack);
+      subtree = ts_tree__build_node(&completed, language);
+    }
+  }
+
+  for (unsigned i = 0; i < stack.size; i++) {
+    DeserializationFrame *frame = &stack.contents[i];
+    for (unsigned j = 0; j < frame->children.size; j++) {
+      ts_subtree_release(&pool, frame->children.contents[j]);
+    }
+    array_delete(&frame->children);
+  }
+  array_delete(&stack);
+
+  TSTree *result = NULL;
+  if (done && reader.position == length) {
+    result = ts_tree_new(root, language, included_ranges, included_range_count);
+  } else if (done) {
+    ts_subtree_release(&pool, root);
+  }
+  ts_subtree_pool_delete(&pool);
+  ts_free(included_ranges);
+  return result;
+}